@@ -0,0 +1,416 @@
+//! Packet sources the monitor's sniffer thread reads from.
+//!
+//! Every source hands out bare IPv4 or IPv6 packets (no link-layer header) so the same
+//! dissector and accounting run whether packets come from a live socket or a recorded
+//! capture.
+
+use crate::pcap::{Frame, PcapReader};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Upper bound on how long a source blocks, so the sniffer can notice cancellation.
+pub const RECV_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub enum Next<'a> {
+    /// A packet, and the index of the interface it was captured on when the source sees
+    /// more than one.
+    Packet(&'a [u8], Option<u32>),
+    /// Nothing arrived within [`RECV_TIMEOUT`].
+    Timeout,
+    /// The source is exhausted (end of a capture file).
+    Finished,
+}
+
+pub trait PacketSource: Send {
+    fn next_packet(&mut self) -> io::Result<Next<'_>>;
+}
+
+/// Live capture of all traffic in `bind_ip`'s address family on the interface that owns it,
+/// or on every interface where the platform allows (see [`crate::interfaces::PER_INTERFACE`]).
+/// IPv4 and IPv6 each need their own source.
+pub fn open_live(bind_ip: IpAddr) -> io::Result<Box<dyn PacketSource>> {
+    #[cfg(windows)]
+    {
+        Ok(Box::new(RawSocketSource::open(bind_ip)?))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let ethertype = match bind_ip {
+            IpAddr::V4(_) => AfPacketSource::ETH_P_IP,
+            IpAddr::V6(_) => AfPacketSource::ETH_P_IPV6,
+        };
+        Ok(Box::new(AfPacketSource::open(ethertype)?))
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        let _ = bind_ip;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "live capture is not supported on this platform",
+        ))
+    }
+}
+
+/// `SIO_RCVALL` raw socket; sees both directions on the bound interface.
+#[cfg(windows)]
+pub struct RawSocketSource {
+    sock: windows::Win32::Networking::WinSock::SOCKET,
+    buffer: Box<[u8; 65536]>,
+}
+
+#[cfg(windows)]
+impl RawSocketSource {
+    pub fn open(bind_ip: IpAddr) -> io::Result<Self> {
+        use windows::Win32::Networking::WinSock::{
+            bind, closesocket, ioctlsocket, setsockopt, socket, WSAGetLastError, WSAStartup,
+            AF_INET, AF_INET6, IN_ADDR, IN_ADDR_0, IPPROTO_IP, SIO_RCVALL, SOCKADDR, SOCKADDR_IN,
+            SOCKADDR_IN6, SOCK_RAW, SOL_SOCKET, SO_RCVTIMEO, WSADATA,
+        };
+
+        const RCVALL_ON: u32 = 1;
+
+        let last_error = || unsafe { io::Error::from_raw_os_error(WSAGetLastError().0) };
+
+        unsafe {
+            let mut wsa_data: WSADATA = std::mem::zeroed();
+            if WSAStartup(0x0202, &mut wsa_data) != 0 {
+                return Err(io::Error::other("winsock init failed"));
+            }
+
+            let family = if bind_ip.is_ipv4() { AF_INET } else { AF_INET6 };
+            // SIO_RCVALL wants IPPROTO_IP for both families; IPv6 packets then arrive with
+            // their IPv6 header like IPv4 ones do.
+            let sock = socket(family.0 as i32, SOCK_RAW, IPPROTO_IP.0).map_err(io::Error::other)?;
+
+            let bound = match bind_ip {
+                IpAddr::V4(ip) => {
+                    let addr = SOCKADDR_IN {
+                        sin_family: AF_INET,
+                        sin_port: 0,
+                        sin_addr: IN_ADDR {
+                            S_un: IN_ADDR_0 {
+                                S_addr: u32::from_ne_bytes(ip.octets()),
+                            },
+                        },
+                        sin_zero: [0; 8],
+                    };
+                    bind(
+                        sock,
+                        &addr as *const _ as *const SOCKADDR,
+                        std::mem::size_of::<SOCKADDR_IN>() as i32,
+                    )
+                }
+                IpAddr::V6(ip) => {
+                    let mut addr: SOCKADDR_IN6 = std::mem::zeroed();
+                    addr.sin6_family = AF_INET6;
+                    addr.sin6_addr.u.Byte = ip.octets();
+                    bind(
+                        sock,
+                        &addr as *const _ as *const SOCKADDR,
+                        std::mem::size_of::<SOCKADDR_IN6>() as i32,
+                    )
+                }
+            };
+            if bound != 0 {
+                let err = last_error();
+                let _ = closesocket(sock);
+                return Err(err);
+            }
+
+            let mut one: u32 = RCVALL_ON;
+            if ioctlsocket(sock, SIO_RCVALL as i32, &mut one) != 0 {
+                let err = last_error();
+                let _ = closesocket(sock);
+                return Err(err);
+            }
+
+            let timeout_ms = RECV_TIMEOUT.as_millis() as u32;
+            if setsockopt(
+                sock,
+                SOL_SOCKET as i32,
+                SO_RCVTIMEO as i32,
+                Some(&timeout_ms.to_ne_bytes()[..]),
+            ) != 0
+            {
+                tracing::warn!(
+                    code = WSAGetLastError().0,
+                    "SO_RCVTIMEO failed; cancel signal may be delayed"
+                );
+            }
+
+            Ok(Self {
+                sock,
+                buffer: Box::new([0u8; 65536]),
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl PacketSource for RawSocketSource {
+    fn next_packet(&mut self) -> io::Result<Next<'_>> {
+        use windows::Win32::Networking::WinSock::{
+            recv, WSAGetLastError, SEND_RECV_FLAGS, WSAEINTR, WSAETIMEDOUT,
+        };
+
+        let bytes_read = unsafe { recv(self.sock, &mut self.buffer[..], SEND_RECV_FLAGS(0)) };
+        if bytes_read > 0 {
+            return Ok(Next::Packet(&self.buffer[..bytes_read as usize], None));
+        }
+        if bytes_read == 0 {
+            return Ok(Next::Timeout);
+        }
+        // Any other failure (the adapter went down, say) ends the source so it is reopened.
+        let code = unsafe { WSAGetLastError() };
+        if code == WSAETIMEDOUT || code == WSAEINTR {
+            return Ok(Next::Timeout);
+        }
+        Err(io::Error::from_raw_os_error(code.0))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for RawSocketSource {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = windows::Win32::Networking::WinSock::closesocket(self.sock);
+        }
+    }
+}
+
+/// `AF_PACKET`/`SOCK_DGRAM` socket for one ethertype (IPv4 or IPv6) on every interface;
+/// the kernel strips the link-layer header and reports the interface. Needs `CAP_NET_RAW`.
+#[cfg(target_os = "linux")]
+pub struct AfPacketSource {
+    socket: socket2::Socket,
+    buffer: Box<[u8; 65536]>,
+}
+
+#[cfg(target_os = "linux")]
+impl AfPacketSource {
+    const AF_PACKET: i32 = 17;
+    const ETH_P_IP: u16 = 0x0800;
+    const ETH_P_IPV6: u16 = 0x86DD;
+
+    pub fn open(ethertype: u16) -> io::Result<Self> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let socket = Socket::new(
+            Domain::from(Self::AF_PACKET),
+            Type::DGRAM,
+            Some(Protocol::from(i32::from(ethertype.to_be()))),
+        )?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+        Ok(Self {
+            socket,
+            buffer: Box::new([0u8; 65536]),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PacketSource for AfPacketSource {
+    fn next_packet(&mut self) -> io::Result<Next<'_>> {
+        // SAFETY: initialized bytes are valid `MaybeUninit`s; recv only writes to them.
+        let buffer = unsafe {
+            &mut *(&mut self.buffer[..] as *mut [u8] as *mut [std::mem::MaybeUninit<u8>])
+        };
+        match self.socket.recv_from(buffer) {
+            Ok((0, _)) => Ok(Next::Timeout),
+            Ok((n, from)) => {
+                // sockaddr_ll: family and protocol (2 bytes each), then the interface index.
+                let interface = (from.len() >= 8).then(|| {
+                    // SAFETY: the address holds at least 8 bytes.
+                    let index = unsafe {
+                        (from.as_ptr() as *const u8)
+                            .add(4)
+                            .cast::<i32>()
+                            .read_unaligned()
+                    };
+                    index as u32
+                });
+                Ok(Next::Packet(&self.buffer[..n], interface))
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                Ok(Next::Timeout)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Replay speeds accepted, as multiples of the recorded pace. Anything outside would either
+/// stall the replay for days or overflow the pacing arithmetic.
+pub const REPLAY_SPEEDS: std::ops::RangeInclusive<f64> = 0.01..=1000.0;
+
+/// Replays a pcap/pcapng file, sleeping between packets so they come out at the recorded
+/// pace divided by `speed`.
+pub struct ReplaySource<R> {
+    reader: PcapReader<R>,
+    speed: f64,
+    pending: Option<Frame>,
+    current: Option<Frame>,
+    /// Capture timestamp of the first packet and the instant it was replayed.
+    origin: Option<(Duration, Instant)>,
+    last_timestamp: Duration,
+}
+
+pub fn open_replay(path: &Path, speed: f64) -> io::Result<ReplaySource<BufReader<File>>> {
+    ReplaySource::new(BufReader::new(File::open(path)?), speed)
+}
+
+impl<R: Read> ReplaySource<R> {
+    pub fn new(inner: R, speed: f64) -> io::Result<Self> {
+        if !REPLAY_SPEEDS.contains(&speed) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "replay speed must be between {} and {}",
+                    REPLAY_SPEEDS.start(),
+                    REPLAY_SPEEDS.end()
+                ),
+            ));
+        }
+        Ok(Self {
+            reader: PcapReader::new(inner)?,
+            speed,
+            pending: None,
+            current: None,
+            origin: None,
+            last_timestamp: Duration::ZERO,
+        })
+    }
+}
+
+impl<R: Read + Send> PacketSource for ReplaySource<R> {
+    fn next_packet(&mut self) -> io::Result<Next<'_>> {
+        let frame = loop {
+            let frame = match self.pending.take() {
+                Some(frame) => frame,
+                None => match self.reader.next_frame()? {
+                    Some(frame) if frame.ip_payload().is_some() => frame,
+                    Some(_) => continue,
+                    None => return Ok(Next::Finished),
+                },
+            };
+
+            // Simple packet blocks carry no timestamp; treat them as simultaneous.
+            let timestamp = frame.timestamp.unwrap_or(self.last_timestamp);
+            let (origin_ts, origin_at) = *self.origin.get_or_insert((timestamp, Instant::now()));
+            let due = origin_at + timestamp.saturating_sub(origin_ts).div_f64(self.speed);
+            let wait = due.saturating_duration_since(Instant::now());
+
+            if wait > RECV_TIMEOUT {
+                std::thread::sleep(RECV_TIMEOUT);
+                self.pending = Some(frame);
+                return Ok(Next::Timeout);
+            }
+            std::thread::sleep(wait);
+            self.last_timestamp = timestamp;
+            break frame;
+        };
+
+        let frame = self.current.insert(frame);
+        Ok(frame
+            .ip_payload()
+            .map_or(Next::Timeout, |packet| Next::Packet(packet, None)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Classic little-endian pcap with LINKTYPE_RAW records at the given microsecond offsets.
+    fn capture(records: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut f = Vec::new();
+        f.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+        f.extend_from_slice(&[0; 12]);
+        f.extend_from_slice(&65535u32.to_le_bytes());
+        f.extend_from_slice(&101u32.to_le_bytes());
+        for (micros, data) in records {
+            f.extend_from_slice(&(micros / 1_000_000).to_le_bytes());
+            f.extend_from_slice(&(micros % 1_000_000).to_le_bytes());
+            f.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            f.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            f.extend_from_slice(data);
+        }
+        f
+    }
+
+    fn drain(source: &mut dyn PacketSource) -> (Vec<Vec<u8>>, usize) {
+        let mut packets = Vec::new();
+        let mut timeouts = 0;
+        loop {
+            match source.next_packet().unwrap() {
+                Next::Packet(p, _) => packets.push(p.to_vec()),
+                Next::Timeout => timeouts += 1,
+                Next::Finished => return (packets, timeouts),
+            }
+        }
+    }
+
+    #[test]
+    fn replay_yields_ip_packets_in_order_and_finishes() {
+        let a = [0x45, 1];
+        let b = [0x60, 2];
+        let not_ip = [0x50, 0];
+        let file = capture(&[(0, &a), (1_000, &not_ip), (2_000, &b)]);
+
+        let mut source = ReplaySource::new(file.as_slice(), 1.0).unwrap();
+        let (packets, _) = drain(&mut source);
+        assert_eq!(packets, vec![a.to_vec(), b.to_vec()]);
+    }
+
+    #[test]
+    fn replay_paces_by_capture_time_and_speed() {
+        let p = [0x45, 0];
+        let file = capture(&[(0, &p), (200_000, &p)]);
+
+        let started = Instant::now();
+        drain(&mut ReplaySource::new(file.as_slice(), 1.0).unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        let started = Instant::now();
+        drain(&mut ReplaySource::new(file.as_slice(), 100.0).unwrap());
+        assert!(started.elapsed() < Duration::from_millis(150));
+    }
+
+    #[test]
+    fn long_gaps_surface_as_timeouts() {
+        let p = [0x45, 0];
+        let file = capture(&[(0, &p), (60_000_000, &p)]);
+
+        // 60s of capture time at 50x is 1.2s: at least two receive timeouts in between.
+        let (packets, timeouts) = drain(&mut ReplaySource::new(file.as_slice(), 50.0).unwrap());
+        assert_eq!(packets.len(), 2);
+        assert!(timeouts >= 2);
+    }
+
+    #[test]
+    fn replay_rejects_bad_speed() {
+        let file = capture(&[]);
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(ReplaySource::new(file.as_slice(), speed).is_err());
+        }
+        // Tiny speeds overflow the pacing delay; huge ones are pointless.
+        for speed in [1e-300, 0.001, 1e6, 1e300] {
+            assert!(ReplaySource::new(file.as_slice(), speed).is_err());
+        }
+        for speed in [*REPLAY_SPEEDS.start(), 1.0, *REPLAY_SPEEDS.end()] {
+            assert!(ReplaySource::new(file.as_slice(), speed).is_ok());
+        }
+    }
+}
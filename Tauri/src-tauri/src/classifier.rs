@@ -0,0 +1,560 @@
+//! Tells game-server flows apart from the rest of what a game process talks to.
+//!
+//! A game session is one long-lived, high-rate UDP flow with varied packet sizes in both
+//! directions. Voice chat is just as steady but with near-constant frame sizes, lobby and
+//! matchmaking traffic is TCP or low-rate UDP, telemetry is mostly upload, and store or
+//! CDN downloads are large packets coming in. Each category gets a score from those
+//! features; the best one wins, and a profile's known ports and networks add evidence.
+
+use crate::dissector::Transport;
+use crate::netaddr::{Cidr, PortRange};
+use crate::quality::ArrivalTiming;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowCategory {
+    Game,
+    Voice,
+    Matchmaking,
+    Telemetry,
+    Other,
+}
+
+impl FlowCategory {
+    /// Same spelling as the serialized form.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FlowCategory::Game => "game",
+            FlowCategory::Voice => "voice",
+            FlowCategory::Matchmaking => "matchmaking",
+            FlowCategory::Telemetry => "telemetry",
+            FlowCategory::Other => "other",
+        }
+    }
+}
+
+/// Counters the sniffer keeps per flow.
+#[derive(Debug, Clone)]
+pub struct FlowStats {
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    pub total_sent: u64,
+    pub total_recv: u64,
+    pub packets_sent: u64,
+    pub packets_recv: u64,
+    /// Sums of payload sizes and their squares, for the size mean and spread.
+    size_sum: f64,
+    size_sq_sum: f64,
+    /// Timing of inbound packets, for passive jitter and loss estimates.
+    pub arrivals: ArrivalTiming,
+    /// Packets by the index of the interface they were captured on; empty for replays.
+    pub interfaces: BTreeMap<u32, u64>,
+}
+
+impl Default for FlowStats {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            first_seen: now,
+            last_seen: now,
+            total_sent: 0,
+            total_recv: 0,
+            packets_sent: 0,
+            packets_recv: 0,
+            size_sum: 0.0,
+            size_sq_sum: 0.0,
+            arrivals: ArrivalTiming::default(),
+            interfaces: BTreeMap::new(),
+        }
+    }
+}
+
+impl FlowStats {
+    pub fn record_sent(&mut self, payload_len: usize) {
+        self.total_sent += payload_len as u64;
+        self.packets_sent += 1;
+        self.record_size(payload_len);
+    }
+
+    pub fn record_recv(&mut self, payload_len: usize) {
+        self.total_recv += payload_len as u64;
+        self.packets_recv += 1;
+        self.record_size(payload_len);
+        self.arrivals.record(self.last_seen);
+    }
+
+    fn record_size(&mut self, payload_len: usize) {
+        let size = payload_len as f64;
+        self.size_sum += size;
+        self.size_sq_sum += size * size;
+        self.last_seen = Instant::now();
+    }
+
+    /// `time_scale` converts our clock to the traffic's clock (replay speed).
+    pub fn features(&self, time_scale: f64) -> FlowFeatures {
+        let duration = self
+            .last_seen
+            .duration_since(self.first_seen)
+            .mul_f64(time_scale);
+        let packets = self.packets_sent + self.packets_recv;
+        let (mean_size, size_stddev) = if packets == 0 {
+            (0.0, 0.0)
+        } else {
+            let n = packets as f64;
+            let mean = self.size_sum / n;
+            (mean, (self.size_sq_sum / n - mean * mean).max(0.0).sqrt())
+        };
+        let (fewer, more) = if self.packets_sent < self.packets_recv {
+            (self.packets_sent, self.packets_recv)
+        } else {
+            (self.packets_recv, self.packets_sent)
+        };
+        let bytes = self.total_sent + self.total_recv;
+
+        FlowFeatures {
+            // Anything younger than a second is rated as if it had lasted one.
+            packets_per_sec: packets as f64 / duration.as_secs_f64().max(1.0),
+            mean_size,
+            size_stddev,
+            symmetry: if more == 0 {
+                0.0
+            } else {
+                fewer as f64 / more as f64
+            },
+            upload_share: if bytes == 0 {
+                0.0
+            } else {
+                self.total_sent as f64 / bytes as f64
+            },
+            duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowFeatures {
+    /// Packets per second in both directions over the flow's lifetime.
+    pub packets_per_sec: f64,
+    /// Mean and standard deviation of payload sizes in bytes.
+    pub mean_size: f64,
+    pub size_stddev: f64,
+    /// Packets in the quieter direction over packets in the busier one; 1.0 is balanced.
+    pub symmetry: f64,
+    /// Share of the flow's bytes sent by us.
+    pub upload_share: f64,
+    pub duration: Duration,
+}
+
+/// Tunables, overridable per game profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Packet rate (both directions) from which UDP looks like live game state; servers
+    /// tick at 20-128 Hz.
+    pub game_min_pps: f64,
+    /// Mean payload above which a flow looks like bulk transfer rather than game state.
+    pub game_max_mean_size: f64,
+    pub game_min_symmetry: f64,
+    /// Voice codecs send a frame every 10-60 ms per direction.
+    pub voice_min_pps: f64,
+    pub voice_max_pps: f64,
+    /// Voice frames hardly vary in size; game snapshots do.
+    pub voice_max_size_stddev: f64,
+    pub voice_max_mean_size: f64,
+    pub telemetry_max_pps: f64,
+    pub telemetry_min_upload_share: f64,
+    /// Flows younger than this are reported with reduced confidence.
+    pub settle_secs: f64,
+    /// Confidence from which a game flow counts as a game server.
+    pub min_confidence: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            game_min_pps: 15.0,
+            game_max_mean_size: 900.0,
+            game_min_symmetry: 0.2,
+            voice_min_pps: 25.0,
+            voice_max_pps: 120.0,
+            voice_max_size_stddev: 24.0,
+            voice_max_mean_size: 320.0,
+            telemetry_max_pps: 3.0,
+            telemetry_min_upload_share: 0.8,
+            settle_secs: 10.0,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+/// What a game profile knows about its servers, plus the thresholds to score with.
+#[derive(Debug, Clone, Default)]
+pub struct Hints {
+    pub server_ports: Vec<PortRange>,
+    pub server_cidrs: Vec<Cidr>,
+    /// Lower-case `*` wildcards matched against the remote's reverse DNS name.
+    pub hostname_patterns: Vec<String>,
+    /// Transports that carry game state; any when empty.
+    pub game_transports: Vec<Transport>,
+    pub thresholds: Thresholds,
+}
+
+impl Hints {
+    pub fn matches_hostname(&self, hostname: &str) -> bool {
+        let hostname = hostname.to_ascii_lowercase();
+        self.hostname_patterns
+            .iter()
+            .any(|p| wildcard_match(p, &hostname))
+    }
+}
+
+/// `*` matches any run of characters, everything else matches itself.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // The last literal is anchored at the end.
+            return rest.len() >= part.len() && rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    // No `*` at all: the whole text had to equal the pattern.
+    rest.is_empty()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Classification {
+    pub category: FlowCategory,
+    /// 0.0-1.0.
+    pub confidence: f32,
+}
+
+impl Classification {
+    pub fn is_game_server(&self, thresholds: &Thresholds) -> bool {
+        self.category == FlowCategory::Game && self.confidence >= thresholds.min_confidence
+    }
+}
+
+/// 0 at or below `lo`, 1 at or above `hi`, linear in between.
+fn ramp(x: f64, lo: f64, hi: f64) -> f64 {
+    if hi <= lo {
+        return if x >= hi { 1.0 } else { 0.0 };
+    }
+    ((x - lo) / (hi - lo)).clamp(0.0, 1.0)
+}
+
+/// `hostname` is the remote's reverse DNS name, when known.
+pub fn classify(
+    transport: Transport,
+    remote: SocketAddr,
+    hostname: Option<&str>,
+    f: &FlowFeatures,
+    hints: &Hints,
+) -> Classification {
+    let t = &hints.thresholds;
+    let known_port = hints.server_ports.iter().any(|r| r.contains(remote.port()));
+    let known_net = hints.server_cidrs.iter().any(|c| c.contains(remote.ip()))
+        || hostname.is_some_and(|h| hints.matches_hostname(h));
+    let udp = transport == Transport::Udp;
+
+    let fast = ramp(f.packets_per_sec, t.game_min_pps * 0.5, t.game_min_pps);
+    let slow = 1.0 - ramp(f.packets_per_sec, t.telemetry_max_pps, t.game_min_pps * 0.5);
+    let bulky = ramp(
+        f.mean_size,
+        t.game_max_mean_size,
+        t.game_max_mean_size * 1.4,
+    );
+    let balanced = ramp(f.symmetry, t.game_min_symmetry * 0.5, t.game_min_symmetry);
+    let varied = ramp(
+        f.size_stddev,
+        t.voice_max_size_stddev * 0.5,
+        t.voice_max_size_stddev * 1.5,
+    );
+
+    // Without the packet rate nothing else makes a flow look like a live game.
+    let mut game = fast * (0.4 + 0.2 * (1.0 - bulky) + 0.2 * balanced + 0.2 * varied);
+    if hints.game_transports.is_empty() {
+        if !udp {
+            // Some games do run over TCP, but most TCP is lobby and store traffic.
+            game *= 0.5;
+        }
+    } else if !hints.game_transports.contains(&transport) {
+        game = f64::MIN;
+    }
+    if known_net {
+        game += 0.3;
+    }
+    if known_port {
+        game += 0.2;
+    }
+
+    let voice_rate = ramp(f.packets_per_sec, t.voice_min_pps * 0.7, t.voice_min_pps)
+        * (1.0 - ramp(f.packets_per_sec, t.voice_max_pps, t.voice_max_pps * 1.3));
+    let steady = 1.0 - varied;
+    let small = 1.0
+        - ramp(
+            f.mean_size,
+            t.voice_max_mean_size,
+            t.voice_max_mean_size * 1.5,
+        );
+    let mut voice = if udp {
+        0.4 * voice_rate + 0.4 * steady + 0.2 * small
+    } else {
+        0.0
+    };
+    if known_net || known_port {
+        voice *= 0.5;
+    }
+
+    let matchmaking = if udp {
+        0.5 * slow * balanced
+    } else {
+        0.5 + 0.2 * (1.0 - fast) + 0.1 * balanced
+    };
+
+    let uploading = ramp(
+        f.upload_share,
+        t.telemetry_min_upload_share - 0.15,
+        t.telemetry_min_upload_share,
+    );
+    let telemetry = 0.7 * uploading * slow + 0.2 * uploading;
+
+    let downloading = ramp(1.0 - f.upload_share, 0.7, 0.9);
+    let other = (0.2 + 0.5 * bulky + 0.3 * bulky * downloading).min(1.0);
+
+    let scores = [
+        (FlowCategory::Game, game),
+        (FlowCategory::Voice, voice),
+        (FlowCategory::Matchmaking, matchmaking),
+        (FlowCategory::Telemetry, telemetry),
+        (FlowCategory::Other, other),
+    ];
+    let (category, score) = scores
+        .into_iter()
+        .fold((FlowCategory::Other, f64::MIN), |best, s| {
+            if s.1 > best.1 {
+                s
+            } else {
+                best
+            }
+        });
+
+    let settled = ramp(f.duration.as_secs_f64(), 0.0, t.settle_secs);
+    Classification {
+        category,
+        confidence: (score.clamp(0.0, 1.0) * (0.5 + 0.5 * settled)) as f32,
+    }
+}
+
+/// Narrows detected flows for session-IP queries and route injection.
+#[derive(Debug, Clone, Default)]
+pub struct FlowFilter {
+    /// Accepted categories; any category when absent.
+    pub categories: Option<Vec<FlowCategory>>,
+    pub min_confidence: Option<f32>,
+}
+
+impl FlowFilter {
+    pub fn is_empty(&self) -> bool {
+        self.categories.is_none() && self.min_confidence.is_none()
+    }
+
+    pub fn matches(&self, c: &Classification) -> bool {
+        self.categories
+            .as_ref()
+            .is_none_or(|cats| cats.contains(&c.category))
+            && self.min_confidence.is_none_or(|min| c.confidence >= min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(pps: f64, mean: f64, stddev: f64, symmetry: f64, upload: f64) -> FlowFeatures {
+        FlowFeatures {
+            packets_per_sec: pps,
+            mean_size: mean,
+            size_stddev: stddev,
+            symmetry,
+            upload_share: upload,
+            duration: Duration::from_secs(60),
+        }
+    }
+
+    fn remote() -> SocketAddr {
+        "203.0.113.7:7086".parse().unwrap()
+    }
+
+    fn udp(f: FlowFeatures) -> Classification {
+        classify(Transport::Udp, remote(), None, &f, &Hints::default())
+    }
+
+    #[test]
+    fn tick_rate_udp_is_a_game_server() {
+        let c = udp(features(90.0, 180.0, 110.0, 0.8, 0.3));
+        assert_eq!(c.category, FlowCategory::Game);
+        assert!(c.is_game_server(&Thresholds::default()), "{c:?}");
+    }
+
+    #[test]
+    fn constant_frame_sizes_are_voice() {
+        let c = udp(features(100.0, 120.0, 6.0, 0.9, 0.5));
+        assert_eq!(c.category, FlowCategory::Voice);
+        assert!(!c.is_game_server(&Thresholds::default()));
+    }
+
+    #[test]
+    fn slow_upload_is_telemetry() {
+        let c = udp(features(0.5, 400.0, 200.0, 0.1, 0.95));
+        assert_eq!(c.category, FlowCategory::Telemetry);
+        let c = classify(
+            Transport::Tcp,
+            remote(),
+            None,
+            &features(1.0, 600.0, 300.0, 0.5, 0.97),
+            &Hints::default(),
+        );
+        assert_eq!(c.category, FlowCategory::Telemetry);
+    }
+
+    #[test]
+    fn lobby_tcp_and_pings_are_matchmaking() {
+        let c = classify(
+            Transport::Tcp,
+            remote(),
+            None,
+            &features(4.0, 300.0, 250.0, 0.9, 0.4),
+            &Hints::default(),
+        );
+        assert_eq!(c.category, FlowCategory::Matchmaking);
+        // Region ping probes: a few balanced small datagrams a second.
+        let c = udp(features(2.0, 40.0, 30.0, 1.0, 0.5));
+        assert_eq!(c.category, FlowCategory::Matchmaking);
+    }
+
+    #[test]
+    fn bulk_download_is_other() {
+        let c = classify(
+            Transport::Tcp,
+            remote(),
+            None,
+            &features(800.0, 1400.0, 100.0, 0.5, 0.02),
+            &Hints::default(),
+        );
+        assert_eq!(c.category, FlowCategory::Other);
+    }
+
+    #[test]
+    fn profile_networks_and_ports_add_evidence() {
+        // Borderline: slowish and lopsided, as a game in a loading screen.
+        let f = features(8.0, 200.0, 60.0, 0.1, 0.2);
+        let plain = udp(f);
+        let hints = Hints {
+            server_ports: vec!["7000-7999".parse().unwrap()],
+            server_cidrs: vec!["203.0.113.0/24".parse().unwrap()],
+            ..Hints::default()
+        };
+        let hinted = classify(Transport::Udp, remote(), None, &f, &hints);
+        assert_eq!(hinted.category, FlowCategory::Game);
+        assert!(hinted.confidence > plain.confidence);
+    }
+
+    #[test]
+    fn young_flows_are_less_certain() {
+        let mut f = features(90.0, 180.0, 110.0, 0.8, 0.3);
+        let settled = udp(f);
+        f.duration = Duration::from_secs(1);
+        let young = udp(f);
+        assert_eq!(young.category, FlowCategory::Game);
+        assert!(young.confidence < settled.confidence);
+    }
+
+    #[test]
+    fn stats_summarize_sizes_and_direction() {
+        let mut stats = FlowStats::default();
+        for _ in 0..3 {
+            stats.record_sent(100);
+        }
+        stats.record_recv(300);
+        let f = stats.features(1.0);
+        assert_eq!(f.mean_size, 150.0);
+        assert!((f.size_stddev - 86.6).abs() < 0.1);
+        assert!((f.symmetry - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(f.upload_share, 0.5);
+        assert_eq!(f.packets_per_sec, 4.0);
+        assert_eq!(FlowStats::default().features(1.0).mean_size, 0.0);
+    }
+
+    #[test]
+    fn filter_matches_category_and_confidence() {
+        let game = Classification {
+            category: FlowCategory::Game,
+            confidence: 0.7,
+        };
+        assert!(FlowFilter::default().matches(&game));
+        let strict = FlowFilter {
+            categories: Some(vec![FlowCategory::Game]),
+            min_confidence: Some(0.8),
+        };
+        assert!(!strict.matches(&game));
+        let voice_only = FlowFilter {
+            categories: Some(vec![FlowCategory::Voice]),
+            min_confidence: None,
+        };
+        assert!(!voice_only.matches(&game));
+    }
+
+    #[test]
+    fn profile_transports_and_hostnames() {
+        let f = features(90.0, 180.0, 110.0, 0.8, 0.3);
+        let tcp_only = Hints {
+            game_transports: vec![Transport::Tcp],
+            ..Hints::default()
+        };
+        let c = classify(Transport::Udp, remote(), None, &f, &tcp_only);
+        assert_ne!(c.category, FlowCategory::Game);
+        let c = classify(Transport::Tcp, remote(), None, &f, &tcp_only);
+        assert_eq!(c.category, FlowCategory::Game);
+
+        let slow = features(8.0, 200.0, 60.0, 0.1, 0.2);
+        let named = Hints {
+            hostname_patterns: vec!["*.gs.example.net".to_string()],
+            ..Hints::default()
+        };
+        let host = Some("Fra-12.GS.example.net");
+        let c = classify(Transport::Udp, remote(), host, &slow, &named);
+        assert_eq!(c.category, FlowCategory::Game);
+        let c = classify(
+            Transport::Udp,
+            remote(),
+            Some("cdn.example.net"),
+            &slow,
+            &named,
+        );
+        assert_ne!(c.category, FlowCategory::Game);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*.pubg.com", "prod-live.pubg.com"));
+        assert!(!wildcard_match("*.pubg.com", "pubg.com.evil.net"));
+        assert!(wildcard_match("gs-*-aws.*", "gs-12-aws.example"));
+        assert!(wildcard_match("exact.host", "exact.host"));
+        assert!(!wildcard_match("exact.host", "exact.hosts"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("a*a", "a"));
+    }
+}
@@ -0,0 +1,697 @@
+//! Which cloud, region and network an address belongs to.
+//!
+//! Routing decisions care about "AWS ap-northeast-1" more than "Tokyo", so addresses are
+//! matched against the IP range files cloud providers publish, stored locally under
+//! `<app data>/cloud/`:
+//!
+//! - AWS `ip-ranges.json`, GCP `cloud.json`, Azure `ServiceTags_Public_*.json` and Oracle
+//!   `public_ip_ranges.json`, recognised by their layout whatever the file is called;
+//! - `{"provider": "...", "prefixes": [{"cidr": "...", "region": "..."}]}` for anyone else;
+//! - `*.tsv` address-to-ASN tables in the iptoasn.com layout
+//!   (`start end asn country description`).
+//!
+//! Addresses outside every range fall back to a built-in table of cloud ASNs.
+
+use crate::geo::GeoInfo;
+use crate::netaddr::Cidr;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Networks of well-known hosting providers, for addresses no range file covers.
+const PROVIDER_ASNS: &[(u32, &str)] = &[
+    (8075, "Azure"),
+    (13335, "Cloudflare"),
+    (14061, "DigitalOcean"),
+    (14618, "AWS"),
+    (15169, "Google Cloud"),
+    (16276, "OVHcloud"),
+    (16509, "AWS"),
+    (20473, "Vultr"),
+    (24940, "Hetzner"),
+    (31898, "Oracle Cloud"),
+    (36351, "IBM Cloud"),
+    (37963, "Alibaba Cloud"),
+    (45102, "Alibaba Cloud"),
+    (49544, "i3D.net"),
+    (63949, "Akamai Linode"),
+    (132203, "Tencent Cloud"),
+    (199524, "Gcore"),
+    (396982, "Google Cloud"),
+];
+
+/// Catch-all service names that say less than any other entry for the same prefix.
+const UMBRELLA_SERVICES: &[&str] = &["AMAZON", "AzureCloud"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CloudLabel {
+    pub provider: Option<String>,
+    /// The provider's own region name, e.g. `ap-northeast-1` or `japaneast`.
+    pub region: Option<String>,
+    pub service: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RangeLabel {
+    provider: String,
+    region: Option<String>,
+    service: Option<String>,
+}
+
+impl RangeLabel {
+    fn new(provider: &str, region: Option<&str>, service: Option<&str>) -> Self {
+        let non_empty = |s: Option<&str>| {
+            s.map(str::trim)
+                .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("global"))
+                .map(str::to_string)
+        };
+        Self {
+            provider: provider.to_string(),
+            region: non_empty(region),
+            service: non_empty(service),
+        }
+    }
+
+    fn specificity(&self) -> u8 {
+        let service = self
+            .service
+            .as_deref()
+            .is_some_and(|s| !UMBRELLA_SERVICES.contains(&s));
+        u8::from(self.region.is_some()) * 2 + u8::from(service)
+    }
+}
+
+/// The family (`true` for IPv6) and the bits of `ip` above `len`.
+fn masked(ip: IpAddr, len: u8) -> (bool, u128) {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+            (false, u128::from(u32::from(ip) & mask))
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+            (true, u128::from(ip) & mask)
+        }
+    }
+}
+
+/// Longest-prefix match over many possibly overlapping prefixes.
+#[derive(Default)]
+struct RangeTable {
+    labels: Vec<RangeLabel>,
+    label_ids: HashMap<RangeLabel, usize>,
+    /// Keyed by prefix length and masked network.
+    prefixes: HashMap<(u8, (bool, u128)), usize>,
+    /// Prefix lengths in use per family (`true` for IPv6), longest first when reversed.
+    lengths: BTreeSet<(bool, u8)>,
+}
+
+impl RangeTable {
+    fn insert(&mut self, cidr: Cidr, label: RangeLabel) {
+        let next_id = self.labels.len();
+        let id = *self.label_ids.entry(label.clone()).or_insert(next_id);
+        if id == next_id {
+            self.labels.push(label);
+        }
+
+        let network = cidr.network();
+        let len = cidr.prefix_len();
+        self.lengths.insert((network.is_ipv6(), len));
+        let slot = self
+            .prefixes
+            .entry((len, masked(network, len)))
+            .or_insert(id);
+        if self.labels[id].specificity() > self.labels[*slot].specificity() {
+            *slot = id;
+        }
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Option<&RangeLabel> {
+        let ip = ip.to_canonical();
+        let v6 = ip.is_ipv6();
+        self.lengths
+            .iter()
+            .rev()
+            .filter(|(family, _)| *family == v6)
+            .find_map(|(_, len)| self.prefixes.get(&(*len, masked(ip, *len))))
+            .map(|id| &self.labels[*id])
+    }
+
+    fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AsnRange {
+    start: (bool, u128),
+    end: (bool, u128),
+    asn: u32,
+    org: usize,
+}
+
+/// Address ranges to ASNs, from iptoasn.com-style tables.
+#[derive(Default)]
+struct AsnTable {
+    ranges: Vec<AsnRange>,
+    orgs: Vec<String>,
+    org_ids: HashMap<String, usize>,
+}
+
+impl AsnTable {
+    fn key(ip: IpAddr) -> (bool, u128) {
+        let ip = ip.to_canonical();
+        masked(ip, if ip.is_ipv4() { 32 } else { 128 })
+    }
+
+    /// Adds the rows of one table; returns how many were usable.
+    fn add_tsv(&mut self, text: &str) -> usize {
+        let before = self.ranges.len();
+        for line in text.lines() {
+            let cols: Vec<&str> = line.split('\t').collect();
+            if cols.len() < 3 {
+                continue;
+            }
+            let (Ok(start), Ok(end), Ok(asn)) = (
+                cols[0].trim().parse::<IpAddr>(),
+                cols[1].trim().parse::<IpAddr>(),
+                cols[2].trim().parse::<u32>(),
+            ) else {
+                continue;
+            };
+            // AS 0 marks unrouted space.
+            if asn == 0 || start.is_ipv6() != end.is_ipv6() {
+                continue;
+            }
+            let org_name = cols.get(4).map(|s| s.trim()).unwrap_or_default();
+            let next_id = self.orgs.len();
+            let org = *self.org_ids.entry(org_name.to_string()).or_insert(next_id);
+            if org == next_id {
+                self.orgs.push(org_name.to_string());
+            }
+            self.ranges.push(AsnRange {
+                start: Self::key(start),
+                end: Self::key(end),
+                asn,
+                org,
+            });
+        }
+        self.ranges.len() - before
+    }
+
+    fn finish(&mut self) {
+        self.ranges.sort_by_key(|r| r.start);
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Option<(u32, Option<&str>)> {
+        let key = Self::key(ip);
+        let i = self
+            .ranges
+            .partition_point(|r| r.start <= key)
+            .checked_sub(1)?;
+        let range = self.ranges[i];
+        (key <= range.end).then(|| {
+            let org = self.orgs[range.org].as_str();
+            (range.asn, (!org.is_empty()).then_some(org))
+        })
+    }
+}
+
+// Published range file layouts; only the fields used here.
+
+#[derive(Deserialize)]
+struct AwsRanges {
+    #[serde(default)]
+    prefixes: Vec<AwsPrefix>,
+    #[serde(default)]
+    ipv6_prefixes: Vec<AwsPrefix>,
+}
+
+#[derive(Deserialize)]
+struct AwsPrefix {
+    #[serde(alias = "ipv6_prefix")]
+    ip_prefix: String,
+    region: Option<String>,
+    service: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GcpRanges {
+    prefixes: Vec<GcpPrefix>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpPrefix {
+    ipv4_prefix: Option<String>,
+    ipv6_prefix: Option<String>,
+    scope: Option<String>,
+    service: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AzureServiceTags {
+    values: Vec<AzureServiceTag>,
+}
+
+#[derive(Deserialize)]
+struct AzureServiceTag {
+    name: String,
+    properties: AzureTagProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureTagProperties {
+    region: Option<String>,
+    #[serde(default)]
+    address_prefixes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OracleRanges {
+    regions: Vec<OracleRegion>,
+}
+
+#[derive(Deserialize)]
+struct OracleRegion {
+    region: String,
+    cidrs: Vec<OracleCidr>,
+}
+
+#[derive(Deserialize)]
+struct OracleCidr {
+    cidr: String,
+}
+
+#[derive(Deserialize)]
+struct GenericRanges {
+    provider: String,
+    prefixes: Vec<GenericPrefix>,
+}
+
+#[derive(Deserialize)]
+struct GenericPrefix {
+    cidr: String,
+    region: Option<String>,
+    service: Option<String>,
+}
+
+/// Reads one range file into `table`; returns the provider and how many prefixes it had.
+fn add_range_file(table: &mut RangeTable, json: &str) -> Result<(String, usize), String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let first_prefix = value.get("prefixes").and_then(|p| p.get(0));
+    let has = |key: &str| first_prefix.is_some_and(|p| p.get(key).is_some());
+
+    let mut entries: Vec<(String, RangeLabel)> = Vec::new();
+    let provider = if value.get("values").is_some() {
+        let tags: AzureServiceTags = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        for tag in tags.values {
+            let service = tag.name.split('.').next().unwrap_or(&tag.name).to_string();
+            let label = RangeLabel::new("Azure", tag.properties.region.as_deref(), Some(&service));
+            for prefix in tag.properties.address_prefixes {
+                entries.push((prefix, label.clone()));
+            }
+        }
+        "Azure"
+    } else if value.get("regions").is_some() {
+        let ranges: OracleRanges = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        for region in ranges.regions {
+            let label = RangeLabel::new("Oracle Cloud", Some(&region.region), None);
+            for cidr in region.cidrs {
+                entries.push((cidr.cidr, label.clone()));
+            }
+        }
+        "Oracle Cloud"
+    } else if value.get("provider").is_some() {
+        let ranges: GenericRanges = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        for prefix in ranges.prefixes {
+            let label = RangeLabel::new(
+                &ranges.provider,
+                prefix.region.as_deref(),
+                prefix.service.as_deref(),
+            );
+            entries.push((prefix.cidr, label));
+        }
+        return finish_range_file(table, entries, ranges.provider);
+    } else if has("ip_prefix") || value.get("ipv6_prefixes").is_some() {
+        let ranges: AwsRanges = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        for prefix in ranges.prefixes.into_iter().chain(ranges.ipv6_prefixes) {
+            let label = RangeLabel::new("AWS", prefix.region.as_deref(), prefix.service.as_deref());
+            entries.push((prefix.ip_prefix, label));
+        }
+        "AWS"
+    } else if has("ipv4Prefix") || has("ipv6Prefix") {
+        let ranges: GcpRanges = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        for prefix in ranges.prefixes {
+            let label = RangeLabel::new(
+                "Google Cloud",
+                prefix.scope.as_deref(),
+                prefix.service.as_deref(),
+            );
+            for cidr in prefix.ipv4_prefix.into_iter().chain(prefix.ipv6_prefix) {
+                entries.push((cidr, label.clone()));
+            }
+        }
+        "Google Cloud"
+    } else {
+        return Err("not a known cloud range layout".to_string());
+    };
+    finish_range_file(table, entries, provider.to_string())
+}
+
+fn finish_range_file(
+    table: &mut RangeTable,
+    entries: Vec<(String, RangeLabel)>,
+    provider: String,
+) -> Result<(String, usize), String> {
+    let mut count = 0;
+    for (cidr, label) in entries {
+        match cidr.parse::<Cidr>() {
+            Ok(cidr) => {
+                table.insert(cidr, label);
+                count += 1;
+            }
+            Err(e) => tracing::debug!(error = %e, "skipping cloud range"),
+        }
+    }
+    Ok((provider, count))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudSource {
+    pub file: String,
+    pub provider: String,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudStatus {
+    pub directory: Option<String>,
+    pub sources: Vec<CloudSource>,
+    pub prefixes: usize,
+    pub asn_ranges: usize,
+}
+
+#[derive(Default)]
+struct Tables {
+    ranges: RangeTable,
+    asns: AsnTable,
+    sources: Vec<CloudSource>,
+}
+
+impl Tables {
+    fn load(dir: &Path) -> Self {
+        let mut tables = Tables::default();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return tables;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let file = path
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let ext = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            let loaded = match ext {
+                "json" => std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| add_range_file(&mut tables.ranges, &json)),
+                "tsv" => std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .map(|text| ("ASN".to_string(), tables.asns.add_tsv(&text))),
+                _ => continue,
+            };
+            match loaded {
+                Ok((provider, entries)) => {
+                    tracing::info!(%file, %provider, entries, "cloud ranges loaded");
+                    tables.sources.push(CloudSource {
+                        file,
+                        provider,
+                        entries,
+                    });
+                }
+                Err(e) => tracing::warn!(%file, error = %e, "skipping cloud range file"),
+            }
+        }
+        tables.asns.finish();
+        tables
+    }
+
+    fn label(&self, ip: IpAddr, geo: Option<&GeoInfo>) -> Option<CloudLabel> {
+        let range = self.ranges.lookup(ip);
+        let (asn, as_org) = match self.asns.lookup(ip) {
+            Some((asn, org)) => (Some(asn), org.map(str::to_string)),
+            None => (geo.and_then(|g| g.asn), geo.and_then(|g| g.as_org.clone())),
+        };
+        let provider = range.map(|r| r.provider.clone()).or_else(|| {
+            let asn = asn?;
+            PROVIDER_ASNS
+                .iter()
+                .find(|(known, _)| *known == asn)
+                .map(|(_, name)| name.to_string())
+        });
+
+        let label = CloudLabel {
+            provider,
+            region: range.and_then(|r| r.region.clone()),
+            service: range.and_then(|r| r.service.clone()),
+            asn,
+            as_org,
+        };
+        (label != CloudLabel::default()).then_some(label)
+    }
+}
+
+/// App-wide labeler; tables are swapped whole on reload so lookups never wait for a load.
+pub struct CloudLabeler {
+    dir: Option<PathBuf>,
+    tables: RwLock<Arc<Tables>>,
+}
+
+impl CloudLabeler {
+    /// Starts empty; call [`CloudLabeler::reload`] to read the range files.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            tables: RwLock::new(Arc::default()),
+        }
+    }
+
+    /// Re-reads the range files. Big files take a moment, so call it off the async runtime.
+    pub fn reload(&self) {
+        let tables = match &self.dir {
+            Some(dir) => Tables::load(dir),
+            None => Tables::default(),
+        };
+        *self.tables.write().unwrap() = Arc::new(tables);
+    }
+
+    /// Provider, region and ASN of `ip`; `geo` supplies the ASN when no table has one.
+    pub fn label(&self, ip: IpAddr, geo: Option<&GeoInfo>) -> Option<CloudLabel> {
+        let tables = self.tables.read().unwrap().clone();
+        tables.label(ip, geo)
+    }
+
+    pub fn status(&self) -> CloudStatus {
+        let tables = self.tables.read().unwrap().clone();
+        CloudStatus {
+            directory: self.dir.as_ref().map(|d| d.display().to_string()),
+            sources: tables.sources.clone(),
+            prefixes: tables.ranges.len(),
+            asn_ranges: tables.asns.ranges.len(),
+        }
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub fn get_cloud_ranges_status(
+    state: tauri::State<'_, Arc<CloudLabeler>>,
+) -> Result<CloudStatus, String> {
+    Ok(state.status())
+}
+
+/// Re-reads the range files in the cloud folder.
+#[tracing::instrument(level = "info", skip(state))]
+#[tauri::command]
+pub async fn reload_cloud_ranges(
+    state: tauri::State<'_, Arc<CloudLabeler>>,
+) -> Result<CloudStatus, String> {
+    let labeler = state.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        labeler.reload();
+        labeler.status()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWS: &str = r#"{"syncToken": "1", "prefixes": [
+        {"ip_prefix": "13.112.0.0/14", "region": "ap-northeast-1", "service": "AMAZON"},
+        {"ip_prefix": "13.112.0.0/14", "region": "ap-northeast-1", "service": "EC2"},
+        {"ip_prefix": "13.0.0.0/8", "region": "GLOBAL", "service": "AMAZON"}
+    ], "ipv6_prefixes": [
+        {"ipv6_prefix": "2406:da14::/32", "region": "ap-northeast-1", "service": "EC2"}
+    ]}"#;
+
+    const GCP: &str = r#"{"syncToken": "1", "prefixes": [
+        {"ipv4Prefix": "34.84.0.0/16", "service": "Google Cloud", "scope": "asia-northeast1"},
+        {"ipv6Prefix": "2600:1900:4050::/44", "service": "Google Cloud", "scope": "asia-northeast1"}
+    ]}"#;
+
+    const AZURE: &str = r#"{"changeNumber": 1, "cloud": "Public", "values": [
+        {"name": "AzureCloud", "id": "AzureCloud",
+         "properties": {"region": "", "addressPrefixes": ["20.0.0.0/8"]}},
+        {"name": "AzureCloud.japaneast", "id": "AzureCloud.japaneast",
+         "properties": {"region": "japaneast", "addressPrefixes": ["20.18.0.0/16"]}}
+    ]}"#;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn tables(files: &[&str]) -> Tables {
+        let mut tables = Tables::default();
+        for file in files {
+            add_range_file(&mut tables.ranges, file).unwrap();
+        }
+        tables
+    }
+
+    #[test]
+    fn reads_published_layouts() {
+        let mut table = RangeTable::default();
+        assert_eq!(
+            add_range_file(&mut table, AWS).unwrap(),
+            ("AWS".to_string(), 4)
+        );
+        assert_eq!(
+            add_range_file(&mut table, GCP).unwrap(),
+            ("Google Cloud".to_string(), 2)
+        );
+        assert_eq!(
+            add_range_file(&mut table, AZURE).unwrap(),
+            ("Azure".to_string(), 2)
+        );
+        let oracle = r#"{"last_updated_timestamp": "x", "regions": [
+            {"region": "ap-tokyo-1", "cidrs": [{"cidr": "140.238.32.0/20", "tags": ["OCI"]}]}
+        ]}"#;
+        assert_eq!(add_range_file(&mut table, oracle).unwrap().1, 1);
+        let generic = r#"{"provider": "i3D.net", "prefixes": [{"cidr": "185.50.104.0/22", "region": "Tokyo"}]}"#;
+        assert_eq!(
+            add_range_file(&mut table, generic).unwrap(),
+            ("i3D.net".to_string(), 1)
+        );
+        assert!(add_range_file(&mut table, r#"{"something": []}"#).is_err());
+    }
+
+    #[test]
+    fn most_specific_range_wins() {
+        let tables = tables(&[AWS, GCP, AZURE]);
+
+        let ec2 = tables.label(ip("13.114.1.2"), None).unwrap();
+        assert_eq!(ec2.provider.as_deref(), Some("AWS"));
+        assert_eq!(ec2.region.as_deref(), Some("ap-northeast-1"));
+        assert_eq!(ec2.service.as_deref(), Some("EC2"));
+
+        // Only the global umbrella covers this one.
+        let global = tables.label(ip("13.200.0.1"), None).unwrap();
+        assert_eq!(global.region, None);
+
+        let azure = tables.label(ip("20.18.7.7"), None).unwrap();
+        assert_eq!(azure.provider.as_deref(), Some("Azure"));
+        assert_eq!(azure.region.as_deref(), Some("japaneast"));
+
+        let gcp = tables.label(ip("2600:1900:4050::1"), None).unwrap();
+        assert_eq!(gcp.region.as_deref(), Some("asia-northeast1"));
+        assert_eq!(
+            tables
+                .label(ip("2406:da14::5"), None)
+                .unwrap()
+                .region
+                .as_deref(),
+            Some("ap-northeast-1")
+        );
+        assert_eq!(tables.label(ip("8.8.8.8"), None), None);
+    }
+
+    #[test]
+    fn asn_tables_and_fallbacks() {
+        let mut tables = tables(&[AWS]);
+        tables.asns.add_tsv(
+            "13.112.0.0\t13.115.255.255\t16509\tUS\tAMAZON-02\n\
+             8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n\
+             10.0.0.0\t10.255.255.255\t0\tNone\tNot routed\n\
+             2001:db8::\t2001:db8::ffff\t64500\tZZ\tDOC\n\
+             garbage\n",
+        );
+        tables.asns.finish();
+
+        let ec2 = tables.label(ip("13.114.1.2"), None).unwrap();
+        assert_eq!(
+            (ec2.asn, ec2.as_org.as_deref()),
+            (Some(16509), Some("AMAZON-02"))
+        );
+
+        // No range file covers Google DNS, but its ASN is a known provider.
+        let google = tables.label(ip("8.8.8.8"), None).unwrap();
+        assert_eq!(google.provider.as_deref(), Some("Google Cloud"));
+        assert_eq!(google.region, None);
+
+        assert_eq!(
+            tables.label(ip("2001:db8::10"), None).unwrap().asn,
+            Some(64500)
+        );
+        assert_eq!(tables.label(ip("10.1.1.1"), None), None);
+
+        // Without a table entry the geo lookup's ASN is used.
+        let geo = GeoInfo {
+            asn: Some(24940),
+            as_org: Some("Hetzner Online GmbH".to_string()),
+            ..GeoInfo::default()
+        };
+        let hetzner = tables.label(ip("198.51.100.1"), Some(&geo)).unwrap();
+        assert_eq!(hetzner.provider.as_deref(), Some("Hetzner"));
+        assert_eq!(hetzner.asn, Some(24940));
+    }
+
+    #[test]
+    fn loads_a_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("ip-ranges.json"), AWS).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        std::fs::write(
+            dir.join("ip2asn-v4.tsv"),
+            "13.112.0.0\t13.115.255.255\t16509\tUS\tAMAZON-02\n",
+        )
+        .unwrap();
+
+        let labeler = CloudLabeler::new(Some(dir.to_path_buf()));
+        assert_eq!(labeler.label(ip("13.114.1.2"), None), None);
+        labeler.reload();
+
+        let status = labeler.status();
+        assert_eq!(status.sources.len(), 2);
+        assert_eq!(status.asn_ranges, 1);
+        let label = labeler.label(ip("13.114.1.2"), None).unwrap();
+        assert_eq!(label.service.as_deref(), Some("EC2"));
+        assert_eq!(label.asn, Some(16509));
+    }
+}
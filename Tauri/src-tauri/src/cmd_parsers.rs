@@ -0,0 +1,293 @@
+//! Parsers for `route print`, `netsh interface ipv4 show interfaces` and `sc query`.
+//!
+//! Headers, labels and words like "On-link" are localized. These parsers therefore only
+//! look at the row shapes and tokens Windows never translates: addresses, numbers and the
+//! `SERVICE_STATUS` constant names.
+//!
+//! The tools write in the OEM code page (CP950 on zh-TW, CP932 on ja-JP), not UTF-8, so
+//! their output goes through [`decode_console_output`] first; interface names in it would
+//! otherwise be mangled.
+
+use std::net::Ipv4Addr;
+
+/// Text of a console tool's stdout or stderr, decoded from the OEM code page.
+pub fn decode_console_output(bytes: &[u8]) -> String {
+    #[cfg(windows)]
+    {
+        use windows::Win32::Globalization::CP_OEMCP;
+        if let Some(text) = decode_code_page(bytes, CP_OEMCP) {
+            return text;
+        }
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(windows)]
+fn decode_code_page(bytes: &[u8], code_page: u32) -> Option<String> {
+    use windows::Win32::Globalization::{MultiByteToWideChar, MULTI_BYTE_TO_WIDE_CHAR_FLAGS};
+
+    if bytes.is_empty() {
+        return Some(String::new());
+    }
+    let flags = MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0);
+    let len = unsafe { MultiByteToWideChar(code_page, flags, bytes, None) };
+    if len <= 0 {
+        return None;
+    }
+    let mut wide = vec![0u16; len as usize];
+    let written = unsafe { MultiByteToWideChar(code_page, flags, bytes, Some(&mut wide)) };
+    (written > 0).then(|| String::from_utf16_lossy(&wide[..written as usize]))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteRow {
+    pub destination: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// `None` for on-link routes (the gateway column holds a localized word).
+    pub gateway: Option<Ipv4Addr>,
+    pub metric: u32,
+}
+
+/// Active IPv4 routes from `route print`. Persistent routes have four columns and are skipped.
+pub fn parse_route_print(text: &str) -> Vec<RouteRow> {
+    text.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            // The interface column is always an address; it tells route rows from everything else.
+            if cols.len() != 5 || cols[3].parse::<Ipv4Addr>().is_err() {
+                return None;
+            }
+            Some(RouteRow {
+                destination: cols[0].parse().ok()?,
+                netmask: cols[1].parse().ok()?,
+                gateway: cols[2].parse().ok(),
+                metric: cols[4].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+pub fn route_exists(rows: &[RouteRow], destination: Ipv4Addr, netmask: Ipv4Addr) -> bool {
+    rows.iter()
+        .any(|r| r.destination == destination && r.netmask == netmask)
+}
+
+/// Gateway of the lowest-metric `0.0.0.0/0` route, ignoring our own half-default routes.
+pub fn default_gateway(rows: &[RouteRow]) -> Option<Ipv4Addr> {
+    rows.iter()
+        .filter(|r| r.destination == Ipv4Addr::UNSPECIFIED && r.netmask == Ipv4Addr::UNSPECIFIED)
+        .filter_map(|r| r.gateway.map(|gw| (r.metric, gw)))
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gw)| gw)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceRow {
+    pub index: u32,
+    pub name: String,
+}
+
+/// Rows of `netsh interface ipv4 show interfaces`: Idx, Met, MTU, State (one localized token),
+/// then the name, which may contain spaces.
+pub fn parse_netsh_interfaces(text: &str) -> Vec<InterfaceRow> {
+    text.lines()
+        .filter_map(|line| {
+            let mut rest = line.trim();
+            let mut head = Vec::with_capacity(4);
+            for _ in 0..4 {
+                let (token, tail) = rest.split_once(char::is_whitespace)?;
+                head.push(token);
+                rest = tail.trim_start();
+            }
+            if rest.is_empty() || head[1].parse::<u32>().is_err() || head[2].parse::<u32>().is_err()
+            {
+                return None;
+            }
+            Some(InterfaceRow {
+                index: head[0].parse().ok()?,
+                name: rest.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Index of the interface named exactly `name` (Windows compares aliases case-insensitively).
+pub fn interface_index(rows: &[InterfaceRow], name: &str) -> Option<u32> {
+    rows.iter()
+        .find(|r| r.name.eq_ignore_ascii_case(name))
+        .map(|r| r.index)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    Stopped,
+    StartPending,
+    StopPending,
+    Running,
+    ContinuePending,
+    PausePending,
+    Paused,
+}
+
+// SERVICE_STATUS.dwCurrentState values and the constant names sc.exe prints next to them.
+const SERVICE_STATES: &[(u32, &str, ServiceState)] = &[
+    (1, "STOPPED", ServiceState::Stopped),
+    (2, "START_PENDING", ServiceState::StartPending),
+    (3, "STOP_PENDING", ServiceState::StopPending),
+    (4, "RUNNING", ServiceState::Running),
+    (5, "CONTINUE_PENDING", ServiceState::ContinuePending),
+    (6, "PAUSE_PENDING", ServiceState::PausePending),
+    (7, "PAUSED", ServiceState::Paused),
+];
+
+/// Finds the `<label> : <code>  <NAME>` line whose code and name agree with the state table.
+pub fn parse_sc_query_state(text: &str) -> Option<ServiceState> {
+    text.lines().find_map(|line| {
+        let (_, value) = line.split_once(':')?;
+        let mut tokens = value.split_whitespace();
+        let code: u32 = tokens.next()?.parse().ok()?;
+        let name = tokens.next()?;
+        SERVICE_STATES
+            .iter()
+            .find(|(c, n, _)| *c == code && *n == name)
+            .map(|(_, _, state)| *state)
+    })
+}
+
+// Win32 error codes sc.exe returns as its exit code.
+pub const ERROR_SERVICE_ALREADY_RUNNING: i32 = 1056;
+pub const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+pub const ERROR_SERVICE_NOT_ACTIVE: i32 = 1062;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE_EN: &str = include_str!("../tests/fixtures/route_print_en_us.txt");
+    const ROUTE_ZH: &str = include_str!("../tests/fixtures/route_print_zh_tw.txt");
+    const ROUTE_JA: &str = include_str!("../tests/fixtures/route_print_ja_jp.txt");
+    const NETSH_EN: &str = include_str!("../tests/fixtures/netsh_interfaces_en_us.txt");
+    const NETSH_ZH: &str = include_str!("../tests/fixtures/netsh_interfaces_zh_tw.txt");
+    const NETSH_JA: &str = include_str!("../tests/fixtures/netsh_interfaces_ja_jp.txt");
+    // As netsh writes it on a zh-TW system: CP950 bytes.
+    const NETSH_ZH_RAW: &[u8] =
+        include_bytes!("../tests/fixtures/netsh_interfaces_zh_tw_cp950.bin");
+    const SC_RUNNING_EN: &str = include_str!("../tests/fixtures/sc_query_running_en_us.txt");
+    const SC_RUNNING_ZH: &str = include_str!("../tests/fixtures/sc_query_running_zh_tw.txt");
+    const SC_PENDING_ZH: &str = include_str!("../tests/fixtures/sc_query_start_pending_zh_tw.txt");
+    const SC_STOPPED_JA: &str = include_str!("../tests/fixtures/sc_query_stopped_ja_jp.txt");
+
+    fn ip(s: &str) -> Ipv4Addr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn route_print_reads_active_rows_in_every_locale() {
+        let en = parse_route_print(ROUTE_EN);
+        assert_eq!(en.len(), 8);
+        assert!(route_exists(&en, ip("0.0.0.0"), ip("128.0.0.0")));
+        assert!(route_exists(&en, ip("128.0.0.0"), ip("128.0.0.0")));
+        assert!(!route_exists(&en, ip("13.0.0.0"), ip("255.0.0.0")));
+
+        let zh = parse_route_print(ROUTE_ZH);
+        assert_eq!(zh.len(), 6);
+        assert!(route_exists(&zh, ip("13.0.0.0"), ip("255.0.0.0")));
+        let on_link = zh
+            .iter()
+            .find(|r| r.destination == ip("127.0.0.0"))
+            .unwrap();
+        assert_eq!(on_link.gateway, None);
+        assert_eq!(on_link.metric, 331);
+
+        let ja = parse_route_print(ROUTE_JA);
+        assert_eq!(ja.len(), 5);
+    }
+
+    #[test]
+    fn default_gateway_prefers_lowest_metric_active_route() {
+        assert_eq!(
+            default_gateway(&parse_route_print(ROUTE_EN)),
+            Some(ip("192.168.1.1"))
+        );
+        // The persistent route (192.168.1.254) must not be picked up.
+        assert_eq!(
+            default_gateway(&parse_route_print(ROUTE_ZH)),
+            Some(ip("192.168.1.1"))
+        );
+        assert_eq!(
+            default_gateway(&parse_route_print(ROUTE_JA)),
+            Some(ip("192.168.0.1"))
+        );
+        assert_eq!(default_gateway(&[]), None);
+    }
+
+    #[test]
+    fn netsh_interfaces_match_exact_name_in_every_locale() {
+        for text in [NETSH_EN, NETSH_ZH, NETSH_JA] {
+            let rows = parse_netsh_interfaces(text);
+            assert_eq!(interface_index(&rows, "PingPalAdapter"), Some(23));
+            assert_eq!(interface_index(&rows, "pingpaladapter"), Some(23));
+        }
+
+        let zh = parse_netsh_interfaces(NETSH_ZH);
+        assert_eq!(zh.len(), 4);
+        assert_eq!(interface_index(&zh, "PingPalAdapter 2"), Some(31));
+        assert_eq!(interface_index(&zh, "Loopback Pseudo-Interface 1"), Some(1));
+        assert_eq!(zh[1].name, "乙太網路");
+        assert_eq!(interface_index(&zh, "Missing"), None);
+    }
+
+    #[test]
+    fn sc_query_state_ignores_localized_labels() {
+        assert_eq!(
+            parse_sc_query_state(SC_RUNNING_EN),
+            Some(ServiceState::Running)
+        );
+        assert_eq!(
+            parse_sc_query_state(SC_RUNNING_ZH),
+            Some(ServiceState::Running)
+        );
+        assert_eq!(
+            parse_sc_query_state(SC_PENDING_ZH),
+            Some(ServiceState::StartPending)
+        );
+        assert_eq!(
+            parse_sc_query_state(SC_STOPPED_JA),
+            Some(ServiceState::Stopped)
+        );
+        assert_eq!(parse_sc_query_state("[SC] OpenService FAILED 1060:"), None);
+    }
+
+    #[test]
+    fn parsers_survive_code_page_mangled_labels() {
+        // Big5 bytes for the zh-TW labels decoded as UTF-8, as `from_utf8_lossy` would see them.
+        let mut raw = b"\r\n\xaa\xac\xba\x41              : 4  RUNNING\r\n".to_vec();
+        raw.extend_from_slice(
+            b" 23           5        1420  \xa4\x77\xb3\x73\xbd\x75  PingPalAdapter\r\n",
+        );
+        let text = String::from_utf8_lossy(&raw);
+
+        assert_eq!(parse_sc_query_state(&text), Some(ServiceState::Running));
+        let rows = parse_netsh_interfaces(&text);
+        assert_eq!(interface_index(&rows, "PingPalAdapter"), Some(23));
+    }
+
+    #[test]
+    fn raw_code_page_output_parses() {
+        let rows = parse_netsh_interfaces(&decode_console_output(NETSH_ZH_RAW));
+        assert_eq!(rows.len(), 4);
+        assert_eq!(interface_index(&rows, "PingPalAdapter"), Some(23));
+        assert_eq!(interface_index(&rows, "PingPalAdapter 2"), Some(31));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn decodes_cp950_to_the_localized_text() {
+        assert_eq!(
+            decode_code_page(NETSH_ZH_RAW, 950).as_deref(),
+            Some(NETSH_ZH)
+        );
+        let rows = parse_netsh_interfaces(NETSH_ZH);
+        assert_eq!(interface_index(&rows, "乙太網路"), Some(12));
+    }
+}
@@ -0,0 +1,533 @@
+//! IPv4 and IPv6 UDP/TCP dissection for the sniffer.
+//!
+//! Everything here works on the `&[u8]` handed back by `recv` and never indexes past it:
+//! header lengths, total/payload length, IPv6 extension header lengths, UDP length and TCP
+//! data offset are all checked against the bytes we actually have before they are trusted.
+//! Checksums are not verified on purpose; with checksum offload enabled Windows captures
+//! outbound packets before the NIC fills them in.
+
+use etherparse::err::{ipv4, ipv6, tcp};
+use etherparse::{IpNumber, Ipv4HeaderSlice, Ipv6HeaderSlice, TcpHeaderSlice, UdpHeaderSlice};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+
+const UDP_HEADER_LEN: usize = 8;
+const IPV6_HEADER_LEN: usize = 40;
+const IPV6_FRAGMENT_HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Transport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Transport::Udp => "UDP",
+            Transport::Tcp => "TCP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub transport: Transport,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    /// Application payload size. For a fragmented UDP datagram this is the size declared
+    /// by the UDP header, so the remaining fragments can be skipped.
+    pub payload_len: usize,
+}
+
+/// Why a captured packet was not turned into a [`Segment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// Fewer bytes than the headers or the IP length fields promise.
+    Truncated,
+    /// Neither IPv4 nor IPv6, IHL below 5, or lengths that contradict each other.
+    Malformed,
+    /// A non-first fragment; its bytes were accounted with the first one.
+    Fragment,
+    /// Neither UDP nor TCP.
+    Unsupported,
+}
+
+pub fn dissect(packet: &[u8]) -> Result<Segment, Skip> {
+    match packet.first().map(|b| b >> 4) {
+        None => Err(Skip::Truncated),
+        Some(4) => dissect_ipv4(packet),
+        Some(6) => dissect_ipv6(packet),
+        Some(_) => Err(Skip::Malformed),
+    }
+}
+
+fn dissect_ipv4(packet: &[u8]) -> Result<Segment, Skip> {
+    let ip = Ipv4HeaderSlice::from_slice(packet).map_err(|e| match e {
+        ipv4::HeaderSliceError::Len(_) => Skip::Truncated,
+        ipv4::HeaderSliceError::Content(_) => Skip::Malformed,
+    })?;
+
+    let header_len = ip.slice().len();
+    let total_len = match usize::from(ip.total_len()) {
+        // Outbound segments handed to the NIC for large send offload are captured before
+        // the NIC fills in the length; the buffer then holds exactly that segment.
+        0 => packet.len(),
+        len => len,
+    };
+    if total_len < header_len {
+        return Err(Skip::Malformed);
+    }
+    // Anything past total_len is link-layer padding.
+    let ip_payload = packet.get(header_len..total_len).ok_or(Skip::Truncated)?;
+
+    let protocol = ip.protocol();
+    if protocol != IpNumber::UDP && protocol != IpNumber::TCP {
+        return Err(Skip::Unsupported);
+    }
+    if ip.fragments_offset().value() != 0 {
+        return Err(Skip::Fragment);
+    }
+
+    transport_segment(
+        protocol,
+        IpAddr::V4(ip.source_addr()),
+        IpAddr::V4(ip.destination_addr()),
+        ip_payload,
+        ip.more_fragments(),
+    )
+}
+
+fn dissect_ipv6(packet: &[u8]) -> Result<Segment, Skip> {
+    let ip = Ipv6HeaderSlice::from_slice(packet).map_err(|e| match e {
+        ipv6::HeaderSliceError::Len(_) => Skip::Truncated,
+        ipv6::HeaderSliceError::Content(_) => Skip::Malformed,
+    })?;
+
+    let end = match usize::from(ip.payload_length()) {
+        // Jumbograms and large-send-offload segments: the buffer is the packet.
+        0 => packet.len(),
+        len => IPV6_HEADER_LEN + len,
+    };
+    let mut payload = packet.get(IPV6_HEADER_LEN..end).ok_or(Skip::Truncated)?;
+
+    // Walk the extension header chain to the transport header. Every header is at least
+    // eight bytes, so the walk always makes progress.
+    let mut next = ip.next_header();
+    let mut more_fragments = false;
+    while next != IpNumber::UDP && next != IpNumber::TCP {
+        let len = match next {
+            IpNumber::IPV6_HEADER_HOP_BY_HOP
+            | IpNumber::IPV6_ROUTE_HEADER
+            | IpNumber::IPV6_DESTINATION_OPTIONS => {
+                (usize::from(*payload.get(1).ok_or(Skip::Truncated)?) + 1) * 8
+            }
+            IpNumber::AUTHENTICATION_HEADER => {
+                (usize::from(*payload.get(1).ok_or(Skip::Truncated)?) + 2) * 4
+            }
+            IpNumber::IPV6_FRAGMENTATION_HEADER => {
+                let header = payload
+                    .get(..IPV6_FRAGMENT_HEADER_LEN)
+                    .ok_or(Skip::Truncated)?;
+                let offset_and_flags = u16::from_be_bytes([header[2], header[3]]);
+                if offset_and_flags >> 3 != 0 {
+                    return Err(Skip::Fragment);
+                }
+                more_fragments = offset_and_flags & 1 != 0;
+                IPV6_FRAGMENT_HEADER_LEN
+            }
+            _ => return Err(Skip::Unsupported),
+        };
+        next = IpNumber(payload[0]);
+        payload = payload.get(len..).ok_or(Skip::Truncated)?;
+    }
+
+    transport_segment(
+        next,
+        IpAddr::V6(ip.source_addr()),
+        IpAddr::V6(ip.destination_addr()),
+        payload,
+        more_fragments,
+    )
+}
+
+/// Parses the UDP or TCP header at the start of `ip_payload`. `more_fragments` is the IP
+/// layer's flag for a first fragment with the rest of the datagram still to come.
+fn transport_segment(
+    protocol: IpNumber,
+    src: IpAddr,
+    dst: IpAddr,
+    ip_payload: &[u8],
+    more_fragments: bool,
+) -> Result<Segment, Skip> {
+    if protocol == IpNumber::TCP {
+        let tcp = TcpHeaderSlice::from_slice(ip_payload).map_err(|e| match e {
+            tcp::HeaderSliceError::Len(_) => Skip::Truncated,
+            tcp::HeaderSliceError::Content(_) => Skip::Malformed,
+        })?;
+        return Ok(Segment {
+            transport: Transport::Tcp,
+            src: SocketAddr::new(src, tcp.source_port()),
+            dst: SocketAddr::new(dst, tcp.destination_port()),
+            payload_len: ip_payload.len() - tcp.slice().len(),
+        });
+    }
+
+    let udp = UdpHeaderSlice::from_slice(ip_payload).map_err(|_| Skip::Truncated)?;
+    let udp_len = usize::from(udp.length());
+    if udp_len < UDP_HEADER_LEN {
+        return Err(Skip::Malformed);
+    }
+    if more_fragments {
+        // First fragment: the UDP length covers data that is still to come, but never less
+        // than what this fragment already carries.
+        if udp_len < ip_payload.len() {
+            return Err(Skip::Malformed);
+        }
+    } else if udp_len > ip_payload.len() {
+        return Err(Skip::Truncated);
+    }
+
+    Ok(Segment {
+        transport: Transport::Udp,
+        src: SocketAddr::new(src, udp.source_port()),
+        dst: SocketAddr::new(dst, udp.destination_port()),
+        payload_len: udp_len - UDP_HEADER_LEN,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::net::Ipv6Addr;
+
+    /// Builds an IPv4 packet with a zero checksum (as offloaded captures often have).
+    fn ipv4(protocol: u8, ihl: u8, frag: u16, payload: &[u8]) -> Vec<u8> {
+        let header_len = usize::from(ihl) * 4;
+        let total_len = (header_len + payload.len()) as u16;
+        let mut p = vec![0u8; header_len.max(20)];
+        p[0] = 0x40 | ihl;
+        p[2..4].copy_from_slice(&total_len.to_be_bytes());
+        p[6..8].copy_from_slice(&frag.to_be_bytes());
+        p[8] = 64;
+        p[9] = protocol;
+        p[12..16].copy_from_slice(&[192, 168, 1, 10]);
+        p[16..20].copy_from_slice(&[203, 0, 113, 7]);
+        p.truncate(header_len.max(20));
+        p.extend_from_slice(payload);
+        p
+    }
+
+    fn udp(src_port: u16, dst_port: u16, udp_len: u16, data: &[u8]) -> Vec<u8> {
+        let mut u = Vec::with_capacity(8 + data.len());
+        u.extend_from_slice(&src_port.to_be_bytes());
+        u.extend_from_slice(&dst_port.to_be_bytes());
+        u.extend_from_slice(&udp_len.to_be_bytes());
+        u.extend_from_slice(&[0, 0]);
+        u.extend_from_slice(data);
+        u
+    }
+
+    const MORE_FRAGMENTS: u16 = 0x2000;
+
+    #[test]
+    fn dissects_plain_udp_and_ignores_trailing_padding() {
+        let mut packet = ipv4(17, 5, 0, &udp(50000, 3074, 8 + 32, &[0xAB; 32]));
+        packet.extend_from_slice(&[0; 6]);
+
+        let d = dissect(&packet).unwrap();
+        assert_eq!(d.src, "192.168.1.10:50000".parse().unwrap());
+        assert_eq!(d.dst, "203.0.113.7:3074".parse().unwrap());
+        assert_eq!(d.payload_len, 32);
+    }
+
+    #[test]
+    fn honours_ip_options() {
+        let packet = ipv4(17, 6, 0, &udp(1, 2, 8 + 4, &[1, 2, 3, 4]));
+        assert_eq!(dissect(&packet).unwrap().payload_len, 4);
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_headers() {
+        let good = ipv4(17, 5, 0, &udp(1, 2, 8 + 10, &[0; 10]));
+        assert_eq!(dissect(&good[..19]), Err(Skip::Truncated));
+        assert_eq!(dissect(&good[..good.len() - 1]), Err(Skip::Truncated));
+
+        let mut bad_ihl = good.clone();
+        bad_ihl[0] = 0x44;
+        assert_eq!(dissect(&bad_ihl), Err(Skip::Malformed));
+
+        let mut ihl_past_end = good.clone();
+        ihl_past_end[0] = 0x4F;
+        assert_eq!(dissect(&ihl_past_end), Err(Skip::Truncated));
+
+        let mut short_total = good.clone();
+        short_total[2..4].copy_from_slice(&12u16.to_be_bytes());
+        assert_eq!(dissect(&short_total), Err(Skip::Malformed));
+
+        let mut version5 = good.clone();
+        version5[0] = 0x55;
+        assert_eq!(dissect(&version5), Err(Skip::Malformed));
+        assert_eq!(dissect(&[]), Err(Skip::Truncated));
+    }
+
+    #[test]
+    fn rejects_inconsistent_udp_length() {
+        let long = ipv4(17, 5, 0, &udp(1, 2, 8 + 20, &[0; 10]));
+        assert_eq!(dissect(&long), Err(Skip::Truncated));
+        let short = ipv4(17, 5, 0, &udp(1, 2, 4, &[0; 10]));
+        assert_eq!(dissect(&short), Err(Skip::Malformed));
+        let no_udp_header = ipv4(17, 5, 0, &[0; 6]);
+        assert_eq!(dissect(&no_udp_header), Err(Skip::Truncated));
+    }
+
+    #[test]
+    fn first_fragment_counts_whole_datagram_and_rest_are_skipped() {
+        let first = ipv4(17, 5, MORE_FRAGMENTS, &udp(1, 2, 8 + 3000, &[0; 1472]));
+        assert_eq!(dissect(&first).unwrap().payload_len, 3000);
+
+        let middle = ipv4(17, 5, MORE_FRAGMENTS | 185, &[0; 1480]);
+        assert_eq!(dissect(&middle), Err(Skip::Fragment));
+        let last = ipv4(17, 5, 370, &[0; 48]);
+        assert_eq!(dissect(&last), Err(Skip::Fragment));
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, data_offset: u8, data: &[u8]) -> Vec<u8> {
+        let mut t = vec![0u8; 20];
+        t[0..2].copy_from_slice(&src_port.to_be_bytes());
+        t[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        t[12] = data_offset << 4;
+        t[13] = 0x18;
+        t.resize(usize::from(data_offset.max(5)) * 4, 0);
+        t.extend_from_slice(data);
+        t
+    }
+
+    #[test]
+    fn dissects_tcp_payload_after_options() {
+        let packet = ipv4(6, 5, 0, &tcp(443, 50123, 8, &[0xCD; 100]));
+        let s = dissect(&packet).unwrap();
+        assert_eq!(s.transport, Transport::Tcp);
+        assert_eq!(s.src, "192.168.1.10:443".parse().unwrap());
+        assert_eq!(s.dst.port(), 50123);
+        assert_eq!(s.payload_len, 100);
+
+        let pure_ack = ipv4(6, 5, 0, &tcp(1, 2, 5, &[]));
+        assert_eq!(dissect(&pure_ack).unwrap().payload_len, 0);
+    }
+
+    #[test]
+    fn rejects_bad_tcp_headers() {
+        let short = ipv4(6, 5, 0, &[0; 12]);
+        assert_eq!(dissect(&short), Err(Skip::Truncated));
+        let mut bad_offset = ipv4(6, 5, 0, &tcp(1, 2, 5, &[0; 8]));
+        bad_offset[20 + 12] = 0x30;
+        assert_eq!(dissect(&bad_offset), Err(Skip::Malformed));
+        let mut offset_past_end = ipv4(6, 5, 0, &tcp(1, 2, 5, &[]));
+        offset_past_end[20 + 12] = 0xF0;
+        assert_eq!(dissect(&offset_past_end), Err(Skip::Truncated));
+    }
+
+    #[test]
+    fn zero_total_length_uses_captured_length() {
+        // Large-send-offload segment as seen on Windows.
+        let mut packet = ipv4(6, 5, 0, &tcp(1, 2, 5, &[0; 3000]));
+        packet[2..4].copy_from_slice(&[0, 0]);
+        assert_eq!(dissect(&packet).unwrap().payload_len, 3000);
+    }
+
+    #[test]
+    fn skips_other_protocols() {
+        let icmp = ipv4(1, 5, 0, &[8, 0, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(dissect(&icmp), Err(Skip::Unsupported));
+        let gre = ipv4(47, 5, 0, &[0; 8]);
+        assert_eq!(dissect(&gre), Err(Skip::Unsupported));
+    }
+
+    /// Builds an IPv6 packet; `extensions` is the raw extension header chain, whose first
+    /// header type is `next_header`.
+    fn ipv6(next_header: u8, extensions: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![0u8; 40];
+        p[0] = 0x60;
+        p[4..6].copy_from_slice(&((extensions.len() + payload.len()) as u16).to_be_bytes());
+        p[6] = next_header;
+        p[7] = 64;
+        p[8..24].copy_from_slice(&"fd00::10".parse::<Ipv6Addr>().unwrap().octets());
+        p[24..40].copy_from_slice(&"2001:db8::7".parse::<Ipv6Addr>().unwrap().octets());
+        p.extend_from_slice(extensions);
+        p.extend_from_slice(payload);
+        p
+    }
+
+    /// Hop-by-hop, routing or destination options header padded to `len_units` + 1 octets.
+    fn options_header(next_header: u8, len_units: u8) -> Vec<u8> {
+        let mut h = vec![0u8; (usize::from(len_units) + 1) * 8];
+        h[0] = next_header;
+        h[1] = len_units;
+        h
+    }
+
+    fn fragment_header(next_header: u8, offset: u16, more: bool) -> Vec<u8> {
+        let mut h = vec![0u8; 8];
+        h[0] = next_header;
+        h[2..4].copy_from_slice(&(offset << 3 | u16::from(more)).to_be_bytes());
+        h
+    }
+
+    #[test]
+    fn dissects_ipv6_udp() {
+        let mut packet = ipv6(17, &[], &udp(50000, 3074, 8 + 32, &[0xAB; 32]));
+        packet.extend_from_slice(&[0; 4]);
+
+        let d = dissect(&packet).unwrap();
+        assert_eq!(d.transport, Transport::Udp);
+        assert_eq!(d.src, "[fd00::10]:50000".parse().unwrap());
+        assert_eq!(d.dst, "[2001:db8::7]:3074".parse().unwrap());
+        assert_eq!(d.payload_len, 32);
+    }
+
+    #[test]
+    fn walks_ipv6_extension_headers() {
+        let mut chain = options_header(60, 0);
+        chain.extend(options_header(6, 1));
+        let packet = ipv6(0, &chain, &tcp(443, 50123, 5, &[0xCD; 100]));
+        let s = dissect(&packet).unwrap();
+        assert_eq!(s.transport, Transport::Tcp);
+        assert_eq!(s.src.port(), 443);
+        assert_eq!(s.payload_len, 100);
+
+        // Authentication header lengths count 4-octet units, minus two.
+        let mut ah = vec![0u8; 16];
+        ah[0] = 17;
+        ah[1] = 2;
+        let packet = ipv6(51, &ah, &udp(1, 2, 8 + 4, &[0; 4]));
+        assert_eq!(dissect(&packet).unwrap().payload_len, 4);
+    }
+
+    #[test]
+    fn ipv6_fragments_count_once() {
+        let first = ipv6(
+            44,
+            &fragment_header(17, 0, true),
+            &udp(1, 2, 8 + 3000, &[0; 1448]),
+        );
+        assert_eq!(dissect(&first).unwrap().payload_len, 3000);
+
+        let rest = ipv6(44, &fragment_header(17, 182, false), &[0; 1552]);
+        assert_eq!(dissect(&rest), Err(Skip::Fragment));
+
+        // An unfragmented datagram in a fragment header ("atomic fragment").
+        let atomic = ipv6(
+            44,
+            &fragment_header(17, 0, false),
+            &udp(1, 2, 8 + 4, &[0; 4]),
+        );
+        assert_eq!(dissect(&atomic).unwrap().payload_len, 4);
+    }
+
+    #[test]
+    fn rejects_bad_ipv6_packets() {
+        let good = ipv6(17, &[], &udp(1, 2, 8 + 10, &[0; 10]));
+        assert_eq!(dissect(&good[..39]), Err(Skip::Truncated));
+        assert_eq!(dissect(&good[..good.len() - 1]), Err(Skip::Truncated));
+
+        let mut past_end = ipv6(0, &options_header(17, 0), &udp(1, 2, 8, &[]));
+        past_end[40 + 1] = 200;
+        assert_eq!(dissect(&past_end), Err(Skip::Truncated));
+
+        let icmpv6 = ipv6(58, &[], &[128, 0, 0, 0]);
+        assert_eq!(dissect(&icmpv6), Err(Skip::Unsupported));
+        let esp = ipv6(50, &[], &[0; 16]);
+        assert_eq!(dissect(&esp), Err(Skip::Unsupported));
+        let no_next = ipv6(59, &[], &[]);
+        assert_eq!(dissect(&no_next), Err(Skip::Unsupported));
+    }
+
+    #[test]
+    fn zero_ipv6_payload_length_uses_captured_length() {
+        let mut packet = ipv6(6, &[], &tcp(1, 2, 5, &[0; 3000]));
+        packet[4..6].copy_from_slice(&[0, 0]);
+        assert_eq!(dissect(&packet).unwrap().payload_len, 3000);
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_arbitrary_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..128)) {
+            let _ = dissect(&bytes);
+        }
+
+        #[test]
+        fn never_panics_on_mangled_udp(
+            data in proptest::collection::vec(any::<u8>(), 0..64),
+            ihl in 0u8..16,
+            total_len in any::<u16>(),
+            frag in any::<u16>(),
+            udp_len in any::<u16>(),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let mut packet = ipv4(17, 5, 0, &udp(1, 2, udp_len, &data));
+            packet[0] = 0x40 | ihl;
+            packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+            packet[6..8].copy_from_slice(&frag.to_be_bytes());
+            let cut = cut.index(packet.len() + 1);
+
+            if let Ok(d) = dissect(&packet[..cut]) {
+                prop_assert!(d.payload_len + UDP_HEADER_LEN <= usize::from(udp_len));
+                prop_assert!(d.payload_len <= u16::MAX as usize);
+            }
+        }
+
+        #[test]
+        fn never_panics_on_mangled_tcp(
+            data in proptest::collection::vec(any::<u8>(), 0..64),
+            data_offset in 0u8..16,
+            total_len in any::<u16>(),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let mut packet = ipv4(6, 5, 0, &tcp(1, 2, 5, &data));
+            packet[20 + 12] = data_offset << 4;
+            packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+            let cut = cut.index(packet.len() + 1);
+
+            if let Ok(s) = dissect(&packet[..cut]) {
+                prop_assert!(s.payload_len + 40 <= cut);
+            }
+        }
+
+        #[test]
+        fn never_panics_on_mangled_ipv6(
+            data in proptest::collection::vec(any::<u8>(), 0..64),
+            chain in proptest::collection::vec(any::<u8>(), 0..48),
+            first_header in prop::sample::select(vec![0u8, 6, 17, 43, 44, 51, 60]),
+            payload_len in any::<u16>(),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let mut packet = ipv6(first_header, &chain, &data);
+            packet[4..6].copy_from_slice(&payload_len.to_be_bytes());
+            let cut = cut.index(packet.len() + 1);
+
+            if let Ok(s) = dissect(&packet[..cut]) {
+                if s.transport == Transport::Tcp {
+                    prop_assert!(s.payload_len + 60 <= cut);
+                }
+            }
+        }
+
+        #[test]
+        fn well_formed_datagrams_round_trip(
+            src_port in any::<u16>(),
+            dst_port in any::<u16>(),
+            data in proptest::collection::vec(any::<u8>(), 0..1400),
+            v6 in any::<bool>(),
+        ) {
+            let datagram = udp(src_port, dst_port, (8 + data.len()) as u16, &data);
+            let packet = if v6 { ipv6(17, &[], &datagram) } else { ipv4(17, 5, 0, &datagram) };
+            let d = dissect(&packet).unwrap();
+            prop_assert_eq!(d.src.port(), src_port);
+            prop_assert_eq!(d.dst.port(), dst_port);
+            prop_assert_eq!(d.payload_len, data.len());
+        }
+    }
+}
@@ -10,6 +10,8 @@ pub enum AppError {
     AlreadyMonitoring,
     #[error("process '{0}' was not found; start the game first")]
     ProcessNotFound(String),
+    #[error("unknown game profile '{0}'")]
+    UnknownProfile(String),
     #[error("no active monitoring session")]
     NoActiveMonitoring,
     #[error("{0}")]
@@ -43,6 +45,12 @@ mod tests {
         assert_eq!(to_cmd_err(err), "process 'TslGame.exe' was not found; start the game first");
     }
 
+    #[test]
+    fn test_to_cmd_err_unknown_profile() {
+        let err = AppError::UnknownProfile("halo".to_string());
+        assert_eq!(to_cmd_err(err), "unknown game profile 'halo'");
+    }
+
     #[test]
     fn test_to_cmd_err_hostname() {
         let io_err = Error::new(ErrorKind::NotFound, "file not found");
@@ -0,0 +1,206 @@
+//! Recent samples per remote endpoint, for charts.
+//!
+//! Each monitor tick adds one sample per live flow to a bounded ring buffer, so a chart
+//! can be redrawn after a page reload and long sessions do not grow without limit. Flows
+//! that went quiet are kept for [`RETENTION`] so their chart can still be opened.
+
+use crate::dissector::Transport;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// One hour at the 2 s monitor tick.
+const MAX_SAMPLES: usize = 1800;
+/// Beyond this many flows, the ones quiet the longest are dropped.
+const MAX_FLOWS: usize = 128;
+const RETENTION: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FlowSample {
+    /// Unix time in milliseconds.
+    pub at_ms: u64,
+    /// Bytes per second.
+    pub send_rate: f64,
+    pub recv_rate: f64,
+    /// Packets per second.
+    pub send_pps: f64,
+    pub recv_pps: f64,
+    pub ping_ms: Option<f32>,
+    pub jitter_ms: Option<f32>,
+}
+
+/// Mean of the samples, stamped with the last one's time; `None` fields stay `None` only
+/// when no sample had a value.
+fn average(samples: &[FlowSample]) -> FlowSample {
+    let n = samples.len() as f64;
+    let mean_of = |value: fn(&FlowSample) -> Option<f32>| {
+        let values: Vec<f32> = samples.iter().filter_map(value).collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+    };
+    FlowSample {
+        at_ms: samples.last().map_or(0, |s| s.at_ms),
+        send_rate: samples.iter().map(|s| s.send_rate).sum::<f64>() / n,
+        recv_rate: samples.iter().map(|s| s.recv_rate).sum::<f64>() / n,
+        send_pps: samples.iter().map(|s| s.send_pps).sum::<f64>() / n,
+        recv_pps: samples.iter().map(|s| s.recv_pps).sum::<f64>() / n,
+        ping_ms: mean_of(|s| s.ping_ms),
+        jitter_ms: mean_of(|s| s.jitter_ms),
+    }
+}
+
+/// At most `max_points` samples, each averaging a run of consecutive ones.
+pub fn downsample(samples: &[FlowSample], max_points: usize) -> Vec<FlowSample> {
+    if max_points == 0 || samples.len() <= max_points {
+        return samples.to_vec();
+    }
+    samples
+        .chunks(samples.len().div_ceil(max_points))
+        .map(average)
+        .collect()
+}
+
+#[derive(Default)]
+pub struct FlowHistory {
+    flows: HashMap<(Transport, SocketAddr), VecDeque<FlowSample>>,
+}
+
+impl FlowHistory {
+    pub fn record(&mut self, transport: Transport, remote: SocketAddr, sample: FlowSample) {
+        let samples = self.flows.entry((transport, remote)).or_default();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// Drops flows quiet for longer than [`RETENTION`], then the quietest beyond
+    /// [`MAX_FLOWS`].
+    pub fn prune(&mut self, now_ms: u64) {
+        let cutoff = now_ms.saturating_sub(RETENTION.as_millis() as u64);
+        self.flows
+            .retain(|_, samples| samples.back().is_some_and(|s| s.at_ms >= cutoff));
+        if self.flows.len() > MAX_FLOWS {
+            let mut last_seen: Vec<u64> = self
+                .flows
+                .values()
+                .filter_map(|samples| samples.back().map(|s| s.at_ms))
+                .collect();
+            last_seen.sort_unstable();
+            let keep_from = last_seen[self.flows.len() - MAX_FLOWS];
+            self.flows
+                .retain(|_, samples| samples.back().is_some_and(|s| s.at_ms >= keep_from));
+        }
+    }
+
+    /// Time of the newest sample for the endpoint, over any transport.
+    pub fn last_sample_at(&self, remote: SocketAddr) -> Option<u64> {
+        [Transport::Udp, Transport::Tcp]
+            .iter()
+            .filter_map(|t| self.flows.get(&(*t, remote))?.back())
+            .map(|s| s.at_ms)
+            .max()
+    }
+
+    /// Samples of the last `window`, downsampled to `max_points`. Without a transport,
+    /// UDP is preferred when the endpoint has both.
+    pub fn query(
+        &self,
+        transport: Option<Transport>,
+        remote: SocketAddr,
+        window: Duration,
+        max_points: usize,
+        now_ms: u64,
+    ) -> Option<Vec<FlowSample>> {
+        let samples = match transport {
+            Some(t) => self.flows.get(&(t, remote))?,
+            None => self
+                .flows
+                .get(&(Transport::Udp, remote))
+                .or_else(|| self.flows.get(&(Transport::Tcp, remote)))?,
+        };
+        let since = now_ms.saturating_sub(window.as_millis() as u64);
+        let start = samples.partition_point(|s| s.at_ms < since);
+        let recent: Vec<FlowSample> = samples.range(start..).copied().collect();
+        Some(downsample(&recent, max_points))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn sample(at_ms: u64, send_rate: f64, ping_ms: Option<f32>) -> FlowSample {
+        FlowSample {
+            at_ms,
+            send_rate,
+            ping_ms,
+            ..FlowSample::default()
+        }
+    }
+
+    #[test]
+    fn downsampling_averages_runs() {
+        let samples: Vec<FlowSample> = (0..10)
+            .map(|i| sample(i * 2000, i as f64, (i % 2 == 0).then_some(20.0)))
+            .collect();
+        assert_eq!(downsample(&samples, 10), samples);
+
+        let down = downsample(&samples, 4);
+        assert_eq!(down.len(), 4);
+        assert_eq!(down[0].at_ms, 4000);
+        assert_eq!(down[0].send_rate, 1.0);
+        assert_eq!(down[0].ping_ms, Some(20.0));
+        // The last run holds sample 9 only, which had no ping.
+        assert_eq!((down[3].send_rate, down[3].ping_ms), (9.0, None));
+    }
+
+    #[test]
+    fn queries_a_window_and_stays_bounded() {
+        let mut history = FlowHistory::default();
+        let server = addr("203.0.113.7:7086");
+        for i in 0..(MAX_SAMPLES as u64 + 10) {
+            history.record(Transport::Udp, server, sample(i * 2000, 1.0, None));
+        }
+        let now = (MAX_SAMPLES as u64 + 9) * 2000;
+
+        let all = history
+            .query(None, server, Duration::from_secs(86_400), 0, now)
+            .unwrap();
+        assert_eq!(all.len(), MAX_SAMPLES);
+        assert_eq!(all[0].at_ms, 20_000);
+        let minute = Duration::from_secs(60);
+        let udp = history.query(Some(Transport::Udp), server, minute, 0, now);
+        assert_eq!(udp.map(|s| s.len()), Some(31));
+        assert_eq!(
+            history.query(Some(Transport::Tcp), server, minute, 0, now),
+            None
+        );
+        assert_eq!(history.last_sample_at(server), Some(now));
+    }
+
+    #[test]
+    fn prunes_quiet_flows() {
+        let mut history = FlowHistory::default();
+        let quiet = addr("198.51.100.2:443");
+        history.record(Transport::Tcp, quiet, sample(0, 1.0, None));
+        for port in 0..(MAX_FLOWS as u16 + 5) {
+            let remote = SocketAddr::new(addr("203.0.113.7:0").ip(), port);
+            history.record(
+                Transport::Udp,
+                remote,
+                sample(1000 + port as u64, 1.0, None),
+            );
+        }
+
+        history.prune(RETENTION.as_millis() as u64 + 500);
+        assert_eq!(history.last_sample_at(quiet), None);
+        assert_eq!(history.flows.len(), MAX_FLOWS);
+        assert_eq!(history.last_sample_at(addr("203.0.113.7:0")), None);
+        assert!(history.last_sample_at(addr("203.0.113.7:132")).is_some());
+    }
+}
@@ -0,0 +1,293 @@
+//! Noticing games start and stop.
+//!
+//! A background task reads the process list every [`SCAN_INTERVAL`] and matches it against
+//! the game profiles, so a game started after the app is picked up like one that was
+//! already running. Changes are emitted as `game-launched` and `game-exited` events. With
+//! auto-monitoring on, a launched game gets a monitoring session of its own, keyed by its
+//! profile id, which the watcher stops again when the game exits.
+
+use crate::network_monitor::{self, MonitorState};
+use crate::processes::{self, ProcInfo};
+use crate::profiles::{self, GameProfile};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::System;
+use tokio::sync::Mutex as AsyncMonitorMutex;
+
+pub const GAME_LAUNCHED_EVENT: &str = "game-launched";
+pub const GAME_EXITED_EVENT: &str = "game-exited";
+const SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunningGame {
+    pub profile_id: String,
+    /// Display name from the profile.
+    pub name: String,
+    /// Executable name of the matched process.
+    pub process: String,
+    pub pids: Vec<u32>,
+}
+
+/// Profiles with a matching process in `procs`, by profile id.
+pub fn running_games(
+    profiles: &[GameProfile],
+    procs: &[ProcInfo],
+) -> BTreeMap<String, RunningGame> {
+    profiles
+        .iter()
+        .filter_map(|profile| {
+            let found = processes::select(&profile.executables, procs)?;
+            let game = RunningGame {
+                profile_id: profile.id.clone(),
+                name: profile.name.clone(),
+                process: found.name,
+                pids: found.matched,
+            };
+            Some((profile.id.clone(), game))
+        })
+        .collect()
+}
+
+/// Games in `after` but not `before`, and games in `before` but not `after`.
+fn changes(
+    before: &BTreeMap<String, RunningGame>,
+    after: &BTreeMap<String, RunningGame>,
+) -> (Vec<RunningGame>, Vec<RunningGame>) {
+    let launched = after
+        .iter()
+        .filter(|(id, _)| !before.contains_key(*id))
+        .map(|(_, game)| game.clone())
+        .collect();
+    let exited = before
+        .iter()
+        .filter(|(id, _)| !after.contains_key(*id))
+        .map(|(_, game)| game.clone())
+        .collect();
+    (launched, exited)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameWatcherStatus {
+    pub auto_monitor: bool,
+    pub running: Vec<RunningGame>,
+}
+
+#[derive(Default)]
+pub struct GameWatcher {
+    auto_monitor: AtomicBool,
+    running: Mutex<BTreeMap<String, RunningGame>>,
+    stopped: AtomicBool,
+    /// Held while the task starts sessions, so `shutdown` can wait out a start in flight.
+    starting: AsyncMonitorMutex<()>,
+}
+
+impl GameWatcher {
+    pub fn status(&self) -> GameWatcherStatus {
+        GameWatcherStatus {
+            auto_monitor: self.auto_monitor.load(Ordering::Relaxed),
+            running: self.running.lock().unwrap().values().cloned().collect(),
+        }
+    }
+
+    /// Ends the background task; it starts no further sessions once this returns.
+    pub async fn shutdown(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        drop(self.starting.lock().await);
+    }
+}
+
+/// Starts the background task; profiles are re-read on every scan so new files apply
+/// without a restart.
+pub fn spawn(app: tauri::AppHandle, watcher: Arc<GameWatcher>) {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn(async move {
+        let mut system = System::new();
+        // Sessions this task started and therefore stops when their game exits.
+        let mut auto_sessions: HashSet<String> = HashSet::new();
+        let mut auto_was_on = false;
+        let mut interval = tokio::time::interval(SCAN_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            if watcher.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let profile_dir = network_monitor::user_profile_dir(&app);
+            let scanned = tokio::task::spawn_blocking(move || {
+                let profiles = profiles::load(profile_dir.as_deref());
+                let running = running_games(&profiles, &processes::scan(&mut system));
+                (system, profiles, running)
+            })
+            .await;
+            let Ok((scanned_system, profiles, running)) = scanned else {
+                tracing::warn!("game scan failed; stopping the game watcher");
+                break;
+            };
+            system = scanned_system;
+            // The app may have started shutting down during the scan.
+            if watcher.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let (launched, exited) = {
+                let mut known = watcher.running.lock().unwrap();
+                let changes = changes(&known, &running);
+                *known = running.clone();
+                changes
+            };
+            for game in &launched {
+                tracing::info!(profile = %game.profile_id, process = %game.process, "game launched");
+                emit(&app, GAME_LAUNCHED_EVENT, game);
+            }
+            for game in &exited {
+                tracing::info!(profile = %game.profile_id, "game exited");
+                emit(&app, GAME_EXITED_EVENT, game);
+            }
+
+            let Some(state) = app
+                .try_state::<Arc<AsyncMonitorMutex<MonitorState>>>()
+                .map(|s| s.inner().clone())
+            else {
+                continue;
+            };
+
+            for game in &exited {
+                if auto_sessions.remove(&game.profile_id) {
+                    network_monitor::stop_session(&state, &game.profile_id).await;
+                }
+            }
+
+            // Turning auto-monitoring on also covers games that are already running.
+            let auto_on = watcher.auto_monitor.load(Ordering::Relaxed);
+            let to_start: Vec<&RunningGame> = match (auto_on, auto_was_on) {
+                (false, _) => Vec::new(),
+                (true, false) => running.values().collect(),
+                (true, true) => launched.iter().collect(),
+            };
+            auto_was_on = auto_on;
+
+            let _starting = watcher.starting.lock().await;
+            for game in to_start {
+                if watcher.stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Some(profile) = profiles.iter().find(|p| p.id == game.profile_id) else {
+                    continue;
+                };
+                let id = game.profile_id.clone();
+                match network_monitor::start_session(
+                    app.clone(),
+                    &state,
+                    id.clone(),
+                    profile.clone(),
+                )
+                .await
+                {
+                    Ok(()) => {
+                        tracing::info!(session = %id, "monitoring started automatically");
+                        auto_sessions.insert(id);
+                    }
+                    Err(e) => tracing::debug!(session = %id, error = %e, "not auto-monitoring"),
+                }
+            }
+        }
+
+        tracing::debug!("game watcher stopped");
+    });
+}
+
+fn emit(app: &tauri::AppHandle, event: &str, game: &RunningGame) {
+    use tauri::Emitter;
+
+    if let Err(e) = app.emit(event, game) {
+        tracing::warn!(event, error = %e, "failed to emit game event");
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(watcher))]
+#[tauri::command]
+pub fn get_game_watcher_status(
+    watcher: tauri::State<'_, Arc<GameWatcher>>,
+) -> Result<GameWatcherStatus, String> {
+    Ok(watcher.status())
+}
+
+/// Starts a monitoring session when a known game launches, and stops it when the game
+/// exits. Enabling it also starts sessions for games already running.
+#[tracing::instrument(level = "info", skip(watcher))]
+#[tauri::command]
+pub fn set_auto_monitor(
+    enabled: bool,
+    watcher: tauri::State<'_, Arc<GameWatcher>>,
+) -> Result<GameWatcherStatus, String> {
+    watcher.auto_monitor.store(enabled, Ordering::Relaxed);
+    Ok(watcher.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(id: &str, executables: &[&str]) -> GameProfile {
+        GameProfile {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            executables: executables.iter().map(|e| e.to_string()).collect(),
+            ..GameProfile::for_executable(id)
+        }
+    }
+
+    fn proc(pid: u32, name: &str) -> ProcInfo {
+        ProcInfo {
+            pid,
+            parent: None,
+            name: name.to_string(),
+            exe: None,
+            arg0: None,
+        }
+    }
+
+    #[test]
+    fn matches_the_catalog_and_reports_launches_and_exits() {
+        let catalog = [
+            profile("pubg", &["TslGame.exe"]),
+            profile("cs2", &["cs2.exe"]),
+            profile("discord", &["Discord.exe"]),
+        ];
+        let at_start = running_games(
+            &catalog,
+            &[proc(10, "Discord.exe"), proc(11, "explorer.exe")],
+        );
+        assert_eq!(at_start.keys().collect::<Vec<_>>(), ["discord"]);
+        assert_eq!(at_start["discord"].name, "DISCORD");
+        assert_eq!(at_start["discord"].pids, [10]);
+
+        let (launched, exited) = changes(&BTreeMap::new(), &at_start);
+        assert_eq!(launched.len(), 1);
+        assert!(exited.is_empty());
+
+        let later = running_games(
+            &catalog,
+            &[proc(10, "Discord.exe"), proc(42, "TSLGAME.EXE")],
+        );
+        let (launched, exited) = changes(&at_start, &later);
+        assert_eq!(launched[0].profile_id, "pubg");
+        assert_eq!(launched[0].process, "TSLGAME.EXE");
+        assert!(exited.is_empty());
+
+        let (launched, exited) = changes(&later, &running_games(&catalog, &[]));
+        assert!(launched.is_empty());
+        assert_eq!(
+            exited
+                .iter()
+                .map(|g| g.profile_id.as_str())
+                .collect::<Vec<_>>(),
+            ["discord", "pubg"]
+        );
+    }
+}
@@ -0,0 +1,914 @@
+//! Where detected servers are.
+//!
+//! Lookups go to MaxMind-format databases (GeoLite2/GeoIP2 City and ASN, or compatible
+//! `.mmdb` files) under `<app data>/geoip/`, so nothing leaves the machine. The ip-api.com
+//! web service is only asked when the user opts in, and only for addresses the local
+//! databases do not cover; it is plain HTTP and rate limited, so it is off by default. The
+//! choice is kept in `<app data>/geo_settings.json`.
+
+use crate::geo_cache::{self, CachedGeo, GeoCache};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub const GEO_SETTINGS_FILE: &str = "geo_settings.json";
+
+const IP_API_URL: &str = "http://ip-api.com/json/";
+const IP_API_BATCH_URL: &str = "http://ip-api.com/batch";
+const IP_API_FIELDS: &str = "status,message,countryCode,country,city,lat,lon,as";
+/// ip-api.com accepts up to 100 addresses per batch request...
+const IP_API_BATCH_SIZE: usize = 100;
+/// ...and 15 batch requests per minute.
+const IP_API_BATCHES_PER_MINUTE: u32 = 15;
+/// A request that takes longer fails, so its addresses can be retried later.
+const IP_API_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a located address is trusted.
+const FOUND_TTL: Duration = Duration::from_secs(30 * 24 * 3600);
+/// How long an address no provider could place is left alone.
+const UNKNOWN_TTL: Duration = Duration::from_secs(24 * 3600);
+/// Shortest wait before retrying after a provider failed or was rate limited.
+const RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Structured location and network owner of an address; any part may be unknown.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2, e.g. `JP`.
+    pub country_code: Option<String>,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+impl GeoInfo {
+    /// "City, Country", or just the country; `None` without a country.
+    pub fn label(&self) -> Option<String> {
+        let country = self.country.as_deref().or(self.country_code.as_deref())?;
+        Some(match self.city.as_deref().filter(|c| !c.is_empty()) {
+            Some(city) => format!("{}, {}", city, country),
+            None => country.to_string(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == GeoInfo::default()
+    }
+
+    /// Fills whatever `self` lacks from `other`.
+    fn merge(&mut self, other: GeoInfo) {
+        self.country_code = self.country_code.take().or(other.country_code);
+        self.country = self.country.take().or(other.country);
+        self.city = self.city.take().or(other.city);
+        if self.latitude.is_none() || self.longitude.is_none() {
+            self.latitude = other.latitude;
+            self.longitude = other.longitude;
+        }
+        self.asn = self.asn.or(other.asn);
+        self.as_org = self.as_org.take().or(other.as_org);
+    }
+}
+
+pub type GeoFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<GeoInfo>, String>> + Send + 'a>>;
+pub type GeoBatchFuture<'a> =
+    Pin<Box<dyn Future<Output = Vec<Result<Option<GeoInfo>, String>>> + Send + 'a>>;
+
+/// A source of [`GeoInfo`]. `Ok(None)` means the source knows nothing about the address;
+/// `Err` means it could not be asked.
+pub trait GeoProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn lookup(&self, ip: IpAddr) -> GeoFuture<'_>;
+
+    /// Most addresses one [`GeoProvider::lookup_batch`] call should carry.
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// One answer per address, in order. Providers with a batch API send a single request.
+    fn lookup_batch<'a>(&'a self, ips: &'a [IpAddr]) -> GeoBatchFuture<'a> {
+        Box::pin(async move {
+            let mut answers = Vec::with_capacity(ips.len());
+            for ip in ips {
+                answers.push(self.lookup(*ip).await);
+            }
+            answers
+        })
+    }
+}
+
+/// Allows `capacity` requests at once, refilled evenly over `period`.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, period: Duration, now: Instant) -> Self {
+        let capacity = f64::from(capacity.max(1));
+        Self {
+            capacity,
+            per_sec: capacity / period.as_secs_f64(),
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Takes a token, or says how long until one is available.
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.per_sec))
+        }
+    }
+}
+
+/// Local MaxMind-format databases: one for locations, one for ASNs, either optional.
+pub struct MmdbProvider {
+    location: Option<(PathBuf, maxminddb::Reader<Vec<u8>>)>,
+    asn: Option<(PathBuf, maxminddb::Reader<Vec<u8>>)>,
+}
+
+impl MmdbProvider {
+    /// Opens every `.mmdb` file in `dir`, telling location and ASN databases apart by
+    /// their declared type. `None` when there is no usable database.
+    pub fn open_dir(dir: &Path) -> Option<Self> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "mmdb"))
+            .collect();
+        paths.sort();
+
+        let mut provider = Self {
+            location: None,
+            asn: None,
+        };
+        for path in paths {
+            let reader = match maxminddb::Reader::open_readfile(&path) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "skipping geoip database");
+                    continue;
+                }
+            };
+            let kind = reader.metadata.database_type.to_ascii_lowercase();
+            tracing::info!(path = %path.display(), kind = %kind, "geoip database loaded");
+            if kind.contains("asn") {
+                provider.asn.get_or_insert((path, reader));
+            } else if kind.contains("city") || kind.contains("country") {
+                provider.location.get_or_insert((path, reader));
+            }
+        }
+        (provider.location.is_some() || provider.asn.is_some()).then_some(provider)
+    }
+
+    fn lookup_sync(&self, ip: IpAddr) -> Result<Option<GeoInfo>, String> {
+        let mut info = GeoInfo::default();
+
+        if let Some((_, reader)) = &self.location {
+            match reader.lookup::<maxminddb::geoip2::City>(ip) {
+                Ok(city) => {
+                    let english = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+                        names.and_then(|n| n.get("en").map(|s| s.to_string()))
+                    };
+                    if let Some(country) = city.country {
+                        info.country_code = country.iso_code.map(str::to_string);
+                        info.country = english(country.names);
+                    }
+                    info.city = city.city.and_then(|c| english(c.names));
+                    if let Some(location) = city.location {
+                        info.latitude = location.latitude;
+                        info.longitude = location.longitude;
+                    }
+                }
+                Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        if let Some((_, reader)) = &self.asn {
+            match reader.lookup::<maxminddb::geoip2::Asn>(ip) {
+                Ok(asn) => {
+                    info.asn = asn.autonomous_system_number;
+                    info.as_org = asn.autonomous_system_organization.map(str::to_string);
+                }
+                Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        Ok((!info.is_empty()).then_some(info))
+    }
+}
+
+impl GeoProvider for MmdbProvider {
+    fn name(&self) -> &'static str {
+        "mmdb"
+    }
+
+    fn lookup(&self, ip: IpAddr) -> GeoFuture<'_> {
+        Box::pin(std::future::ready(self.lookup_sync(ip)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpApiResponse {
+    status: String,
+    message: Option<String>,
+    country_code: Option<String>,
+    country: Option<String>,
+    city: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    /// e.g. "AS16509 Amazon.com, Inc."
+    #[serde(rename = "as")]
+    as_name: Option<String>,
+}
+
+impl IpApiResponse {
+    fn into_geo(self) -> Result<Option<GeoInfo>, String> {
+        if self.status != "success" {
+            // "private range", "reserved range" and "invalid query" are answers, not failures.
+            return match self.message.as_deref() {
+                Some("private range" | "reserved range" | "invalid query") => Ok(None),
+                other => Err(other.unwrap_or("lookup failed").to_string()),
+            };
+        }
+        let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+        let (asn, as_org) = match non_empty(self.as_name) {
+            Some(name) => {
+                let (number, org) = name.split_once(' ').unwrap_or((&name, ""));
+                let asn = number.strip_prefix("AS").and_then(|n| n.parse().ok());
+                (asn, non_empty(Some(org.trim().to_string())))
+            }
+            None => (None, None),
+        };
+        let info = GeoInfo {
+            country_code: non_empty(self.country_code),
+            country: non_empty(self.country),
+            city: non_empty(self.city),
+            latitude: self.lat,
+            longitude: self.lon,
+            asn,
+            as_org,
+        };
+        Ok((!info.is_empty()).then_some(info))
+    }
+}
+
+/// The ip-api.com web service (free tier: plain HTTP, rate limited).
+pub struct IpApiProvider {
+    client: reqwest::Client,
+}
+
+impl Default for IpApiProvider {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(IP_API_TIMEOUT)
+            .build()
+            .unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "geo lookup client without timeout");
+                reqwest::Client::default()
+            });
+        Self { client }
+    }
+}
+
+impl GeoProvider for IpApiProvider {
+    fn name(&self) -> &'static str {
+        "ip-api"
+    }
+
+    fn lookup(&self, ip: IpAddr) -> GeoFuture<'_> {
+        Box::pin(async move {
+            let url = format!("{}{}?fields={}", IP_API_URL, ip, IP_API_FIELDS);
+            let response = self
+                .client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            response
+                .json::<IpApiResponse>()
+                .await
+                .map_err(|e| e.to_string())?
+                .into_geo()
+        })
+    }
+
+    fn batch_size(&self) -> usize {
+        IP_API_BATCH_SIZE
+    }
+
+    fn lookup_batch<'a>(&'a self, ips: &'a [IpAddr]) -> GeoBatchFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}?fields={}", IP_API_BATCH_URL, IP_API_FIELDS);
+            let query: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+            let answers = match self.client.post(&url).json(&query).send().await {
+                Ok(response) => response
+                    .json::<Vec<IpApiResponse>>()
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            match answers {
+                Ok(answers) if answers.len() == ips.len() => {
+                    answers.into_iter().map(IpApiResponse::into_geo).collect()
+                }
+                Ok(answers) => {
+                    let e = format!("{} answers for {} addresses", answers.len(), ips.len());
+                    vec![Err(e); ips.len()]
+                }
+                Err(e) => vec![Err(e); ips.len()],
+            }
+        })
+    }
+}
+
+/// Choices that outlive the app.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GeoSettings {
+    #[serde(default)]
+    online_fallback: bool,
+}
+
+impl GeoSettings {
+    fn load(path: Option<&Path>) -> Self {
+        path.and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|text| match serde_json::from_str(&text) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    tracing::warn!(error = %e, "geo settings are corrupt; using defaults");
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let result = serde_json::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "failed to persist geo settings");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoStatus {
+    pub database_dir: Option<String>,
+    pub location_db: Option<String>,
+    pub asn_db: Option<String>,
+    pub online_fallback: bool,
+    pub cached_entries: usize,
+}
+
+/// What the providers made of one address.
+#[derive(Default)]
+struct Answer {
+    geo: Option<GeoInfo>,
+    /// Set when a provider that was asked failed or could not be asked yet.
+    retry_in: Option<Duration>,
+}
+
+impl Answer {
+    fn add(&mut self, result: Result<Option<GeoInfo>, String>, provider: &str, ip: IpAddr) {
+        match result {
+            Ok(Some(info)) => match self.geo.as_mut() {
+                Some(geo) => geo.merge(info),
+                None => self.geo = Some(info),
+            },
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(provider, %ip, error = %e, "geo lookup failed");
+                self.postpone(RETRY_AFTER);
+            }
+        }
+    }
+
+    fn postpone(&mut self, wait: Duration) {
+        self.retry_in = Some(self.retry_in.unwrap_or_default().max(wait).max(RETRY_AFTER));
+    }
+
+    fn is_located(&self) -> bool {
+        self.geo.as_ref().is_some_and(|g| g.label().is_some())
+    }
+
+    fn into_entry(self, now: u64) -> CachedGeo {
+        let ttl = match &self.retry_in {
+            Some(wait) => *wait,
+            None if self.is_located() => FOUND_TTL,
+            None => UNKNOWN_TTL,
+        };
+        let until = now + ttl.as_secs();
+        match self.geo {
+            Some(geo) => CachedGeo::Found {
+                geo,
+                expires_at: until,
+            },
+            None => CachedGeo::Missing { retry_after: until },
+        }
+    }
+}
+
+/// App-wide geo lookups: local databases first, then ip-api if enabled, with answers
+/// cached on disk and online requests batched and rate limited.
+pub struct GeoResolver {
+    dir: Option<PathBuf>,
+    settings_path: Option<PathBuf>,
+    offline: RwLock<Option<Arc<MmdbProvider>>>,
+    online: Arc<dyn GeoProvider>,
+    online_fallback: AtomicBool,
+    limiter: Mutex<TokenBucket>,
+    cache: Mutex<GeoCache>,
+    /// Addresses being resolved right now.
+    pending: Mutex<HashSet<IpAddr>>,
+    /// Held while the cache is written, so snapshots reach the disk in the order taken.
+    saving: Mutex<()>,
+}
+
+impl GeoResolver {
+    /// A resolver without databases yet; [`GeoResolver::load_databases`] opens them.
+    pub fn new(
+        dir: Option<PathBuf>,
+        cache_path: Option<PathBuf>,
+        settings_path: Option<PathBuf>,
+    ) -> Self {
+        let limiter = TokenBucket::new(
+            IP_API_BATCHES_PER_MINUTE,
+            Duration::from_secs(60),
+            Instant::now(),
+        );
+        Self::with_online(
+            dir,
+            cache_path,
+            settings_path,
+            Arc::new(IpApiProvider::default()),
+            limiter,
+        )
+    }
+
+    fn with_online(
+        dir: Option<PathBuf>,
+        cache_path: Option<PathBuf>,
+        settings_path: Option<PathBuf>,
+        online: Arc<dyn GeoProvider>,
+        limiter: TokenBucket,
+    ) -> Self {
+        let settings = GeoSettings::load(settings_path.as_deref());
+        Self {
+            dir,
+            settings_path,
+            offline: RwLock::new(None),
+            online,
+            online_fallback: AtomicBool::new(settings.online_fallback),
+            limiter: Mutex::new(limiter),
+            cache: Mutex::new(GeoCache::load(cache_path, geo_cache::unix_now())),
+            pending: Mutex::new(HashSet::new()),
+            saving: Mutex::new(()),
+        }
+    }
+
+    fn open_databases(&self) -> bool {
+        let provider = self.dir.as_deref().and_then(MmdbProvider::open_dir);
+        if provider.is_none() {
+            tracing::info!(dir = ?self.dir, "no geoip database found; locations need the online fallback");
+        }
+        let found = provider.is_some();
+        *self.offline.write().unwrap() = provider.map(Arc::new);
+        found
+    }
+
+    /// Opens the databases in the geoip folder. They run to tens of megabytes, so call it
+    /// off the main thread and the async runtime; lookups made meanwhile are retried.
+    pub fn load_databases(&self) {
+        if self.open_databases() {
+            self.cache.lock().unwrap().forget_incomplete();
+        }
+    }
+
+    /// Re-reads the database directory, e.g. after the user dropped in new files, and
+    /// forgets cached answers so they are looked up again. Blocking, like
+    /// [`GeoResolver::load_databases`].
+    pub fn reload(&self) {
+        self.open_databases();
+        self.cache.lock().unwrap().clear();
+        self.save_cache();
+    }
+
+    /// Writes the cache if it changed. Blocking; the cache itself is only held while it is
+    /// copied.
+    fn save_cache(&self) {
+        let _saving = self.saving.lock().unwrap();
+        let snapshot = self
+            .cache
+            .lock()
+            .unwrap()
+            .take_snapshot(geo_cache::unix_now());
+        if let Some(snapshot) = snapshot {
+            snapshot.write();
+        }
+    }
+
+    /// Allows or stops online lookups, remembering the choice for the next start.
+    pub fn set_online_fallback(&self, enabled: bool) {
+        let was = self.online_fallback.swap(enabled, Ordering::SeqCst);
+        if enabled == was {
+            return;
+        }
+        if let Some(path) = &self.settings_path {
+            GeoSettings {
+                online_fallback: enabled,
+            }
+            .save(path);
+        }
+        if enabled {
+            // Give the newly allowed provider a chance at what the databases missed.
+            self.cache.lock().unwrap().forget_incomplete();
+        }
+    }
+
+    /// The cached answer for `ip`, without asking anyone.
+    pub fn cached(&self, ip: IpAddr) -> Option<GeoInfo> {
+        let cache = self.cache.lock().unwrap();
+        cache.found(ip, geo_cache::unix_now()).cloned()
+    }
+
+    /// Starts resolving those of `ips` that are public, not cached and not already being
+    /// resolved; the answers land in the cache.
+    pub fn refresh(self: &Arc<Self>, ips: impl IntoIterator<Item = IpAddr>) {
+        let now = geo_cache::unix_now();
+        let misses: Vec<IpAddr> = {
+            let cache = self.cache.lock().unwrap();
+            let mut pending = self.pending.lock().unwrap();
+            ips.into_iter()
+                .filter(|ip| crate::netaddr::is_public(*ip))
+                .filter(|ip| cache.get(*ip, now).is_none())
+                .filter(|ip| pending.insert(*ip))
+                .collect()
+        };
+        if misses.is_empty() {
+            return;
+        }
+        let resolver = self.clone();
+        tokio::spawn(async move {
+            resolver.resolve(misses).await;
+            let _ = tokio::task::spawn_blocking(move || resolver.save_cache()).await;
+        });
+    }
+
+    async fn resolve(&self, ips: Vec<IpAddr>) {
+        let mut answers: Vec<Answer> = ips.iter().map(|_| Answer::default()).collect();
+
+        let offline = self.offline.read().unwrap().clone();
+        if let Some(offline) = offline {
+            for (ip, answer) in ips.iter().zip(answers.iter_mut()) {
+                answer.add(offline.lookup(*ip).await, offline.name(), *ip);
+            }
+        }
+
+        if self.online_fallback.load(Ordering::SeqCst) {
+            let wanted: Vec<usize> = (0..ips.len())
+                .filter(|i| !answers[*i].is_located())
+                .collect();
+            for chunk in wanted.chunks(self.online.batch_size().max(1)) {
+                let allowed = self.limiter.lock().unwrap().try_take(Instant::now());
+                if let Err(wait) = allowed {
+                    tracing::debug!(addresses = chunk.len(), ?wait, "geo lookups rate limited");
+                    for &i in chunk {
+                        answers[i].postpone(wait);
+                    }
+                    continue;
+                }
+                let batch: Vec<IpAddr> = chunk.iter().map(|&i| ips[i]).collect();
+                let results = self.online.lookup_batch(&batch).await;
+                for (&i, result) in chunk.iter().zip(results) {
+                    answers[i].add(result, self.online.name(), ips[i]);
+                }
+            }
+        }
+
+        let now = geo_cache::unix_now();
+        let mut cache = self.cache.lock().unwrap();
+        for (ip, answer) in ips.iter().zip(answers) {
+            cache.insert(*ip, answer.into_entry(now));
+        }
+        drop(cache);
+
+        let mut pending = self.pending.lock().unwrap();
+        for ip in &ips {
+            pending.remove(ip);
+        }
+    }
+
+    pub fn status(&self) -> GeoStatus {
+        let offline = self.offline.read().unwrap();
+        let file = |db: Option<&(PathBuf, maxminddb::Reader<Vec<u8>>)>| {
+            db.map(|(path, _)| path.display().to_string())
+        };
+        GeoStatus {
+            database_dir: self.dir.as_ref().map(|d| d.display().to_string()),
+            location_db: offline.as_ref().and_then(|p| file(p.location.as_ref())),
+            asn_db: offline.as_ref().and_then(|p| file(p.asn.as_ref())),
+            online_fallback: self.online_fallback.load(Ordering::SeqCst),
+            cached_entries: self.cache.lock().unwrap().entry_count(),
+        }
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub fn get_geo_status(state: tauri::State<'_, Arc<GeoResolver>>) -> Result<GeoStatus, String> {
+    Ok(state.status())
+}
+
+/// Re-reads `.mmdb` files from the geoip folder and clears the lookup cache.
+#[tracing::instrument(level = "info", skip(state))]
+#[tauri::command]
+pub async fn reload_geo_databases(
+    state: tauri::State<'_, Arc<GeoResolver>>,
+) -> Result<GeoStatus, String> {
+    let resolver = state.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        resolver.reload();
+        resolver.status()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Allows asking ip-api.com about addresses the local databases do not cover.
+#[tracing::instrument(level = "info", skip(state))]
+#[tauri::command]
+pub fn set_geo_online_fallback(
+    enabled: bool,
+    state: tauri::State<'_, Arc<GeoResolver>>,
+) -> Result<GeoStatus, String> {
+    state.set_online_fallback(enabled);
+    Ok(state.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every address the same way and records the batches it was asked.
+    struct Fixed {
+        answer: Result<Option<GeoInfo>, String>,
+        batch_size: usize,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl Fixed {
+        fn new(answer: Result<Option<GeoInfo>, String>, batch_size: usize) -> Arc<Self> {
+            Arc::new(Self {
+                answer,
+                batch_size,
+                batches: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn batches(&self) -> Vec<usize> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+
+    impl GeoProvider for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn lookup(&self, _ip: IpAddr) -> GeoFuture<'_> {
+            Box::pin(std::future::ready(self.answer.clone()))
+        }
+
+        fn batch_size(&self) -> usize {
+            self.batch_size
+        }
+
+        fn lookup_batch<'a>(&'a self, ips: &'a [IpAddr]) -> GeoBatchFuture<'a> {
+            self.batches.lock().unwrap().push(ips.len());
+            Box::pin(std::future::ready(vec![self.answer.clone(); ips.len()]))
+        }
+    }
+
+    fn resolver(online: Arc<Fixed>, per_minute: u32) -> GeoResolver {
+        let limiter = TokenBucket::new(per_minute, Duration::from_secs(60), Instant::now());
+        let resolver = GeoResolver::with_online(None, None, None, online, limiter);
+        resolver.set_online_fallback(true);
+        resolver
+    }
+
+    fn cached_entry(resolver: &GeoResolver, ip: IpAddr) -> CachedGeo {
+        let cache = resolver.cache.lock().unwrap();
+        cache.get(ip, geo_cache::unix_now()).cloned().unwrap()
+    }
+
+    fn ips(n: u8) -> Vec<IpAddr> {
+        (1..=n).map(|i| IpAddr::from([203, 0, 113, i])).collect()
+    }
+
+    fn tokyo() -> GeoInfo {
+        GeoInfo {
+            country_code: Some("JP".to_string()),
+            country: Some("Japan".to_string()),
+            city: Some("Tokyo".to_string()),
+            latitude: Some(35.68),
+            longitude: Some(139.69),
+            ..GeoInfo::default()
+        }
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(tokyo().label().as_deref(), Some("Tokyo, Japan"));
+        let country_only = GeoInfo {
+            country_code: Some("DE".to_string()),
+            city: Some(String::new()),
+            ..GeoInfo::default()
+        };
+        assert_eq!(country_only.label().as_deref(), Some("DE"));
+        let asn_only = GeoInfo {
+            asn: Some(16509),
+            ..GeoInfo::default()
+        };
+        assert_eq!(asn_only.label(), None);
+    }
+
+    #[test]
+    fn answers_merge_and_pick_their_lifetime() {
+        let ip = ips(1)[0];
+        let mut answer = Answer::default();
+        answer.add(
+            Ok(Some(GeoInfo {
+                asn: Some(16509),
+                ..GeoInfo::default()
+            })),
+            "mmdb",
+            ip,
+        );
+        assert!(!answer.is_located());
+        answer.add(
+            Ok(Some(GeoInfo {
+                asn: Some(1),
+                ..tokyo()
+            })),
+            "ip-api",
+            ip,
+        );
+        assert!(answer.is_located());
+        match answer.into_entry(1_000) {
+            CachedGeo::Found { geo, expires_at } => {
+                assert_eq!(geo.asn, Some(16509));
+                assert_eq!(geo.label().as_deref(), Some("Tokyo, Japan"));
+                assert_eq!(expires_at, 1_000 + FOUND_TTL.as_secs());
+            }
+            other => panic!("{other:?}"),
+        }
+
+        let mut failed = Answer::default();
+        failed.add(Err("timeout".to_string()), "ip-api", ip);
+        assert_eq!(
+            failed.into_entry(1_000),
+            CachedGeo::Missing {
+                retry_after: 1_000 + RETRY_AFTER.as_secs()
+            }
+        );
+        assert_eq!(
+            Answer::default().into_entry(1_000),
+            CachedGeo::Missing {
+                retry_after: 1_000 + UNKNOWN_TTL.as_secs()
+            }
+        );
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(2, Duration::from_secs(60), t0);
+        assert!(bucket.try_take(t0).is_ok());
+        assert!(bucket.try_take(t0).is_ok());
+        let wait = bucket.try_take(t0).unwrap_err();
+        assert!((wait.as_secs_f64() - 30.0).abs() < 0.01, "{wait:?}");
+        assert!(bucket.try_take(t0 + Duration::from_secs(29)).is_err());
+        assert!(bucket.try_take(t0 + Duration::from_secs(31)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn misses_go_out_in_batches_and_are_cached() {
+        let online = Fixed::new(Ok(Some(tokyo())), 2);
+        let resolver = resolver(online.clone(), 15);
+        resolver.resolve(ips(3)).await;
+
+        assert_eq!(online.batches(), [2, 1]);
+        for ip in ips(3) {
+            assert_eq!(resolver.cached(ip), Some(tokyo()));
+        }
+        assert!(resolver.pending.lock().unwrap().is_empty());
+        assert_eq!(resolver.status().cached_entries, 3);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_lookups_are_retried_later() {
+        let online = Fixed::new(Ok(Some(tokyo())), 1);
+        let resolver = resolver(online.clone(), 1);
+        let now = geo_cache::unix_now();
+        resolver.resolve(ips(2)).await;
+
+        assert_eq!(online.batches(), [1]);
+        assert_eq!(resolver.cached(ips(2)[0]), Some(tokyo()));
+        match cached_entry(&resolver, ips(2)[1]) {
+            CachedGeo::Missing { retry_after } => {
+                // The bucket refills one token a minute, which beats the default wait.
+                assert!(retry_after >= now + RETRY_AFTER.as_secs(), "{retry_after}");
+                assert!(retry_after < now + UNKNOWN_TTL.as_secs(), "{retry_after}");
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn enabling_the_fallback_retries_what_was_unknown() {
+        let online = Fixed::new(Ok(Some(tokyo())), 100);
+        let resolver = resolver(online.clone(), 15);
+        resolver.set_online_fallback(false);
+        resolver.resolve(ips(1)).await;
+        assert!(online.batches().is_empty());
+        assert!(matches!(
+            cached_entry(&resolver, ips(1)[0]),
+            CachedGeo::Missing { .. }
+        ));
+
+        resolver.set_online_fallback(true);
+        assert_eq!(resolver.status().cached_entries, 0);
+        resolver.resolve(ips(1)).await;
+        assert_eq!(resolver.cached(ips(1)[0]), Some(tokyo()));
+    }
+
+    #[test]
+    fn parses_ip_api_answers() {
+        let ok: IpApiResponse = serde_json::from_str(
+            r#"{"status":"success","countryCode":"JP","country":"Japan","city":"Tokyo",
+                "lat":35.68,"lon":139.69,"as":"AS16509 Amazon.com, Inc."}"#,
+        )
+        .unwrap();
+        let info = ok.into_geo().unwrap().unwrap();
+        assert_eq!(info.asn, Some(16509));
+        assert_eq!(info.as_org.as_deref(), Some("Amazon.com, Inc."));
+        assert_eq!(info.label().as_deref(), Some("Tokyo, Japan"));
+
+        let private: IpApiResponse =
+            serde_json::from_str(r#"{"status":"fail","message":"private range"}"#).unwrap();
+        assert_eq!(private.into_geo(), Ok(None));
+        let limited: IpApiResponse =
+            serde_json::from_str(r#"{"status":"fail","message":"quota exceeded"}"#).unwrap();
+        assert!(limited.into_geo().is_err());
+    }
+
+    #[test]
+    fn resolver_without_databases_reports_so() {
+        let resolver = GeoResolver::new(None, None, None);
+        let status = resolver.status();
+        assert!(status.location_db.is_none() && status.asn_db.is_none());
+        assert!(!status.online_fallback);
+    }
+
+    #[test]
+    fn the_online_opt_in_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(GEO_SETTINGS_FILE);
+        let resolver = GeoResolver::new(None, None, Some(path.clone()));
+        assert!(!resolver.status().online_fallback);
+        resolver.set_online_fallback(true);
+
+        let restarted = GeoResolver::new(None, None, Some(path.clone()));
+        assert!(restarted.status().online_fallback);
+        restarted.set_online_fallback(false);
+        assert!(
+            !GeoResolver::new(None, None, Some(path.clone()))
+                .status()
+                .online_fallback
+        );
+    }
+}
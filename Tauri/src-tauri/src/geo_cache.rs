@@ -0,0 +1,302 @@
+//! Geo lookups remembered across sessions.
+//!
+//! Game servers are reused for months, so answers are kept on disk and only asked again
+//! when they expire. Addresses nobody could place are remembered too, with a time after
+//! which they may be retried, so a failing or rate-limited provider is not hammered.
+
+use crate::geo::GeoInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const GEO_CACHE_FILE: &str = "geo_cache.json";
+const FORMAT_VERSION: u32 = 1;
+/// Beyond this many entries the ones expiring soonest are dropped on save.
+const MAX_ENTRIES: usize = 20_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CachedGeo {
+    Found {
+        geo: GeoInfo,
+        expires_at: u64,
+    },
+    /// Nothing known; do not ask again before `retry_after`.
+    Missing {
+        retry_after: u64,
+    },
+}
+
+impl CachedGeo {
+    fn valid_until(&self) -> u64 {
+        match self {
+            CachedGeo::Found { expires_at, .. } => *expires_at,
+            CachedGeo::Missing { retry_after } => *retry_after,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<IpAddr, CachedGeo>,
+}
+
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    entries: &'a HashMap<IpAddr, CachedGeo>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub struct GeoCache {
+    path: Option<PathBuf>,
+    entries: HashMap<IpAddr, CachedGeo>,
+    dirty: bool,
+}
+
+impl GeoCache {
+    /// Reads the cache file, starting empty when it is missing, corrupt or of another
+    /// version. Expired entries are dropped.
+    pub fn load(path: Option<PathBuf>, now: u64) -> Self {
+        let mut entries = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|text| match serde_json::from_str::<CacheFile>(&text) {
+                Ok(file) if file.version == FORMAT_VERSION => Some(file.entries),
+                Ok(file) => {
+                    tracing::info!(
+                        version = file.version,
+                        "geo cache has another version; starting fresh"
+                    );
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "geo cache is corrupt; starting fresh");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        entries.retain(|_, entry| entry.valid_until() > now);
+
+        Self {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// The unexpired entry for `ip`, if any.
+    pub fn get(&self, ip: IpAddr, now: u64) -> Option<&CachedGeo> {
+        self.entries.get(&ip).filter(|e| e.valid_until() > now)
+    }
+
+    pub fn found(&self, ip: IpAddr, now: u64) -> Option<&GeoInfo> {
+        match self.get(ip, now)? {
+            CachedGeo::Found { geo, .. } => Some(geo),
+            CachedGeo::Missing { .. } => None,
+        }
+    }
+
+    pub fn insert(&mut self, ip: IpAddr, entry: CachedGeo) {
+        self.entries.insert(ip, entry);
+        self.dirty = true;
+    }
+
+    /// Drops entries without a location, so a newly available provider gets to answer.
+    pub fn forget_incomplete(&mut self) {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| match entry {
+            CachedGeo::Found { geo, .. } => geo.label().is_some(),
+            CachedGeo::Missing { .. } => false,
+        });
+        self.dirty |= self.entries.len() != before;
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Writes the cache if anything changed since the last save.
+    pub fn save(&mut self, now: u64) {
+        if let Some(snapshot) = self.take_snapshot(now) {
+            snapshot.write();
+        }
+    }
+
+    /// Trims the cache and copies it for writing if anything changed since the last save,
+    /// so the slow part, [`CacheSnapshot::write`], can run without holding the cache.
+    pub fn take_snapshot(&mut self, now: u64) -> Option<CacheSnapshot> {
+        if !self.dirty {
+            return None;
+        }
+        self.entries.retain(|_, entry| entry.valid_until() > now);
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        if excess > 0 {
+            let mut by_expiry: Vec<(u64, IpAddr)> = self
+                .entries
+                .iter()
+                .map(|(ip, entry)| (entry.valid_until(), *ip))
+                .collect();
+            by_expiry.sort_unstable();
+            for (_, ip) in &by_expiry[..excess] {
+                self.entries.remove(ip);
+            }
+        }
+        self.dirty = false;
+
+        Some(CacheSnapshot {
+            path: self.path.clone()?,
+            entries: self.entries.clone(),
+        })
+    }
+}
+
+/// The cache's entries as of a [`GeoCache::take_snapshot`], ready to be written.
+pub struct CacheSnapshot {
+    path: PathBuf,
+    entries: HashMap<IpAddr, CachedGeo>,
+}
+
+impl CacheSnapshot {
+    pub fn write(self) {
+        let path = &self.path;
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let file = CacheFileRef {
+            version: FORMAT_VERSION,
+            entries: &self.entries,
+        };
+        let json = match serde_json::to_string(&file) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to serialize geo cache");
+                return;
+            }
+        };
+        let tmp = path.with_extension("json.tmp");
+        if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, path)) {
+            tracing::warn!(error = %e, "failed to persist geo cache");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn tokyo() -> GeoInfo {
+        GeoInfo {
+            country: Some("Japan".to_string()),
+            city: Some("Tokyo".to_string()),
+            ..GeoInfo::default()
+        }
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut cache = GeoCache::load(None, 100);
+        cache.insert(
+            ip("203.0.113.7"),
+            CachedGeo::Found {
+                geo: tokyo(),
+                expires_at: 200,
+            },
+        );
+        cache.insert(ip("203.0.113.8"), CachedGeo::Missing { retry_after: 150 });
+
+        assert_eq!(cache.found(ip("203.0.113.7"), 199), Some(&tokyo()));
+        assert_eq!(cache.found(ip("203.0.113.7"), 200), None);
+        assert!(cache.get(ip("203.0.113.8"), 149).is_some());
+        assert_eq!(cache.found(ip("203.0.113.8"), 149), None);
+        assert!(cache.get(ip("203.0.113.8"), 150).is_none());
+    }
+
+    #[test]
+    fn survives_a_restart_without_expired_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(GEO_CACHE_FILE);
+        let mut cache = GeoCache::load(Some(path.clone()), 100);
+        cache.insert(
+            ip("203.0.113.7"),
+            CachedGeo::Found {
+                geo: tokyo(),
+                expires_at: 1_000,
+            },
+        );
+        cache.insert(ip("2001:db8::1"), CachedGeo::Missing { retry_after: 300 });
+        cache.save(100);
+
+        let reloaded = GeoCache::load(Some(path.clone()), 200);
+        assert_eq!(reloaded.entry_count(), 2);
+        assert_eq!(reloaded.found(ip("203.0.113.7"), 200), Some(&tokyo()));
+        let later = GeoCache::load(Some(path.clone()), 500);
+        assert_eq!(later.entry_count(), 1);
+
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(GeoCache::load(Some(path.clone()), 100).entry_count(), 0);
+    }
+
+    #[test]
+    fn trims_exactly_the_excess_soonest_expiring_first() {
+        let mut cache = GeoCache::load(None, 0);
+        // One batch of lookups shares an expiry; crossing the cap must not drop it all.
+        for i in 0..MAX_ENTRIES as u32 + 2 {
+            let expires_at = if i < 10 { 50 } else { 100 };
+            let geo = CachedGeo::Found {
+                geo: tokyo(),
+                expires_at,
+            };
+            cache.insert(IpAddr::from(std::net::Ipv4Addr::from(i)), geo);
+        }
+        cache.save(0);
+        assert_eq!(cache.entry_count(), MAX_ENTRIES);
+        assert!(cache.get(ip("0.0.0.1"), 0).is_none());
+        assert!(cache.get(ip("0.0.0.2"), 0).is_some());
+        assert!(cache.get(ip("0.0.0.10"), 0).is_some());
+    }
+
+    #[test]
+    fn forgets_incomplete_entries() {
+        let mut cache = GeoCache::load(None, 0);
+        cache.insert(
+            ip("203.0.113.7"),
+            CachedGeo::Found {
+                geo: tokyo(),
+                expires_at: 10,
+            },
+        );
+        cache.insert(
+            ip("203.0.113.8"),
+            CachedGeo::Found {
+                geo: GeoInfo {
+                    asn: Some(16509),
+                    ..GeoInfo::default()
+                },
+                expires_at: 10,
+            },
+        );
+        cache.insert(ip("203.0.113.9"), CachedGeo::Missing { retry_after: 10 });
+        cache.forget_incomplete();
+        assert_eq!(cache.entry_count(), 1);
+        assert!(cache.found(ip("203.0.113.7"), 0).is_some());
+    }
+}
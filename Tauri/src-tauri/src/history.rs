@@ -0,0 +1,833 @@
+//! Played-session history in a local SQLite database.
+//!
+//! A live monitoring session feeds a [`SessionRecorder`] every tick. Its session row is
+//! written when it starts and its per-server totals, one row per server, are rewritten
+//! periodically and when it ends, so a crash loses at most the last interval. Timestamps
+//! are stored as UTC RFC 3339 text so they sort and compare as strings.
+
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const HISTORY_FILE: &str = "session_history.sqlite3";
+const SCHEMA_VERSION: i64 = 1;
+const DEFAULT_LIST_LIMIT: u32 = 100;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id          INTEGER PRIMARY KEY,
+    game        TEXT NOT NULL,
+    vpn_profile TEXT,
+    started_at  TEXT NOT NULL,
+    ended_at    TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_game_started ON sessions (game, started_at);
+CREATE TABLE IF NOT EXISTS session_servers (
+    session_id   INTEGER NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+    ip           TEXT NOT NULL,
+    port         INTEGER NOT NULL,
+    protocol     TEXT NOT NULL,
+    country      TEXT,
+    category     TEXT NOT NULL,
+    ping_min     INTEGER,
+    ping_max     INTEGER,
+    ping_sum     INTEGER NOT NULL,
+    ping_samples INTEGER NOT NULL,
+    bytes_sent   INTEGER NOT NULL,
+    bytes_recv   INTEGER NOT NULL,
+    first_seen   TEXT NOT NULL,
+    last_seen    TEXT NOT NULL,
+    PRIMARY KEY (session_id, ip, port, protocol)
+);
+CREATE INDEX IF NOT EXISTS session_servers_endpoint ON session_servers (ip, port, protocol);
+";
+
+pub fn now_utc() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// One server as seen on one monitor tick.
+#[derive(Debug, Clone)]
+pub struct ServerSample<'a> {
+    pub ip: &'a str,
+    pub port: u16,
+    pub protocol: &'a str,
+    pub country: Option<&'a str>,
+    pub category: &'a str,
+    pub ping_ms: Option<u32>,
+    /// Bytes exchanged since the previous tick.
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerRecord {
+    pub ip: String,
+    pub port: u16,
+    pub protocol: String,
+    pub country: Option<String>,
+    /// Flow category on the last tick the server was seen.
+    pub category: String,
+    pub ping_min_ms: Option<u32>,
+    pub ping_avg_ms: Option<f64>,
+    pub ping_max_ms: Option<u32>,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ServerTotals {
+    country: Option<String>,
+    category: String,
+    ping_min: Option<u32>,
+    ping_max: Option<u32>,
+    ping_sum: u64,
+    ping_samples: u64,
+    bytes_sent: u64,
+    bytes_recv: u64,
+    first_seen: String,
+    last_seen: String,
+}
+
+/// Accumulates one live session's servers while it runs.
+#[derive(Debug, Clone)]
+pub struct SessionRecorder {
+    game: String,
+    vpn_profile: Option<String>,
+    started_at: String,
+    servers: HashMap<(String, u16, String), ServerTotals>,
+}
+
+impl SessionRecorder {
+    pub fn new(game: &str, started_at: String) -> Self {
+        Self {
+            game: game.to_string(),
+            vpn_profile: None,
+            started_at,
+            servers: HashMap::new(),
+        }
+    }
+
+    /// Notes the tunnel profile in use; the last one seen is kept if the tunnel drops.
+    pub fn set_vpn_profile(&mut self, profile: Option<String>) {
+        if profile.is_some() {
+            self.vpn_profile = profile;
+        }
+    }
+
+    pub fn observe(&mut self, sample: &ServerSample<'_>, at: &str) {
+        let key = (
+            sample.ip.to_string(),
+            sample.port,
+            sample.protocol.to_string(),
+        );
+        let totals = self.servers.entry(key).or_insert_with(|| ServerTotals {
+            first_seen: at.to_string(),
+            ..ServerTotals::default()
+        });
+        if let Some(country) = sample.country {
+            totals.country = Some(country.to_string());
+        }
+        totals.category = sample.category.to_string();
+        if let Some(ping) = sample.ping_ms {
+            totals.ping_min = Some(totals.ping_min.map_or(ping, |min| min.min(ping)));
+            totals.ping_max = Some(totals.ping_max.map_or(ping, |max| max.max(ping)));
+            totals.ping_sum += u64::from(ping);
+            totals.ping_samples += 1;
+        }
+        totals.bytes_sent += sample.bytes_sent;
+        totals.bytes_recv += sample.bytes_recv;
+        totals.last_seen = at.to_string();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionFilter {
+    pub game: Option<String>,
+    /// RFC 3339 time or local `YYYY-MM-DD` date; sessions starting at or after it.
+    pub from: Option<String>,
+    /// RFC 3339 time or local `YYYY-MM-DD` date (inclusive); sessions starting before it.
+    pub to: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+    pub id: i64,
+    pub game: String,
+    pub vpn_profile: Option<String>,
+    pub started_at: String,
+    /// For a session still being recorded, when its totals were last saved.
+    pub ended_at: String,
+    pub in_progress: bool,
+    pub server_count: u32,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+/// One server across every matching session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerAggregate {
+    pub ip: String,
+    pub port: u16,
+    pub protocol: String,
+    pub country: Option<String>,
+    pub sessions: u32,
+    pub ping_min_ms: Option<u32>,
+    pub ping_avg_ms: Option<f64>,
+    pub ping_max_ms: Option<u32>,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub last_seen: String,
+    /// Tunnel profiles used in those sessions, without repeats.
+    pub vpn_profiles: Vec<String>,
+}
+
+pub struct SessionDb {
+    conn: Connection,
+}
+
+impl SessionDb {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            conn.execute_batch(SCHEMA)?;
+            conn.execute_batch(&format!("PRAGMA user_version = {};", SCHEMA_VERSION))?;
+        }
+        // Sessions interrupted before any server was written have nothing to show.
+        conn.execute(
+            "DELETE FROM sessions WHERE id NOT IN (SELECT session_id FROM session_servers)",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Writes a finished session; returns its id.
+    pub fn insert(&mut self, recorder: &SessionRecorder, ended_at: &str) -> rusqlite::Result<i64> {
+        let id = self.begin(recorder, ended_at)?;
+        self.update(id, recorder, ended_at)?;
+        Ok(id)
+    }
+
+    /// Writes the row of a session that has just started; returns its id.
+    pub fn begin(&self, recorder: &SessionRecorder, ended_at: &str) -> rusqlite::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO sessions (game, vpn_profile, started_at, ended_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                recorder.game,
+                recorder.vpn_profile,
+                recorder.started_at,
+                ended_at
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Replaces session `id`'s profile, end and server rows with the recorder's totals.
+    pub fn update(
+        &mut self,
+        id: i64,
+        recorder: &SessionRecorder,
+        ended_at: &str,
+    ) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE sessions SET vpn_profile = ?2, ended_at = ?3 WHERE id = ?1",
+            params![id, recorder.vpn_profile, ended_at],
+        )?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO session_servers (session_id, ip, port, protocol, country, category,
+                     ping_min, ping_max, ping_sum, ping_samples, bytes_sent, bytes_recv,
+                     first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for ((ip, port, protocol), t) in &recorder.servers {
+                insert.execute(params![
+                    id,
+                    ip,
+                    port,
+                    protocol,
+                    t.country,
+                    t.category,
+                    t.ping_min,
+                    t.ping_max,
+                    t.ping_sum as i64,
+                    t.ping_samples as i64,
+                    t.bytes_sent as i64,
+                    t.bytes_recv as i64,
+                    t.first_seen,
+                    t.last_seen,
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    pub fn remove(&self, id: i64) -> rusqlite::Result<()> {
+        self.conn
+            .execute("DELETE FROM sessions WHERE id = ?1", params![id])
+            .map(|_| ())
+    }
+
+    /// Matching sessions, newest first.
+    pub fn list(&self, filter: &SessionFilter) -> Result<Vec<SessionSummary>, String> {
+        let (from, to) = bounds(filter)?;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT s.id, s.game, s.vpn_profile, s.started_at, s.ended_at,
+                        COUNT(v.ip), COALESCE(SUM(v.bytes_sent), 0), COALESCE(SUM(v.bytes_recv), 0)
+                 FROM sessions s LEFT JOIN session_servers v ON v.session_id = s.id
+                 WHERE (?1 IS NULL OR s.game = ?1)
+                   AND (?2 IS NULL OR s.started_at >= ?2)
+                   AND (?3 IS NULL OR s.started_at < ?3)
+                 GROUP BY s.id
+                 ORDER BY s.started_at DESC, s.id DESC
+                 LIMIT ?4",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filter.game,
+                    from,
+                    to,
+                    filter.limit.unwrap_or(DEFAULT_LIST_LIMIT)
+                ],
+                |row| {
+                    Ok(SessionSummary {
+                        id: row.get(0)?,
+                        game: row.get(1)?,
+                        vpn_profile: row.get(2)?,
+                        started_at: row.get(3)?,
+                        ended_at: row.get(4)?,
+                        server_count: row.get(5)?,
+                        bytes_sent: row.get::<_, i64>(6)? as u64,
+                        bytes_recv: row.get::<_, i64>(7)? as u64,
+                        in_progress: false,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())
+    }
+
+    /// Servers of session `id`, busiest first; `None` if there is no such session.
+    pub fn servers(&self, id: i64) -> Result<Option<Vec<ServerRecord>>, String> {
+        let exists = self
+            .conn
+            .query_row("SELECT 1 FROM sessions WHERE id = ?1", [id], |_| Ok(()))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT ip, port, protocol, country, category, ping_min, ping_max, ping_sum,
+                        ping_samples, bytes_sent, bytes_recv, first_seen, last_seen
+                 FROM session_servers WHERE session_id = ?1
+                 ORDER BY bytes_sent + bytes_recv DESC, ip, port",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([id], |row| {
+                let ping_sum: i64 = row.get(7)?;
+                let ping_samples: i64 = row.get(8)?;
+                Ok(ServerRecord {
+                    ip: row.get(0)?,
+                    port: row.get(1)?,
+                    protocol: row.get(2)?,
+                    country: row.get(3)?,
+                    category: row.get(4)?,
+                    ping_min_ms: row.get(5)?,
+                    ping_avg_ms: average(ping_sum, ping_samples),
+                    ping_max_ms: row.get(6)?,
+                    bytes_sent: row.get::<_, i64>(9)? as u64,
+                    bytes_recv: row.get::<_, i64>(10)? as u64,
+                    first_seen: row.get(11)?,
+                    last_seen: row.get(12)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Per-server totals over the matching sessions, most played first. The average ping
+    /// weighs every sample equally rather than every session.
+    pub fn server_stats(&self, filter: &SessionFilter) -> Result<Vec<ServerAggregate>, String> {
+        let (from, to) = bounds(filter)?;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT v.ip, v.port, v.protocol,
+                        (SELECT c.country FROM session_servers c
+                         WHERE c.ip = v.ip AND c.port = v.port AND c.protocol = v.protocol
+                           AND c.country IS NOT NULL
+                         ORDER BY c.last_seen DESC LIMIT 1),
+                        COUNT(DISTINCT v.session_id), MIN(v.ping_min), MAX(v.ping_max),
+                        SUM(v.ping_sum), SUM(v.ping_samples), SUM(v.bytes_sent),
+                        SUM(v.bytes_recv), MAX(v.last_seen),
+                        GROUP_CONCAT(DISTINCT s.vpn_profile)
+                 FROM session_servers v JOIN sessions s ON s.id = v.session_id
+                 WHERE (?1 IS NULL OR s.game = ?1)
+                   AND (?2 IS NULL OR s.started_at >= ?2)
+                   AND (?3 IS NULL OR s.started_at < ?3)
+                 GROUP BY v.ip, v.port, v.protocol
+                 ORDER BY COUNT(DISTINCT v.session_id) DESC, MAX(v.last_seen) DESC
+                 LIMIT ?4",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filter.game,
+                    from,
+                    to,
+                    filter.limit.unwrap_or(DEFAULT_LIST_LIMIT)
+                ],
+                |row| {
+                    let profiles: Option<String> = row.get(12)?;
+                    let mut vpn_profiles: Vec<String> = profiles
+                        .map(|p| p.split(',').map(str::to_string).collect())
+                        .unwrap_or_default();
+                    vpn_profiles.sort();
+                    Ok(ServerAggregate {
+                        ip: row.get(0)?,
+                        port: row.get(1)?,
+                        protocol: row.get(2)?,
+                        country: row.get(3)?,
+                        sessions: row.get(4)?,
+                        ping_min_ms: row.get(5)?,
+                        ping_avg_ms: average(row.get(7)?, row.get(8)?),
+                        ping_max_ms: row.get(6)?,
+                        bytes_sent: row.get::<_, i64>(9)? as u64,
+                        bytes_recv: row.get::<_, i64>(10)? as u64,
+                        last_seen: row.get(11)?,
+                        vpn_profiles,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn average(sum: i64, samples: i64) -> Option<f64> {
+    (samples > 0).then(|| sum as f64 / samples as f64)
+}
+
+fn bounds(filter: &SessionFilter) -> Result<(Option<String>, Option<String>), String> {
+    let from = filter
+        .from
+        .as_deref()
+        .map(|s| parse_bound(s, false))
+        .transpose()?;
+    let to = filter
+        .to
+        .as_deref()
+        .map(|s| parse_bound(s, true))
+        .transpose()?;
+    Ok((from, to))
+}
+
+/// Normalizes a filter bound to stored form. A date stands for local midnight, or for the
+/// following midnight when it closes the range, so `to` includes the whole day.
+fn parse_bound(value: &str, end_of_range: bool) -> Result<String, String> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date: {}", value))?;
+    let date = if end_of_range {
+        date.succ_opt()
+            .ok_or_else(|| format!("invalid date: {}", value))?
+    } else {
+        date
+    };
+    let midnight = date
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .ok_or_else(|| format!("invalid date: {}", value))?;
+    Ok(midnight
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// App-wide handle to the history database; `None` when it could not be opened.
+pub struct SessionHistory {
+    db: Mutex<Option<SessionDb>>,
+    /// Sessions started and not yet finished.
+    live: Mutex<HashSet<i64>>,
+}
+
+impl SessionHistory {
+    pub fn open(path: Option<&Path>) -> Self {
+        let db = path.and_then(|path| match SessionDb::open(path) {
+            Ok(db) => Some(db),
+            Err(e) => {
+                tracing::warn!(error = %e, path = %path.display(), "session history unavailable");
+                None
+            }
+        });
+        Self {
+            db: Mutex::new(db),
+            live: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Writes the row of a live session as it starts; returns its id, `None` when the
+    /// history is unavailable.
+    pub fn start(&self, recorder: &SessionRecorder) -> Option<i64> {
+        let db = self.db.lock().unwrap();
+        match db.as_ref()?.begin(recorder, &now_utc()) {
+            Ok(id) => {
+                self.live.lock().unwrap().insert(id);
+                Some(id)
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to start session history");
+                None
+            }
+        }
+    }
+
+    /// Stores a running session's totals so far.
+    pub fn save(&self, id: i64, recorder: &SessionRecorder) {
+        let mut db = self.db.lock().unwrap();
+        let Some(db) = db.as_mut() else {
+            return;
+        };
+        if let Err(e) = db.update(id, recorder, &now_utc()) {
+            tracing::warn!(id, error = %e, "failed to save session history");
+        }
+    }
+
+    /// Stores a finished session, or drops it when nothing was seen during it.
+    pub fn finish(&self, id: i64, recorder: &SessionRecorder) {
+        self.live.lock().unwrap().remove(&id);
+        if !recorder.is_empty() {
+            self.save(id, recorder);
+            tracing::info!(id, game = %recorder.game, servers = recorder.servers.len(), "session saved");
+            return;
+        }
+        if let Some(db) = self.db.lock().unwrap().as_ref() {
+            if let Err(e) = db.remove(id) {
+                tracing::warn!(id, error = %e, "failed to drop empty session");
+            }
+        }
+    }
+
+    /// [`SessionDb::list`], with the sessions being recorded marked as such.
+    pub fn list(&self, filter: &SessionFilter) -> Result<Vec<SessionSummary>, String> {
+        let mut sessions = self.with_db(|db| db.list(filter))?;
+        let live = self.live.lock().unwrap();
+        for session in &mut sessions {
+            session.in_progress = live.contains(&session.id);
+        }
+        Ok(sessions)
+    }
+
+    fn with_db<T>(&self, f: impl FnOnce(&SessionDb) -> Result<T, String>) -> Result<T, String> {
+        match self.db.lock().unwrap().as_ref() {
+            Some(db) => f(db),
+            None => Err("session history is unavailable".to_string()),
+        }
+    }
+}
+
+/// Recorded sessions, newest first, optionally for one game and a date range. Sessions
+/// still being monitored are included with `in_progress` set.
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn list_sessions(
+    filter: Option<SessionFilter>,
+    state: tauri::State<'_, std::sync::Arc<SessionHistory>>,
+) -> Result<Vec<SessionSummary>, String> {
+    state.list(&filter.unwrap_or_default())
+}
+
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_session_servers(
+    session_id: i64,
+    state: tauri::State<'_, std::sync::Arc<SessionHistory>>,
+) -> Result<Vec<ServerRecord>, String> {
+    state.with_db(|db| {
+        db.servers(session_id)?
+            .ok_or_else(|| format!("session {} not found", session_id))
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_server_history_stats(
+    filter: Option<SessionFilter>,
+    state: tauri::State<'_, std::sync::Arc<SessionHistory>>,
+) -> Result<Vec<ServerAggregate>, String> {
+    state.with_db(|db| db.server_stats(&filter.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample<'a>(ip: &'a str, port: u16, ping: Option<u32>, bytes: u64) -> ServerSample<'a> {
+        ServerSample {
+            ip,
+            port,
+            protocol: "udp",
+            country: Some("Frankfurt, Germany"),
+            category: "game",
+            ping_ms: ping,
+            bytes_sent: bytes,
+            bytes_recv: bytes * 2,
+        }
+    }
+
+    fn session(game: &str, started_at: &str, profile: Option<&str>) -> SessionRecorder {
+        let mut recorder = SessionRecorder::new(game, started_at.to_string());
+        recorder.set_vpn_profile(profile.map(str::to_string));
+        recorder
+    }
+
+    #[test]
+    fn recorder_accumulates_pings_and_bytes() {
+        let mut rec = session("pubg", "2026-03-01T18:00:00Z", Some("fra-1"));
+        rec.observe(
+            &sample("203.0.113.7", 7086, None, 100),
+            "2026-03-01T18:00:02Z",
+        );
+        rec.observe(
+            &sample("203.0.113.7", 7086, Some(40), 100),
+            "2026-03-01T18:00:04Z",
+        );
+        rec.observe(
+            &sample("203.0.113.7", 7086, Some(20), 50),
+            "2026-03-01T18:00:06Z",
+        );
+        // A dropped tunnel does not erase the profile the session ran on.
+        rec.set_vpn_profile(None);
+
+        let mut db = SessionDb::open_in_memory().unwrap();
+        let id = db.insert(&rec, "2026-03-01T18:30:00Z").unwrap();
+        let servers = db.servers(id).unwrap().unwrap();
+        assert_eq!(servers.len(), 1);
+        let s = &servers[0];
+        assert_eq!((s.ping_min_ms, s.ping_max_ms), (Some(20), Some(40)));
+        assert_eq!(s.ping_avg_ms, Some(30.0));
+        assert_eq!((s.bytes_sent, s.bytes_recv), (250, 500));
+        assert_eq!(s.first_seen, "2026-03-01T18:00:02Z");
+        assert_eq!(s.last_seen, "2026-03-01T18:00:06Z");
+
+        let sessions = db.list(&SessionFilter::default()).unwrap();
+        assert_eq!(sessions[0].vpn_profile.as_deref(), Some("fra-1"));
+        assert_eq!(sessions[0].server_count, 1);
+        assert_eq!(db.servers(id + 1).unwrap(), None);
+    }
+
+    #[test]
+    fn lists_and_filters_sessions() {
+        let mut db = SessionDb::open_in_memory().unwrap();
+        for (game, start) in [
+            ("pubg", "2026-03-01T10:00:00Z"),
+            ("cs2", "2026-03-02T10:00:00Z"),
+            ("pubg", "2026-03-03T10:00:00Z"),
+        ] {
+            let mut rec = session(game, start, None);
+            rec.observe(&sample("203.0.113.7", 7086, Some(30), 10), start);
+            db.insert(&rec, start).unwrap();
+        }
+
+        let all = db.list(&SessionFilter::default()).unwrap();
+        let starts: Vec<_> = all.iter().map(|s| s.started_at.as_str()).collect();
+        assert_eq!(
+            starts,
+            [
+                "2026-03-03T10:00:00Z",
+                "2026-03-02T10:00:00Z",
+                "2026-03-01T10:00:00Z"
+            ]
+        );
+
+        let pubg = SessionFilter {
+            game: Some("pubg".to_string()),
+            ..SessionFilter::default()
+        };
+        assert_eq!(db.list(&pubg).unwrap().len(), 2);
+
+        let window = SessionFilter {
+            from: Some("2026-03-01T12:00:00Z".to_string()),
+            to: Some("2026-03-03T00:00:00+00:00".to_string()),
+            ..SessionFilter::default()
+        };
+        let in_window = db.list(&window).unwrap();
+        assert_eq!(in_window.len(), 1);
+        assert_eq!(in_window[0].game, "cs2");
+
+        let limited = SessionFilter {
+            limit: Some(1),
+            ..SessionFilter::default()
+        };
+        assert_eq!(db.list(&limited).unwrap().len(), 1);
+
+        let bad = SessionFilter {
+            from: Some("yesterday".to_string()),
+            ..SessionFilter::default()
+        };
+        assert!(db.list(&bad).is_err());
+    }
+
+    #[test]
+    fn date_bounds_cover_whole_local_days() {
+        let from = parse_bound("2026-03-02", false).unwrap();
+        let to = parse_bound("2026-03-02", true).unwrap();
+        let from = DateTime::parse_from_rfc3339(&from).unwrap();
+        let to = DateTime::parse_from_rfc3339(&to).unwrap();
+        assert_eq!(to - from, chrono::Duration::days(1));
+        assert_eq!(
+            from.with_timezone(&Local).date_naive(),
+            NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn aggregates_servers_across_sessions() {
+        let mut db = SessionDb::open_in_memory().unwrap();
+        let mut first = session("pubg", "2026-03-01T10:00:00Z", Some("fra-1"));
+        first.observe(
+            &sample("203.0.113.7", 7086, Some(10), 100),
+            "2026-03-01T10:00:02Z",
+        );
+        first.observe(
+            &sample("203.0.113.7", 7086, Some(20), 100),
+            "2026-03-01T10:00:04Z",
+        );
+        first.observe(
+            &sample("198.51.100.9", 7001, Some(90), 5),
+            "2026-03-01T10:00:04Z",
+        );
+        db.insert(&first, "2026-03-01T10:30:00Z").unwrap();
+
+        let mut second = session("pubg", "2026-03-02T10:00:00Z", Some("ams-2"));
+        second.observe(
+            &sample("203.0.113.7", 7086, Some(60), 300),
+            "2026-03-02T10:00:02Z",
+        );
+        db.insert(&second, "2026-03-02T10:30:00Z").unwrap();
+
+        let stats = db.server_stats(&SessionFilter::default()).unwrap();
+        assert_eq!(stats.len(), 2);
+        let top = &stats[0];
+        assert_eq!((top.ip.as_str(), top.port), ("203.0.113.7", 7086));
+        assert_eq!(top.sessions, 2);
+        assert_eq!((top.ping_min_ms, top.ping_max_ms), (Some(10), Some(60)));
+        assert_eq!(top.ping_avg_ms, Some(30.0));
+        assert_eq!(top.bytes_sent, 500);
+        assert_eq!(top.last_seen, "2026-03-02T10:00:02Z");
+        assert_eq!(top.vpn_profiles, ["ams-2", "fra-1"]);
+        assert_eq!(top.country.as_deref(), Some("Frankfurt, Germany"));
+
+        let cs2 = SessionFilter {
+            game: Some("cs2".to_string()),
+            ..SessionFilter::default()
+        };
+        assert!(db.server_stats(&cs2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_running_session_is_saved_as_it_goes() {
+        let history = SessionHistory {
+            db: Mutex::new(Some(SessionDb::open_in_memory().unwrap())),
+            live: Mutex::new(HashSet::new()),
+        };
+        let mut rec = session("pubg", "2026-03-01T18:00:00Z", Some("fra-1"));
+        let id = history.start(&rec).unwrap();
+        let listed = || history.list(&SessionFilter::default()).unwrap();
+        assert_eq!(listed()[0].id, id);
+        assert!(listed()[0].in_progress);
+
+        rec.observe(
+            &sample("203.0.113.7", 7086, Some(40), 100),
+            "2026-03-01T18:00:02Z",
+        );
+        history.save(id, &rec);
+        assert_eq!(listed()[0].bytes_sent, 100);
+
+        rec.observe(
+            &sample("203.0.113.7", 7086, Some(20), 50),
+            "2026-03-01T18:00:04Z",
+        );
+        rec.observe(
+            &sample("198.51.100.2", 27015, None, 10),
+            "2026-03-01T18:00:04Z",
+        );
+        history.finish(id, &rec);
+        let sessions = listed();
+        assert_eq!(sessions.len(), 1);
+        assert!(!sessions[0].in_progress);
+        assert_eq!(sessions[0].server_count, 2);
+        assert_eq!(sessions[0].bytes_sent, 160);
+        let servers = history.with_db(|db| db.servers(id)).unwrap().unwrap();
+        let first = servers.iter().find(|s| s.port == 7086).unwrap();
+        assert_eq!((first.ping_min_ms, first.ping_max_ms), (Some(20), Some(40)));
+    }
+
+    #[test]
+    fn reopening_keeps_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(HISTORY_FILE);
+        {
+            let history = SessionHistory::open(Some(&path));
+            let empty = session("pubg", "2026-03-01T10:00:00Z", None);
+            history.finish(history.start(&empty).unwrap(), &empty);
+            let mut rec = session("pubg", "2026-03-01T11:00:00Z", None);
+            let id = history.start(&rec).unwrap();
+            rec.observe(
+                &sample("203.0.113.7", 7086, None, 1),
+                "2026-03-01T11:00:02Z",
+            );
+            history.finish(id, &rec);
+            // Interrupted before its first save.
+            history.start(&session("pubg", "2026-03-01T12:00:00Z", None));
+        }
+        let history = SessionHistory::open(Some(&path));
+        let sessions = history
+            .with_db(|db| db.list(&SessionFilter::default()))
+            .unwrap();
+        // The empty sessions were not kept.
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].started_at, "2026-03-01T11:00:00Z");
+    }
+}
@@ -1,3 +1,5 @@
+use crate::cloud::{CloudLabel, CloudLabeler};
+use crate::geo::GeoResolver;
 use dashmap::DashMap;
 use hickory_resolver::TokioAsyncResolver;
 use serde::{Deserialize, Serialize};
@@ -8,6 +10,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{watch, Mutex, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time;
 
 use windows::Win32::NetworkManagement::IpHelper::{
@@ -24,6 +27,7 @@ const MAX_CONCURRENT_PINGS: usize = 8;
 const ICMP_PAYLOAD_SIZE: usize = 32;
 const ICMP_ERROR_BUFFER_SIZE: usize = 8;
 const PTR_TIMEOUT_MS: u64 = 700;
+const SHUTDOWN_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
 
 type PtrLookupFuture = Pin<Box<dyn Future<Output = Option<String>> + Send>>;
 type PtrLookupFn = Arc<dyn Fn(Ipv4Addr) -> PtrLookupFuture + Send + Sync>;
@@ -33,6 +37,9 @@ pub struct HopStat {
     pub hop: u8,
     pub ip: String,
     pub hostname: Option<String>,
+    /// Cloud provider, region and ASN; filled in when stats are read.
+    #[serde(default)]
+    pub cloud: Option<CloudLabel>,
     pub loss_pct: f64,
     pub sent: u32,
     pub recv: u32,
@@ -48,6 +55,7 @@ impl HopStat {
             hop,
             ip,
             hostname: None,
+            cloud: None,
             loss_pct: 0.0,
             sent: 0,
             recv: 0,
@@ -85,6 +93,8 @@ pub struct HopProbeState {
     pub is_running: bool,
     pub target: Option<String>,
     pub cancel_token: Option<watch::Sender<bool>>,
+    /// The traceroute task; it finishes only once its ping workers have.
+    pub task: Option<JoinHandle<()>>,
     pub hops: Arc<DashMap<u8, HopStat>>,
     pub session_epoch: Arc<AtomicU64>,
 }
@@ -95,6 +105,7 @@ impl Default for HopProbeState {
             is_running: false,
             target: None,
             cancel_token: None,
+            task: None,
             hops: Arc::new(DashMap::new()),
             session_epoch: Arc::new(AtomicU64::new(0)),
         }
@@ -172,7 +183,7 @@ pub(crate) fn sync_ping_icmp(
     }
 }
 
-fn create_resolver() -> Option<TokioAsyncResolver> {
+pub(crate) fn create_resolver() -> Option<TokioAsyncResolver> {
     TokioAsyncResolver::tokio_from_system_conf().ok()
 }
 
@@ -186,8 +197,11 @@ async fn resolve_target(target: &str, resolver: &TokioAsyncResolver) -> Option<I
     None
 }
 
-async fn reverse_resolve_ip(ip: Ipv4Addr, resolver: &TokioAsyncResolver) -> Option<String> {
-    if let Ok(response) = resolver.reverse_lookup(std::net::IpAddr::V4(ip)).await {
+pub(crate) async fn reverse_resolve_ip(
+    ip: std::net::IpAddr,
+    resolver: &TokioAsyncResolver,
+) -> Option<String> {
+    if let Ok(response) = resolver.reverse_lookup(ip).await {
         if let Some(name) = response.into_iter().next() {
             let mut s = name.to_string();
             if s.ends_with('.') {
@@ -330,6 +344,7 @@ async fn traceroute_session(
     ptr_lookup: PtrLookupFn,
 ) {
     let ptr_cache: Arc<DashMap<Ipv4Addr, Option<String>>> = Arc::new(DashMap::new());
+    let mut workers = Vec::new();
 
     for ttl in 1..=MAX_HOPS {
         if !is_session_active(&session_epoch, session_id, &cancel_rx) {
@@ -378,7 +393,7 @@ async fn traceroute_session(
             let worker_map = hops_map.clone();
             let worker_epoch = session_epoch.clone();
             let worker_limiter = ping_limiter.clone();
-            tokio::spawn(async move {
+            workers.push(tokio::spawn(async move {
                 ping_worker(
                     ttl,
                     target_ip,
@@ -390,7 +405,7 @@ async fn traceroute_session(
                     worker_cancel_rx,
                 )
                 .await;
-            });
+            }));
 
             if is_target {
                 break;
@@ -401,6 +416,11 @@ async fn traceroute_session(
             hops_map.insert(ttl, stat);
         }
     }
+
+    // Workers run until the probe is stopped.
+    for worker in workers {
+        let _ = worker.await;
+    }
 }
 
 #[tauri::command]
@@ -436,10 +456,10 @@ pub async fn start_hop_probe(target: String, state: tauri::State<'_, Arc<Mutex<H
     let ptr_resolver = resolver.clone();
     let ptr_lookup: PtrLookupFn = Arc::new(move |ip: Ipv4Addr| {
         let ptr_resolver = ptr_resolver.clone();
-        Box::pin(async move { reverse_resolve_ip(ip, &ptr_resolver).await })
+        Box::pin(async move { reverse_resolve_ip(std::net::IpAddr::V4(ip), &ptr_resolver).await })
     });
 
-    tokio::spawn(async move {
+    s.task = Some(tokio::spawn(async move {
         traceroute_session(
             target_ip,
             hops_map,
@@ -450,7 +470,7 @@ pub async fn start_hop_probe(target: String, state: tauri::State<'_, Arc<Mutex<H
             ptr_lookup,
         )
         .await;
-    });
+    }));
 
     Ok(target_ip.to_string())
 }
@@ -476,12 +496,42 @@ pub async fn stop_hop_probe(state: tauri::State<'_, Arc<Mutex<HopProbeState>>>)
     Ok("Hop probe stopped".to_string())
 }
 
+/// Cancels any running probe and waits, up to a bound, for its workers to exit.
+pub async fn shutdown(state: &Arc<Mutex<HopProbeState>>) {
+    let task = {
+        let mut s = state.lock().await;
+        if let Some(cancel_tx) = s.cancel_token.take() {
+            let _ = cancel_tx.send(true);
+        }
+        s.session_epoch.fetch_add(1, Ordering::SeqCst);
+        s.is_running = false;
+        s.target = None;
+        s.task.take()
+    };
+
+    if let Some(task) = task {
+        if time::timeout(SHUTDOWN_JOIN_TIMEOUT, task).await.is_err() {
+            tracing::warn!("hop probe did not stop in time");
+        }
+    }
+}
+
 #[tauri::command]
-pub async fn get_hop_stats(state: tauri::State<'_, Arc<Mutex<HopProbeState>>>) -> Result<Vec<HopStat>, String> {
+pub async fn get_hop_stats(
+    state: tauri::State<'_, Arc<Mutex<HopProbeState>>>,
+    cloud: tauri::State<'_, Arc<CloudLabeler>>,
+    geo: tauri::State<'_, Arc<GeoResolver>>,
+) -> Result<Vec<HopStat>, String> {
     let state_arc = state.inner().clone();
     let s = state_arc.lock().await;
     let mut results: Vec<HopStat> = s.hops.iter().map(|entry| entry.value().clone()).collect();
     results.sort_by(|a, b| a.hop.cmp(&b.hop));
+    geo.inner().refresh(results.iter().filter_map(|stat| stat.ip.parse().ok()));
+    for stat in &mut results {
+        if let Ok(ip) = stat.ip.parse() {
+            stat.cloud = cloud.label(ip, geo.cached(ip).as_ref());
+        }
+    }
     
     Ok(results)
 }
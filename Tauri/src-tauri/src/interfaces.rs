@@ -0,0 +1,246 @@
+//! Network interfaces the live capture opens sources on.
+//!
+//! With the tunnel up, packets to routed servers leave through the tunnel adapter while the
+//! rest keep using the physical NIC, so the capture covers every interface that is up, not
+//! only the one holding the default route. Packets are tagged with the interface they were
+//! captured on, which tells tunneled traffic apart from direct traffic.
+
+use crate::netaddr;
+use serde::Serialize;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Whether live capture needs a source per interface. Windows raw sockets are bound to one
+/// interface address; Linux packet sockets see every interface and report which.
+pub const PER_INTERFACE: bool = cfg!(windows);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Interface {
+    /// OS interface index.
+    pub index: u32,
+    /// Friendly name, e.g. `Ethernet` or `PingPalAdapter`.
+    pub name: String,
+    /// Unicast addresses; empty where the platform does not need them for capture.
+    pub addrs: Vec<IpAddr>,
+    pub up: bool,
+    pub loopback: bool,
+}
+
+impl Interface {
+    /// Our WireGuard adapter.
+    pub fn is_tunnel(&self) -> bool {
+        self.name.eq_ignore_ascii_case(crate::vpn::INTERFACE_NAME)
+    }
+}
+
+/// One live capture source to run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureTarget {
+    /// Interface the source is bound to; `None` when it sees every interface and tags
+    /// packets itself.
+    pub interface: Option<u32>,
+    pub bind_ip: IpAddr,
+}
+
+/// Sources covering `interfaces`: with `per_interface`, one per address family on each
+/// interface that is up; otherwise one per family for all of them.
+///
+/// IPv6 is only captured on interfaces with a global address, and on the tunnel, whose
+/// address is usually private. Link-local addresses carry no game traffic.
+pub fn capture_targets(interfaces: &[Interface], per_interface: bool) -> Vec<CaptureTarget> {
+    if !per_interface {
+        return [
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        ]
+        .into_iter()
+        .map(|bind_ip| CaptureTarget {
+            interface: None,
+            bind_ip,
+        })
+        .collect();
+    }
+
+    let mut targets = Vec::new();
+    for interface in interfaces.iter().filter(|i| i.up && !i.loopback) {
+        let ipv4 = interface.addrs.iter().find(|ip| match ip {
+            IpAddr::V4(v4) => !v4.is_link_local() && !v4.is_unspecified(),
+            IpAddr::V6(_) => false,
+        });
+        let ipv6 = interface.addrs.iter().find(|ip| match ip {
+            IpAddr::V4(_) => false,
+            IpAddr::V6(v6) if interface.is_tunnel() => !v6.is_unicast_link_local(),
+            IpAddr::V6(_) => netaddr::is_public(**ip),
+        });
+        for ip in ipv4.into_iter().chain(ipv6) {
+            targets.push(CaptureTarget {
+                interface: Some(interface.index),
+                bind_ip: *ip,
+            });
+        }
+    }
+    targets
+}
+
+#[cfg(windows)]
+pub fn list() -> io::Result<Vec<Interface>> {
+    use windows::Win32::NetworkManagement::IpHelper::{
+        GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER,
+        GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES_LH,
+    };
+    use windows::Win32::Networking::WinSock::{
+        AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN, SOCKADDR_IN6,
+    };
+
+    const ERROR_BUFFER_OVERFLOW: u32 = 111;
+    const IF_OPER_STATUS_UP: i32 = 1;
+    const IF_TYPE_SOFTWARE_LOOPBACK: u32 = 24;
+
+    // u64 words keep the adapter records aligned.
+    let mut size: u32 = 16 * 1024;
+    let mut buffer: Vec<u64> = Vec::new();
+    loop {
+        buffer.resize((size as usize).div_ceil(8), 0);
+        let result = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC.0 as u32,
+                GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER,
+                None,
+                Some(buffer.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH),
+                &mut size,
+            )
+        };
+        match result {
+            0 => break,
+            ERROR_BUFFER_OVERFLOW => continue,
+            code => return Err(io::Error::from_raw_os_error(code as i32)),
+        }
+    }
+
+    let mut interfaces = Vec::new();
+    let mut adapter = buffer.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH;
+    while let Some(a) = unsafe { adapter.as_ref() } {
+        let mut addrs = Vec::new();
+        let mut unicast = a.FirstUnicastAddress;
+        while let Some(u) = unsafe { unicast.as_ref() } {
+            let sockaddr = u.Address.lpSockaddr;
+            if let Some(family) = unsafe { sockaddr.as_ref() }.map(|s| s.sa_family) {
+                if family == AF_INET {
+                    let addr = unsafe { &*(sockaddr as *const SOCKADDR_IN) };
+                    let raw = unsafe { addr.sin_addr.S_un.S_addr };
+                    addrs.push(IpAddr::V4(Ipv4Addr::from(raw.to_ne_bytes())));
+                } else if family == AF_INET6 {
+                    let addr = unsafe { &*(sockaddr as *const SOCKADDR_IN6) };
+                    addrs.push(IpAddr::V6(Ipv6Addr::from(unsafe { addr.sin6_addr.u.Byte })));
+                }
+            }
+            unicast = u.Next;
+        }
+
+        let index = unsafe { a.Anonymous1.Anonymous.IfIndex };
+        interfaces.push(Interface {
+            // IPv6-only adapters have no IPv4 index.
+            index: if index != 0 { index } else { a.Ipv6IfIndex },
+            name: unsafe { a.FriendlyName.to_string() }.unwrap_or_default(),
+            addrs,
+            up: a.OperStatus.0 == IF_OPER_STATUS_UP,
+            loopback: a.IfType == IF_TYPE_SOFTWARE_LOOPBACK,
+        });
+        adapter = a.Next;
+    }
+    Ok(interfaces)
+}
+
+#[cfg(target_os = "linux")]
+pub fn list() -> io::Result<Vec<Interface>> {
+    const IFF_UP: u32 = 0x1;
+    const IFF_LOOPBACK: u32 = 0x8;
+
+    let mut interfaces = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/net")? {
+        let entry = entry?;
+        let read =
+            |file: &str| std::fs::read_to_string(entry.path().join(file)).unwrap_or_default();
+        let Ok(index) = read("ifindex").trim().parse() else {
+            continue;
+        };
+        let flags = u32::from_str_radix(read("flags").trim().trim_start_matches("0x"), 16)
+            .unwrap_or_default();
+        interfaces.push(Interface {
+            index,
+            name: entry.file_name().to_string_lossy().into_owned(),
+            addrs: Vec::new(),
+            up: flags & IFF_UP != 0,
+            loopback: flags & IFF_LOOPBACK != 0,
+        });
+    }
+    interfaces.sort_by_key(|i| i.index);
+    Ok(interfaces)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn list() -> io::Result<Vec<Interface>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing interfaces is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(index: u32, name: &str, addrs: &[&str]) -> Interface {
+        Interface {
+            index,
+            name: name.to_string(),
+            addrs: addrs.iter().map(|a| a.parse().unwrap()).collect(),
+            up: true,
+            loopback: false,
+        }
+    }
+
+    #[test]
+    fn one_source_per_family_on_each_usable_interface() {
+        let mut down = interface(4, "Wi-Fi", &["192.168.1.20"]);
+        down.up = false;
+        let mut loopback = interface(1, "Loopback", &["127.0.0.1", "::1"]);
+        loopback.loopback = true;
+        let interfaces = [
+            loopback,
+            interface(
+                7,
+                "Ethernet",
+                &[
+                    "fe80::1",
+                    "169.254.3.4",
+                    "10.0.0.5",
+                    "2001:db8::5",
+                    "2a00:1450::5",
+                ],
+            ),
+            down,
+            interface(23, "PingPalAdapter", &["fd00::2", "10.8.0.2"]),
+            interface(9, "Teredo", &["fe80::9"]),
+        ];
+
+        let targets: Vec<(Option<u32>, String)> = capture_targets(&interfaces, true)
+            .into_iter()
+            .map(|t| (t.interface, t.bind_ip.to_string()))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (Some(7), "10.0.0.5".to_string()),
+                (Some(7), "2a00:1450::5".to_string()),
+                (Some(23), "10.8.0.2".to_string()),
+                (Some(23), "fd00::2".to_string()),
+            ]
+        );
+        assert!(interfaces[3].is_tunnel());
+
+        let shared = capture_targets(&interfaces, false);
+        assert_eq!(shared.len(), 2);
+        assert!(shared.iter().all(|t| t.interface.is_none()));
+    }
+}
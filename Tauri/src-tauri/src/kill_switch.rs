@@ -0,0 +1,635 @@
+use crate::netaddr::Cidr;
+use crate::wg_config::WgConfig;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+const RULE_NAME: &str = "PingPal Kill Switch";
+const MARKER_FILE: &str = "pingpal_killswitch.json";
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Kept reachable in full-tunnel mode so the LAN and the reconnect path keep working.
+const FULL_TUNNEL_EXEMPT: &[&str] = &[
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "127.0.0.0/8",
+    "224.0.0.0/4",
+    "255.255.255.255/32",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: Mutex<Option<KillSwitchSession>> = Mutex::new(None);
+
+/// Platform hook that drops traffic to a set of remote addresses while the tunnel is down.
+pub trait Firewall: Send + Sync {
+    fn block(&self, remote: &[String]) -> Result<(), String>;
+    fn unblock(&self) -> Result<(), String>;
+}
+
+/// Windows Defender Firewall outbound block rule managed through `netsh advfirewall`.
+pub struct NetshFirewall;
+
+impl Firewall for NetshFirewall {
+    fn block(&self, remote: &[String]) -> Result<(), String> {
+        let _ = self.unblock();
+
+        let output = Command::new("netsh")
+            .args([
+                "advfirewall",
+                "firewall",
+                "add",
+                "rule",
+                &format!("name={}", RULE_NAME),
+                "dir=out",
+                "action=block",
+                "enable=yes",
+                "profile=any",
+                &format!("remoteip={}", remote.join(",")),
+            ])
+            .output()
+            .map_err(|e| format!("netsh advfirewall add rule failed: {}", e))?;
+
+        if !output.status.success() {
+            let stdout = crate::cmd_parsers::decode_console_output(&output.stdout);
+            return Err(format!("failed to add kill switch rule: {}", stdout));
+        }
+        Ok(())
+    }
+
+    fn unblock(&self) -> Result<(), String> {
+        let output = Command::new("netsh")
+            .args([
+                "advfirewall",
+                "firewall",
+                "delete",
+                "rule",
+                &format!("name={}", RULE_NAME),
+            ])
+            .output()
+            .map_err(|e| format!("netsh advfirewall delete rule failed: {}", e))?;
+
+        // netsh exits non-zero when no rule matched, which is the state we want anyway.
+        if !output.status.success() {
+            tracing::debug!("no kill switch rule to delete");
+        }
+        Ok(())
+    }
+}
+
+pub fn platform_firewall() -> Arc<dyn Firewall> {
+    Arc::new(NetshFirewall)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KillSwitchPlan {
+    pub full_tunnel: bool,
+    pub protected: Vec<String>,
+}
+
+impl KillSwitchPlan {
+    /// Protects each address family on its own: a family whose traffic all goes through the
+    /// tunnel (`0.0.0.0/0`, `::/0`) is blocked everywhere but the LAN and the endpoints,
+    /// otherwise only its AllowedIPs are. `full_tunnel` is about IPv4, like our routes.
+    pub fn from_config(config: &WgConfig) -> Self {
+        let allowed = config.allowed_networks();
+        let endpoints: Vec<IpAddr> = config
+            .peers
+            .iter()
+            .flat_map(|p| p.endpoint_addrs())
+            .collect();
+
+        let mut full_tunnel = false;
+        let mut protected = Vec::new();
+        for v6 in [false, true] {
+            let family: Vec<Cidr> = allowed
+                .iter()
+                .filter(|c| c.network().is_ipv6() == v6)
+                .copied()
+                .collect();
+            if !family.iter().any(|c| c.prefix_len() == 0) {
+                protected.extend(family.iter().map(Cidr::to_string));
+                continue;
+            }
+            full_tunnel |= !v6;
+
+            let exempt: Vec<(u128, u128)> = FULL_TUNNEL_EXEMPT
+                .iter()
+                .map(|cidr| cidr.parse::<Cidr>().unwrap())
+                .chain(endpoints.iter().map(|ip| Cidr::from(*ip)))
+                .filter(|c| c.network().is_ipv6() == v6)
+                .map(cidr_range)
+                .collect();
+            let max = if v6 { u128::MAX } else { u128::from(u32::MAX) };
+            protected.extend(
+                complement_ranges(&exempt, max)
+                    .into_iter()
+                    .map(|(start, end)| format_range(start, end, v6)),
+            );
+        }
+
+        Self {
+            full_tunnel,
+            protected,
+        }
+    }
+}
+
+/// Inclusive range of a network, as integers of its family's width.
+fn cidr_range(cidr: Cidr) -> (u128, u128) {
+    let (start, bits) = match cidr.network() {
+        IpAddr::V4(ip) => (u128::from(u32::from(ip)), 32),
+        IpAddr::V6(ip) => (u128::from(ip), 128),
+    };
+    let hosts = u128::MAX
+        .checked_shr(128 - bits + u32::from(cidr.prefix_len()))
+        .unwrap_or(0);
+    (start, start | hosts)
+}
+
+fn format_range(start: u128, end: u128, v6: bool) -> String {
+    if v6 {
+        format!("{}-{}", Ipv6Addr::from(start), Ipv6Addr::from(end))
+    } else {
+        format!(
+            "{}-{}",
+            Ipv4Addr::from(start as u32),
+            Ipv4Addr::from(end as u32)
+        )
+    }
+}
+
+/// Inclusive address ranges in `0..=max` covering everything outside `excluded`.
+fn complement_ranges(excluded: &[(u128, u128)], max: u128) -> Vec<(u128, u128)> {
+    let mut sorted = excluded.to_vec();
+    sorted.sort();
+
+    let mut out = Vec::new();
+    let mut next = Some(0);
+    for (start, end) in sorted {
+        let Some(from) = next else {
+            break;
+        };
+        if start > from {
+            out.push((from, start - 1));
+        }
+        next = (end < max).then(|| from.max(end + 1));
+    }
+    if let Some(from) = next {
+        out.push((from, max));
+    }
+    out
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KillSwitchMarker {
+    rule: String,
+    remote: Vec<String>,
+    installed_at: String,
+}
+
+fn marker_path() -> PathBuf {
+    std::env::temp_dir().join(MARKER_FILE)
+}
+
+fn write_marker(remote: &[String]) {
+    let marker = KillSwitchMarker {
+        rule: RULE_NAME.to_string(),
+        remote: remote.to_vec(),
+        installed_at: chrono::Local::now().to_rfc3339(),
+    };
+    if let Ok(json) = serde_json::to_string(&marker) {
+        if let Err(e) = std::fs::write(marker_path(), json) {
+            tracing::warn!(error = %e, "failed to record kill switch marker");
+        }
+    }
+}
+
+fn clear_marker() {
+    let _ = std::fs::remove_file(marker_path());
+}
+
+/// Removes a block rule left behind by a previous run that did not shut down cleanly.
+pub fn recover_stale_rules() {
+    // A rule held across a reconnect is ours, not stale.
+    if ACTIVE.lock().is_ok_and(|active| active.is_some()) {
+        return;
+    }
+    let path = marker_path();
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return;
+    };
+    let installed_at = serde_json::from_str::<KillSwitchMarker>(&text)
+        .map(|m| m.installed_at)
+        .unwrap_or_default();
+    tracing::warn!(%installed_at, "removing stale kill switch rule from previous session");
+
+    if let Err(e) = platform_firewall().unblock() {
+        tracing::warn!(error = %e, "failed to remove stale kill switch rule");
+        return;
+    }
+    clear_marker();
+}
+
+struct KillSwitchSession {
+    cancel_token: watch::Sender<bool>,
+    plan: Arc<Mutex<KillSwitchPlan>>,
+    /// Whether the block rule is in place. Held while netsh adds or removes it, so the
+    /// watcher and `disarm` never act on the rule at the same time.
+    blocking: Arc<Mutex<bool>>,
+    firewall: Arc<dyn Firewall>,
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+async fn tunnel_is_healthy() -> bool {
+    tokio::task::spawn_blocking(crate::service_manager::is_service_running)
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+        .unwrap_or(false)
+}
+
+/// Adds (`block`) or lifts the block rule if it is not already in that state, unless the
+/// watcher was cancelled meanwhile. Blocking: runs netsh.
+fn apply_block(
+    block: bool,
+    plan: &Mutex<KillSwitchPlan>,
+    blocking: &Mutex<bool>,
+    firewall: &dyn Firewall,
+    cancel_rx: &watch::Receiver<bool>,
+) {
+    let mut is_blocking = blocking.lock().unwrap();
+    if *cancel_rx.borrow() || *is_blocking == block {
+        return;
+    }
+
+    if block {
+        let remote = plan.lock().unwrap().protected.clone();
+        tracing::warn!(
+            targets = remote.len(),
+            "tunnel unhealthy; kill switch blocking traffic"
+        );
+        match firewall.block(&remote) {
+            Ok(()) => {
+                write_marker(&remote);
+                *is_blocking = true;
+            }
+            Err(e) => tracing::warn!(error = %e, "kill switch failed to block"),
+        }
+    } else {
+        tracing::info!("tunnel healthy again; kill switch releasing traffic");
+        match firewall.unblock() {
+            Ok(()) => {
+                clear_marker();
+                *is_blocking = false;
+            }
+            Err(e) => tracing::warn!(error = %e, "kill switch failed to unblock"),
+        }
+    }
+}
+
+async fn watch_tunnel(
+    plan: Arc<Mutex<KillSwitchPlan>>,
+    blocking: Arc<Mutex<bool>>,
+    firewall: Arc<dyn Firewall>,
+    mut cancel_rx: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if *cancel_rx.borrow() {
+                    break;
+                }
+
+                let healthy = tunnel_is_healthy().await;
+                if *cancel_rx.borrow() {
+                    break;
+                }
+
+                // Off the runtime: the lock may be held by a disarm waiting on netsh.
+                let (plan, blocking, firewall, cancel_rx) =
+                    (plan.clone(), blocking.clone(), firewall.clone(), cancel_rx.clone());
+                let _ = tokio::task::spawn_blocking(move || {
+                    apply_block(!healthy, &plan, &blocking, firewall.as_ref(), &cancel_rx)
+                })
+                .await;
+            }
+            _ = cancel_rx.changed() => {
+                if *cancel_rx.borrow() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Starts watching tunnel health for the connection described by `plan`. A block rule
+/// still in place from the previous connection is taken over and lifted once the new
+/// tunnel checks healthy.
+pub fn arm(plan: KillSwitchPlan) {
+    let previous = ACTIVE.lock().ok().and_then(|mut active| active.take());
+    let blocking = match previous {
+        Some(previous) => {
+            let _ = previous.cancel_token.send(true);
+            previous.blocking
+        }
+        None => Arc::new(Mutex::new(false)),
+    };
+
+    tracing::info!(
+        full_tunnel = plan.full_tunnel,
+        targets = plan.protected.len(),
+        "arming kill switch"
+    );
+
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let session = KillSwitchSession {
+        cancel_token: cancel_tx,
+        plan: Arc::new(Mutex::new(plan)),
+        blocking,
+        firewall: platform_firewall(),
+    };
+
+    let plan = session.plan.clone();
+    let blocking = session.blocking.clone();
+    let firewall = session.firewall.clone();
+    tauri::async_runtime::spawn(async move {
+        watch_tunnel(plan, blocking, firewall, cancel_rx).await;
+    });
+
+    if let Ok(mut active) = ACTIVE.lock() {
+        *active = Some(session);
+    }
+}
+
+/// Stops the watcher ahead of a reconnect but leaves any block rule in place, so traffic
+/// the old tunnel carried stays blocked until `arm` sees the new one healthy. A reconnect
+/// that fails must `disarm` to lift it.
+pub fn hold() {
+    if let Ok(active) = ACTIVE.lock() {
+        if let Some(session) = active.as_ref() {
+            let _ = session.cancel_token.send(true);
+        }
+    }
+}
+
+/// Stops the watcher and lifts any block rule. Call before intentionally stopping the tunnel.
+pub fn disarm() {
+    let session = match ACTIVE.lock() {
+        Ok(mut active) => active.take(),
+        Err(_) => return,
+    };
+    let Some(session) = session else {
+        return;
+    };
+
+    let _ = session.cancel_token.send(true);
+    // Waits out a rule change the watcher has in flight; any later one sees the cancel.
+    let mut blocking = session.blocking.lock().unwrap_or_else(|e| e.into_inner());
+    if *blocking {
+        if let Err(e) = session.firewall.unblock() {
+            tracing::warn!(error = %e, "failed to remove kill switch rule on disarm");
+            return;
+        }
+        *blocking = false;
+    }
+    clear_marker();
+    tracing::info!("kill switch disarmed");
+}
+
+/// Adds a host routed into the tunnel after connect (split-tunnel only).
+pub fn protect_host(ip: &str) {
+    let Ok(active) = ACTIVE.lock() else {
+        return;
+    };
+    let Some(session) = active.as_ref() else {
+        return;
+    };
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return;
+    };
+    let mut plan = session.plan.lock().unwrap();
+    let entry = Cidr::from(ip).to_string();
+    if !plan.full_tunnel && !plan.protected.contains(&entry) {
+        plan.protected.push(entry);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KillSwitchStatus {
+    pub enabled: bool,
+    pub armed: bool,
+    pub blocking: bool,
+    pub full_tunnel: bool,
+    pub protected: Vec<String>,
+}
+
+#[tracing::instrument(level = "info")]
+#[tauri::command]
+pub fn set_kill_switch_enabled(enabled: bool) -> Result<String, String> {
+    ENABLED.store(enabled, Ordering::SeqCst);
+    if !enabled {
+        disarm();
+    }
+    Ok(format!(
+        "Kill switch {}.",
+        if enabled { "enabled" } else { "disabled" }
+    ))
+}
+
+#[tracing::instrument(level = "debug")]
+#[tauri::command]
+pub fn get_kill_switch_status() -> Result<KillSwitchStatus, String> {
+    let active = ACTIVE
+        .lock()
+        .map_err(|_| "kill switch state poisoned".to_string())?;
+    let mut status = KillSwitchStatus {
+        enabled: is_enabled(),
+        armed: false,
+        blocking: false,
+        full_tunnel: false,
+        protected: Vec::new(),
+    };
+    if let Some(session) = active.as_ref() {
+        let plan = session.plan.lock().unwrap();
+        status.armed = true;
+        status.blocking = *session.blocking.lock().unwrap();
+        status.full_tunnel = plan.full_tunnel;
+        status.protected = plan.protected.clone();
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V4_MAX: u128 = u32::MAX as u128;
+
+    fn ip(s: &str) -> u128 {
+        match s.parse::<IpAddr>().unwrap() {
+            IpAddr::V4(ip) => u128::from(u32::from(ip)),
+            IpAddr::V6(ip) => u128::from(ip),
+        }
+    }
+
+    #[test]
+    fn cidr_range_masks_host_bits() {
+        assert_eq!(
+            cidr_range("10.1.2.3/8".parse().unwrap()),
+            (ip("10.0.0.0"), ip("10.255.255.255"))
+        );
+        assert_eq!(cidr_range("0.0.0.0/0".parse().unwrap()), (0, V4_MAX));
+        assert_eq!(cidr_range("::/0".parse().unwrap()), (0, u128::MAX));
+        assert_eq!(
+            cidr_range("fe80::1/10".parse().unwrap()),
+            (ip("fe80::"), ip("febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff"))
+        );
+        assert_eq!(
+            cidr_range("2001:db8::7/128".parse().unwrap()),
+            (ip("2001:db8::7"), ip("2001:db8::7"))
+        );
+    }
+
+    #[test]
+    fn complement_skips_excluded_ranges() {
+        let out = complement_ranges(
+            &[
+                (ip("10.0.0.0"), ip("10.255.255.255")),
+                (ip("1.2.3.4"), ip("1.2.3.4")),
+            ],
+            V4_MAX,
+        );
+        assert_eq!(
+            out,
+            vec![
+                (0, ip("1.2.3.3")),
+                (ip("1.2.3.5"), ip("9.255.255.255")),
+                (ip("11.0.0.0"), V4_MAX),
+            ]
+        );
+    }
+
+    #[test]
+    fn complement_handles_edges_and_overlap() {
+        assert!(complement_ranges(&[(0, V4_MAX)], V4_MAX).is_empty());
+        assert_eq!(complement_ranges(&[], V4_MAX), vec![(0, V4_MAX)]);
+        assert_eq!(
+            complement_ranges(&[(0, 10), (5, 20), (V4_MAX, V4_MAX)], V4_MAX),
+            vec![(21, V4_MAX - 1)]
+        );
+        assert_eq!(
+            complement_ranges(&[(0, 10), (u128::MAX, u128::MAX)], u128::MAX),
+            vec![(11, u128::MAX - 1)]
+        );
+    }
+
+    #[derive(Default)]
+    struct FakeFirewall {
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl Firewall for FakeFirewall {
+        fn block(&self, _remote: &[String]) -> Result<(), String> {
+            self.calls.lock().unwrap().push("block");
+            Ok(())
+        }
+        fn unblock(&self) -> Result<(), String> {
+            self.calls.lock().unwrap().push("unblock");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rule_changes_stop_once_cancelled() {
+        let plan = Mutex::new(KillSwitchPlan {
+            full_tunnel: false,
+            protected: vec!["13.0.0.0/8".to_string()],
+        });
+        let blocking = Mutex::new(false);
+        let firewall = FakeFirewall::default();
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+
+        apply_block(true, &plan, &blocking, &firewall, &cancel_rx);
+        apply_block(true, &plan, &blocking, &firewall, &cancel_rx);
+        assert!(*blocking.lock().unwrap());
+
+        // A disarm that raced the watcher: the rule is no longer the watcher's to touch.
+        cancel_tx.send(true).unwrap();
+        apply_block(false, &plan, &blocking, &firewall, &cancel_rx);
+        assert!(*blocking.lock().unwrap());
+        assert_eq!(*firewall.calls.lock().unwrap(), ["block"]);
+        clear_marker();
+    }
+
+    fn config(allowed: &str) -> WgConfig {
+        WgConfig::parse(&format!(
+            "[Interface]\nPrivateKey = BwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSY=\n\
+             [Peer]\nPublicKey = Dg8QERITFBUWFxgZGhscHR4fICEiIyQlJicoKSorLC0=\n\
+             AllowedIPs = {}\nEndpoint = 203.0.113.10:51820\n",
+            allowed
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn split_tunnel_plan_protects_allowed_ips() {
+        let plan = KillSwitchPlan::from_config(&config("13.0.0.0/8, 52.0.0.0/8, fd00::/8"));
+        assert!(!plan.full_tunnel);
+        assert_eq!(plan.protected, vec!["13.0.0.0/8", "52.0.0.0/8", "fd00::/8"]);
+    }
+
+    #[test]
+    fn dual_stack_full_tunnel_blocks_ipv6_too() {
+        let plan = KillSwitchPlan::from_config(&config("0.0.0.0/0, ::/0"));
+        assert!(plan.full_tunnel);
+        assert!(plan
+            .protected
+            .contains(&"0.0.0.0-9.255.255.255".to_string()));
+        // Global unicast is blocked; loopback, ULA, link-local and multicast are not.
+        assert!(plan
+            .protected
+            .contains(&"::2-fbff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".to_string()));
+        assert!(plan
+            .protected
+            .contains(&"fec0::-feff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".to_string()));
+        assert!(plan.protected.iter().all(|r| !r.starts_with("fe80:")));
+
+        let split = KillSwitchPlan::from_config(&config("13.0.0.0/8, ::/0"));
+        assert!(!split.full_tunnel);
+        assert_eq!(split.protected[0], "13.0.0.0/8");
+        assert!(split.protected.len() > 1);
+    }
+
+    #[test]
+    fn full_tunnel_plan_exempts_endpoint_and_lan() {
+        let plan = KillSwitchPlan::from_config(&config("0.0.0.0/0"));
+        assert!(plan.full_tunnel);
+        assert!(plan
+            .protected
+            .contains(&"0.0.0.0-9.255.255.255".to_string()));
+        assert!(plan
+            .protected
+            .contains(&"203.0.113.11-223.255.255.255".to_string()));
+        assert!(plan
+            .protected
+            .iter()
+            .all(|r| !r.starts_with("192.168.") && !r.starts_with("203.0.113.10")));
+    }
+}
@@ -1,12 +1,37 @@
+mod capture;
+mod classifier;
+mod cloud;
+mod cmd_parsers;
 mod dev_monitor;
+mod dissector;
 mod error;
+mod flow_history;
+mod game_watcher;
+mod geo;
+mod geo_cache;
+mod history;
 mod hop_probe;
+mod interfaces;
+mod kill_switch;
+mod live_feed;
+mod netaddr;
 mod network_monitor;
+mod pcap;
 mod privileges;
+mod processes;
+mod profiles;
+mod quality;
+mod recording;
+mod route_policy;
 mod service_manager;
+mod shutdown;
+mod tunnel_check;
+mod usage;
 mod vpn;
+mod wg_config;
 
 use std::sync::Arc;
+use tauri::Manager;
 use tokio::sync::Mutex;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -46,6 +71,48 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .setup(|app| {
+            kill_switch::recover_stale_rules();
+
+            let usage_path = app
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(usage::USAGE_FILE))
+                .ok();
+            app.manage(Arc::new(Mutex::new(usage::UsageTracker::load(usage_path))));
+            let history_path = app
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(history::HISTORY_FILE))
+                .ok();
+            app.manage(Arc::new(history::SessionHistory::open(history_path.as_deref())));
+            let geoip_dir = app.path().app_data_dir().map(|dir| dir.join("geoip")).ok();
+            let geo_cache_path = app
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(geo_cache::GEO_CACHE_FILE))
+                .ok();
+            let geo_settings_path = app
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(geo::GEO_SETTINGS_FILE))
+                .ok();
+            let geo_resolver = Arc::new(geo::GeoResolver::new(
+                geoip_dir,
+                geo_cache_path,
+                geo_settings_path,
+            ));
+            app.manage(geo_resolver.clone());
+            std::thread::spawn(move || geo_resolver.load_databases());
+            let cloud_dir = app.path().app_data_dir().map(|dir| dir.join("cloud")).ok();
+            let cloud_labeler = Arc::new(cloud::CloudLabeler::new(cloud_dir));
+            app.manage(cloud_labeler.clone());
+            // Range files run to megabytes; read them without holding up the window.
+            std::thread::spawn(move || cloud_labeler.reload());
+            let game_watcher = Arc::new(game_watcher::GameWatcher::default());
+            app.manage(game_watcher.clone());
+            game_watcher::spawn(app.handle().clone(), game_watcher);
+            shutdown::listen_for_signals(app.handle().clone());
+
             #[cfg(debug_assertions)]
             {
                 tracing::info!("Initializing Dev Monitor HUD via sysinfo...");
@@ -60,15 +127,52 @@ pub fn run() {
             get_device_name,
             vpn::connect_vpn,
             vpn::disconnect_vpn,
+            wg_config::import_wg_config,
+            wg_config::export_wg_config,
+            wg_config::create_wg_share_link,
+            wg_config::parse_wg_share_link,
+            kill_switch::set_kill_switch_enabled,
+            kill_switch::get_kill_switch_status,
+            usage::get_vpn_usage,
             network_monitor::start_monitoring,
+            network_monitor::start_monitoring_from_capture,
+            network_monitor::list_game_profiles,
+            network_monitor::list_monitor_sessions,
+            network_monitor::get_capture_interfaces,
             network_monitor::get_detected_servers,
+            network_monitor::get_flow_history,
             network_monitor::get_all_session_ips,
+            network_monitor::subscribe_monitor_updates,
+            network_monitor::unsubscribe_monitor_updates,
             network_monitor::stop_monitoring,
+            network_monitor::start_capture_recording,
+            network_monitor::stop_capture_recording,
+            network_monitor::get_capture_recording_status,
             network_monitor::add_detected_ip_to_routes,
+            network_monitor::get_route_audit_log,
+            history::list_sessions,
+            history::get_session_servers,
+            history::get_server_history_stats,
+            geo::get_geo_status,
+            geo::reload_geo_databases,
+            geo::set_geo_online_fallback,
+            cloud::get_cloud_ranges_status,
+            cloud::reload_cloud_ranges,
+            game_watcher::get_game_watcher_status,
+            game_watcher::set_auto_monitor,
             hop_probe::start_hop_probe,
             hop_probe::stop_hop_probe,
             hop_probe::get_hop_stats,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| match event {
+            tauri::RunEvent::ExitRequested { api, .. } => {
+                if !shutdown::handle_exit_requested(app_handle) {
+                    api.prevent_exit();
+                }
+            }
+            tauri::RunEvent::Exit => shutdown::handle_exit(app_handle),
+            _ => {}
+        });
 }
@@ -0,0 +1,376 @@
+//! Pushes live monitor results to subscribers as diffs.
+//!
+//! Each subscriber remembers what it was last sent and receives only the changes since
+//! then, at most once per its own interval; a slow subscriber therefore gets fewer, larger
+//! diffs rather than a backlog. The first update after subscribing carries everything as
+//! added.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// Identifies a server across updates.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct ServerKey {
+    /// Sessions are tracked apart, so a server two sessions talk to appears twice.
+    pub session_id: String,
+    pub ip: String,
+    pub port: u16,
+    pub protocol: String,
+}
+
+/// An item the feed can track between updates.
+pub trait Tracked: Clone + Serialize {
+    fn key(&self) -> ServerKey;
+    /// Latest round-trip time to the server's address.
+    fn ping_ms(&self) -> Option<u32>;
+    /// Whether anything a subscriber shows differs from `before`.
+    fn changed_since(&self, before: &Self) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PingUpdate {
+    pub ip: String,
+    pub ping_ms: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorUpdate<T> {
+    /// Counts the updates sent to this subscriber, starting at 1.
+    pub seq: u64,
+    pub added: Vec<T>,
+    pub updated: Vec<T>,
+    pub removed: Vec<ServerKey>,
+    /// Addresses whose ping changed, including newly seen ones that have a ping.
+    pub pings: Vec<PingUpdate>,
+    /// The full set of session IPs, present only when it changed.
+    pub session_ips: Option<Vec<String>>,
+}
+
+impl<T> MonitorUpdate<T> {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.pings.is_empty()
+            && self.session_ips.is_none()
+    }
+}
+
+/// Delivers one update; returns `false` once the receiving end is gone.
+pub type Sink<T> = Box<dyn FnMut(&MonitorUpdate<T>) -> bool + Send>;
+
+struct Subscriber<T> {
+    id: u64,
+    sink: Sink<T>,
+    interval: Duration,
+    last_sent: Option<Instant>,
+    seq: u64,
+    servers: HashMap<ServerKey, T>,
+    pings: HashMap<String, Option<u32>>,
+    session_ips: BTreeSet<String>,
+}
+
+impl<T: Tracked> Subscriber<T> {
+    fn is_due(&self, now: Instant) -> bool {
+        self.last_sent
+            .is_none_or(|sent| now.saturating_duration_since(sent) >= self.interval)
+    }
+
+    fn diff(&mut self, servers: &[T], session_ips: &BTreeSet<String>) -> MonitorUpdate<T> {
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+        let mut current = HashMap::with_capacity(servers.len());
+        for server in servers {
+            let key = server.key();
+            match self.servers.get(&key) {
+                None => added.push(server.clone()),
+                Some(before) if server.changed_since(before) => updated.push(server.clone()),
+                Some(_) => {}
+            }
+            current.insert(key, server.clone());
+        }
+        let mut removed: Vec<ServerKey> = self
+            .servers
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+        removed.sort();
+
+        // Several servers can share an address; the last one listed wins, as in a table.
+        let latest: BTreeMap<String, Option<u32>> =
+            servers.iter().map(|s| (s.key().ip, s.ping_ms())).collect();
+        let pings = latest
+            .iter()
+            .filter(|(ip, ping)| match self.pings.get(*ip) {
+                Some(before) => before != *ping,
+                None => ping.is_some(),
+            })
+            .map(|(ip, ping)| PingUpdate {
+                ip: ip.clone(),
+                ping_ms: *ping,
+            })
+            .collect();
+
+        let session_ips_changed = *session_ips != self.session_ips;
+
+        self.servers = current;
+        self.pings = latest.into_iter().collect();
+        if session_ips_changed {
+            self.session_ips = session_ips.clone();
+        }
+
+        MonitorUpdate {
+            seq: self.seq + 1,
+            added,
+            updated,
+            removed,
+            pings,
+            session_ips: session_ips_changed.then(|| session_ips.iter().cloned().collect()),
+        }
+    }
+}
+
+pub struct LiveFeed<T> {
+    next_id: u64,
+    subscribers: Vec<Subscriber<T>>,
+}
+
+impl<T> Default for LiveFeed<T> {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+impl<T: Tracked> LiveFeed<T> {
+    /// Registers `sink` for updates at most once per `interval`; returns the subscription id.
+    pub fn subscribe(&mut self, sink: Sink<T>, interval: Duration) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push(Subscriber {
+            id,
+            sink,
+            interval,
+            last_sent: None,
+            seq: 0,
+            servers: HashMap::new(),
+            pings: HashMap::new(),
+            session_ips: BTreeSet::new(),
+        });
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|s| s.id != id);
+        self.subscribers.len() != before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// Sends every due subscriber what changed since its last update, skipping empty
+    /// diffs, and drops subscribers whose receiving end has gone away.
+    pub fn publish(&mut self, servers: &[T], session_ips: &BTreeSet<String>, now: Instant) {
+        self.subscribers.retain_mut(|sub| {
+            if !sub.is_due(now) {
+                return true;
+            }
+            let update = sub.diff(servers, session_ips);
+            if update.is_empty() {
+                return true;
+            }
+            sub.seq = update.seq;
+            sub.last_sent = Some(now);
+            let delivered = (sub.sink)(&update);
+            if !delivered {
+                tracing::debug!(id = sub.id, "monitor update subscriber went away");
+            }
+            delivered
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    struct Server {
+        session: &'static str,
+        ip: &'static str,
+        port: u16,
+        rate: u64,
+        ping: Option<u32>,
+    }
+
+    impl Tracked for Server {
+        fn key(&self) -> ServerKey {
+            ServerKey {
+                session_id: self.session.to_string(),
+                ip: self.ip.to_string(),
+                port: self.port,
+                protocol: "udp".to_string(),
+            }
+        }
+
+        fn ping_ms(&self) -> Option<u32> {
+            self.ping
+        }
+
+        fn changed_since(&self, before: &Self) -> bool {
+            self != before
+        }
+    }
+
+    fn server(ip: &'static str, port: u16, rate: u64, ping: Option<u32>) -> Server {
+        Server {
+            session: "default",
+            ip,
+            port,
+            rate,
+            ping,
+        }
+    }
+
+    type Received = Arc<Mutex<Vec<MonitorUpdate<Server>>>>;
+
+    fn recorder() -> (Sink<Server>, Received) {
+        let received: Received = Arc::default();
+        let sink_received = received.clone();
+        let sink: Sink<Server> = Box::new(move |update: &MonitorUpdate<Server>| {
+            sink_received.lock().unwrap().push(update.clone());
+            true
+        });
+        (sink, received)
+    }
+
+    fn ips(list: &[&str]) -> BTreeSet<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn sends_additions_updates_removals_and_pings() {
+        let mut feed = LiveFeed::default();
+        let (sink, received) = recorder();
+        feed.subscribe(sink, Duration::ZERO);
+        let t0 = Instant::now();
+
+        let a = server("203.0.113.7", 7086, 100, None);
+        let b = server("198.51.100.2", 443, 5, None);
+        feed.publish(&[a.clone(), b.clone()], &ips(&["203.0.113.7"]), t0);
+
+        let a2 = server("203.0.113.7", 7086, 250, Some(31));
+        feed.publish(std::slice::from_ref(&a2), &ips(&["203.0.113.7"]), t0);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].seq, 1);
+        assert_eq!(received[0].added, [a, b.clone()]);
+        assert!(received[0].pings.is_empty());
+        assert_eq!(
+            received[0].session_ips,
+            Some(vec!["203.0.113.7".to_string()])
+        );
+
+        assert_eq!(received[1].seq, 2);
+        assert!(received[1].added.is_empty());
+        assert_eq!(received[1].updated, [a2]);
+        assert_eq!(received[1].removed, [b.key()]);
+        assert_eq!(
+            received[1].pings,
+            [PingUpdate {
+                ip: "203.0.113.7".to_string(),
+                ping_ms: Some(31)
+            }]
+        );
+        assert_eq!(received[1].session_ips, None);
+    }
+
+    #[test]
+    fn skips_empty_diffs() {
+        let mut feed = LiveFeed::default();
+        let (sink, received) = recorder();
+        feed.subscribe(sink, Duration::ZERO);
+        let now = Instant::now();
+        let servers = [server("203.0.113.7", 7086, 100, Some(20))];
+
+        feed.publish(&servers, &BTreeSet::new(), now);
+        feed.publish(&servers, &BTreeSet::new(), now);
+        feed.publish(&[], &BTreeSet::new(), now);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].seq, 2);
+        assert_eq!(received[1].removed.len(), 1);
+    }
+
+    #[test]
+    fn throttles_and_coalesces_per_subscriber() {
+        let mut feed = LiveFeed::default();
+        let (fast, fast_received) = recorder();
+        let (slow, slow_received) = recorder();
+        feed.subscribe(fast, Duration::ZERO);
+        feed.subscribe(slow, Duration::from_secs(5));
+        let t0 = Instant::now();
+
+        feed.publish(&[server("203.0.113.7", 1, 1, None)], &BTreeSet::new(), t0);
+        let t1 = t0 + Duration::from_secs(2);
+        feed.publish(&[server("203.0.113.8", 2, 1, None)], &BTreeSet::new(), t1);
+        let t2 = t0 + Duration::from_secs(6);
+        feed.publish(&[server("203.0.113.9", 3, 1, None)], &BTreeSet::new(), t2);
+
+        assert_eq!(fast_received.lock().unwrap().len(), 3);
+        let slow_received = slow_received.lock().unwrap();
+        assert_eq!(slow_received.len(), 2);
+        // The skipped tick's server came and went without the slow subscriber seeing it.
+        assert_eq!(slow_received[1].added[0].ip, "203.0.113.9");
+        assert_eq!(slow_received[1].removed.len(), 1);
+        assert_eq!(slow_received[1].removed[0].ip, "203.0.113.7");
+    }
+
+    #[test]
+    fn drops_closed_subscribers_and_unsubscribes() {
+        let mut feed: LiveFeed<Server> = LiveFeed::default();
+        let closed = feed.subscribe(Box::new(|_| false), Duration::ZERO);
+        let (sink, _) = recorder();
+        let open = feed.subscribe(sink, Duration::ZERO);
+        assert_ne!(closed, open);
+
+        feed.publish(
+            &[server("203.0.113.7", 1, 1, None)],
+            &BTreeSet::new(),
+            Instant::now(),
+        );
+        assert!(!feed.unsubscribe(closed));
+        assert!(feed.unsubscribe(open));
+        assert!(feed.is_empty());
+    }
+
+    #[test]
+    fn tracks_the_same_server_per_session() {
+        let mut feed = LiveFeed::default();
+        let (sink, received) = recorder();
+        feed.subscribe(sink, Duration::ZERO);
+        let now = Instant::now();
+
+        let game = server("203.0.113.7", 7086, 100, Some(20));
+        let voice = Server {
+            session: "voice",
+            ..game.clone()
+        };
+        feed.publish(&[game.clone(), voice.clone()], &BTreeSet::new(), now);
+        feed.publish(std::slice::from_ref(&game), &BTreeSet::new(), now);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0].added, [game, voice.clone()]);
+        assert_eq!(received[1].removed, [voice.key()]);
+        assert!(received[1].pings.is_empty());
+    }
+}
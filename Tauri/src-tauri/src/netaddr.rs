@@ -0,0 +1,317 @@
+//! Which addresses can belong to a remote game server.
+//!
+//! The monitor only credits traffic to, pings, geolocates and routes addresses that are
+//! reachable on the public internet; everything on the LAN, on the host itself or in a
+//! multicast group is ignored.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+/// [`is_public`] for an address in text form; anything that does not parse is not public.
+pub fn is_public_str(ip: &str) -> bool {
+    ip.parse().is_ok_and(is_public)
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_unspecified()
+        || ip.is_broadcast())
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // Dual-stack sockets report IPv4 peers as ::ffff:a.b.c.d, and NAT64 networks reach
+    // IPv4-only servers through 64:ff9b::/96; both stand for the embedded IPv4 address.
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., a, b, c, d] = ip.octets();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    let unique_local = segments[0] & 0xfe00 == 0xfc00;
+    let link_local = segments[0] & 0xffc0 == 0xfe80;
+    let multicast = segments[0] & 0xff00 == 0xff00;
+    let documentation = segments[0] == 0x2001 && segments[1] == 0x0db8;
+    if ip.is_unspecified() || ip.is_loopback() || unique_local || link_local || multicast {
+        return false;
+    }
+    // Everything outside global unicast (2000::/3) is reserved.
+    !documentation && segments[0] & 0xe000 == 0x2000
+}
+
+/// An IPv4 or IPv6 network such as `203.0.113.0/24`; a bare address is a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(ip) & mask == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(ip) & mask == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid network address: {}", s))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length: {}", s))?,
+            None => max,
+        };
+        // Host bits are dropped so 10.1.2.3/8 and 10.0.0.0/8 compare equal.
+        let network = match network {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+/// The single host `ip`.
+impl From<IpAddr> for Cidr {
+    fn from(ip: IpAddr) -> Self {
+        let prefix = if ip.is_ipv4() { 32 } else { 128 };
+        Self {
+            network: ip,
+            prefix,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// An inclusive port range such as `7000-7999`; a single port is a range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PortRange {
+    first: u16,
+    last: u16,
+}
+
+impl PortRange {
+    pub fn contains(&self, port: u16) -> bool {
+        (self.first..=self.last).contains(&port)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid port range: {}", s);
+        let (first, last) = s.trim().split_once('-').unwrap_or((s.trim(), s.trim()));
+        let first: u16 = first.trim().parse().map_err(|_| invalid())?;
+        let last: u16 = last.trim().parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PortRange> for String {
+    fn from(range: PortRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_ipv4() {
+        for ip in ["8.8.8.8", "203.0.113.7", "172.32.0.1"] {
+            assert!(is_public_str(ip), "{ip}");
+        }
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.10",
+            "127.0.0.1",
+            "169.254.1.1",
+            "224.0.0.251",
+            "239.255.255.250",
+            "0.0.0.0",
+            "255.255.255.255",
+        ] {
+            assert!(!is_public_str(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn classifies_ipv6() {
+        for ip in [
+            "2606:4700:4700::1111",
+            "2a01:4f8::1",
+            "2001:4860:4860::8888",
+        ] {
+            assert!(is_public_str(ip), "{ip}");
+        }
+        for ip in [
+            "::",
+            "::1",
+            "fc00::1",
+            "fd12:3456:789a::1",
+            "fe80::1",
+            "febf::1",
+            "ff02::1",
+            "ff0e::fb",
+            "2001:db8::7",
+            "100::1",
+            "4000::1",
+        ] {
+            assert!(!is_public_str(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4_follows_ipv4_rules() {
+        assert!(is_public_str("::ffff:8.8.8.8"));
+        assert!(!is_public_str("::ffff:192.168.1.10"));
+        assert!(is_public_str("64:ff9b::1.1.1.1"));
+        assert!(!is_public_str("64:ff9b::10.0.0.1"));
+    }
+
+    #[test]
+    fn rejects_unparseable_text() {
+        for ip in ["", "localhost", "1.2.3", "1.2.3.4:80", "[2001:4860::1]"] {
+            assert!(!is_public_str(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn cidr_membership() {
+        let net: Cidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(net.to_string(), "10.0.0.0/8");
+        assert!(net.contains("10.255.0.1".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(net.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8:1::/48".parse().unwrap();
+        assert!(v6.contains("2001:db8:1:ff::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db8:2::1".parse().unwrap()));
+        assert!(!v6.contains("10.0.0.1".parse().unwrap()));
+
+        let host: Cidr = "203.0.113.7".parse().unwrap();
+        assert_eq!(host.to_string(), "203.0.113.7/32");
+        assert!(host.contains("203.0.113.7".parse().unwrap()));
+        assert!(!host.contains("203.0.113.8".parse().unwrap()));
+
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains("8.8.8.8".parse().unwrap()));
+
+        for bad in ["10.0.0.0/33", "::/129", "10.0.0/8", "10.0.0.0/x", ""] {
+            assert!(bad.parse::<Cidr>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn port_ranges() {
+        let range: PortRange = "7000-7999".parse().unwrap();
+        assert!(range.contains(7000) && range.contains(7999));
+        assert!(!range.contains(8000));
+        assert_eq!(range.to_string(), "7000-7999");
+
+        let single: PortRange = " 27015 ".parse().unwrap();
+        assert!(single.contains(27015) && !single.contains(27016));
+        assert_eq!(single.to_string(), "27015");
+
+        for bad in ["", "x", "9-1", "1-70000", "1-"] {
+            assert!(bad.parse::<PortRange>().is_err(), "{bad}");
+        }
+    }
+}
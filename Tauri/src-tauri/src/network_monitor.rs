@@ -1,18 +1,42 @@
+use crate::capture::{self, Next, PacketSource};
+use crate::classifier::{self, Classification, FlowCategory, FlowFilter, FlowStats};
+use crate::cloud::{CloudLabel, CloudLabeler};
+use crate::dissector::{self, Transport};
 use crate::error::{to_cmd_err, AppError};
+use crate::flow_history::{FlowHistory, FlowSample};
+use crate::geo::{GeoInfo, GeoResolver};
+use crate::interfaces::{self, CaptureTarget, Interface};
+use crate::history::{self, ServerSample, SessionHistory, SessionRecorder};
+use crate::live_feed::{self, LiveFeed, MonitorUpdate, ServerKey};
+use crate::netaddr;
+use crate::pcap::PcapReader;
+use crate::processes::{GameProcesses, ProcessFinder};
+use crate::profiles::{self, GameProfile};
+use crate::recording::{self, Recording, RecordingStatus, StopReason};
+use crate::route_policy::{AutoRouter, Candidate, RouteDecision, RouteRule};
+use crate::tunnel_check::{self, PathCounts, TunnelStatus};
+use crate::usage::CounterSnapshot;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::mem;
-use std::net::Ipv4Addr;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex as AsyncMonitorMutex;
 
 const MONITORING_INTERVAL_SECS: u64 = 2;
-const UDP_CACHE_TIMEOUT_SECS: u64 = 30;
-const GEOIP_API_URL: &str = "http://ip-api.com/json/";
-const SNIFFER_RECV_TIMEOUT_MS: u32 = 500;
+const FLOW_CACHE_TIMEOUT_SECS: u64 = 30;
+const FLOW_HISTORY_WINDOW_SECS: u64 = 300;
 const PING_REFRESH_AFTER: Duration = Duration::from_secs(5);
 const PING_SPAWN_TIMEOUT: Duration = Duration::from_millis(1500);
+const PTR_LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+const SHUTDOWN_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+const INTERFACE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest wait before reopening a capture source that failed.
+const SOURCE_RETRY_MAX: Duration = Duration::from_secs(60);
+/// How often a live session's totals are written to the history while it runs.
+const HISTORY_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Clone, Copy, Debug)]
 enum PingEntry {
@@ -49,285 +73,545 @@ fn try_mark_ping_inflight(cache: &mut HashMap<String, PingEntry>, ip: &str, now:
 }
 
 use windows::Win32::NetworkManagement::IpHelper::{
-    GetExtendedTcpTable, GetExtendedUdpTable, MIB_TCP_STATE_ESTAB, MIB_TCPTABLE_OWNER_PID,
-    MIB_UDPTABLE_OWNER_PID, TCP_TABLE_OWNER_PID_ALL, UDP_TABLE_OWNER_PID,
-};
-use windows::Win32::Networking::WinSock::{
-    bind, closesocket, ioctlsocket, recv, setsockopt, socket, WSAStartup, AF_INET, IPPROTO_IP,
-    SEND_RECV_FLAGS, SIO_RCVALL, SO_RCVTIMEO, SOCKADDR, SOCKADDR_IN, SOL_SOCKET, SOCK_RAW,
-    WSADATA,
+    GetExtendedTcpTable, GetExtendedUdpTable, MIB_TCP6TABLE_OWNER_PID, MIB_TCP_STATE_ESTAB,
+    MIB_TCPTABLE_OWNER_PID, MIB_UDP6TABLE_OWNER_PID, MIB_UDPTABLE_OWNER_PID,
+    TCP_TABLE_OWNER_PID_ALL, UDP_TABLE_OWNER_PID,
 };
+use windows::Win32::Networking::WinSock::{AF_INET, AF_INET6};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DetectedServer {
+    /// Monitoring session that saw the server.
+    pub session_id: String,
+    /// IPv4 or IPv6 address in its standard text form.
     pub ip: String,
     pub port: u16,
     pub protocol: String,
     pub send_rate: u64,
     pub recv_rate: u64,
+    /// "City, Country" from `geo`, kept for display.
     pub country: Option<String>,
+    pub geo: Option<GeoInfo>,
+    /// Cloud provider, region and ASN.
+    pub cloud: Option<CloudLabel>,
     pub ping_ms: Option<u32>,
     pub detected_at: String,
+    pub category: FlowCategory,
+    pub confidence: f32,
+    /// `category` is game with at least the profile's minimum confidence.
     pub is_game_server: bool,
+    /// Passive estimates from inbound packet timing; `None` until enough packets arrived.
+    pub jitter_ms: Option<f32>,
+    pub tick_rate_hz: Option<f32>,
+    pub loss_pct: Option<f32>,
+    /// Capture interfaces the flow's packets were seen on, busiest first; empty for replays.
+    pub interfaces: Vec<String>,
+    /// Whether its packets went through the tunnel; `None` until seen on a tagged interface.
+    pub tunnel: Option<TunnelStatus>,
 }
 
-#[derive(Debug, Clone)]
-pub struct TrafficStats {
-    pub last_seen: Instant,
-    pub total_sent: u64,
-    pub total_recv: u64,
+impl DetectedServer {
+    fn classification(&self) -> Classification {
+        Classification {
+            category: self.category,
+            confidence: self.confidence,
+        }
+    }
 }
 
-impl Default for TrafficStats {
-    fn default() -> Self {
-        Self {
-            last_seen: Instant::now(),
-            total_sent: 0,
-            total_recv: 0,
+impl live_feed::Tracked for DetectedServer {
+    fn key(&self) -> ServerKey {
+        ServerKey {
+            session_id: self.session_id.clone(),
+            ip: self.ip.clone(),
+            port: self.port,
+            protocol: self.protocol.clone(),
         }
     }
+
+    fn ping_ms(&self) -> Option<u32> {
+        self.ping_ms
+    }
+
+    // `detected_at` is restamped every tick and is not a change on its own.
+    fn changed_since(&self, before: &Self) -> bool {
+        self.send_rate != before.send_rate
+            || self.recv_rate != before.recv_rate
+            || self.geo != before.geo
+            || self.cloud != before.cloud
+            || self.ping_ms != before.ping_ms
+            || self.category != before.category
+            || self.confidence != before.confidence
+            || self.is_game_server != before.is_game_server
+            || self.jitter_ms != before.jitter_ms
+            || self.tick_rate_hz != before.tick_rate_hz
+            || self.loss_pct != before.loss_pct
+            || self.interfaces != before.interfaces
+            || self.tunnel != before.tunnel
+    }
 }
 
-pub struct MonitorState {
-    is_monitoring: bool,
-    process_name: Option<String>,
-    detected_servers: Vec<DetectedServer>,
-    tcp_session_ips: HashMap<String, Instant>,
-    cancel_token: Option<tokio::sync::watch::Sender<bool>>,
-    interesting_ports: Arc<Mutex<HashSet<u16>>>,
-    udp_detected_cache: Arc<Mutex<HashMap<String, TrafficStats>>>,
-    geo_cache: Arc<Mutex<HashMap<String, Option<String>>>>,
-    ping_cache: Arc<Mutex<HashMap<String, PingEntry>>>,
+/// Local ports the game owns, per transport.
+#[derive(Debug, Clone, Default)]
+struct GamePorts {
+    udp: HashSet<u16>,
+    tcp: HashSet<u16>,
 }
 
-impl Default for MonitorState {
-    fn default() -> Self {
-        Self {
-            is_monitoring: false,
-            process_name: None,
-            detected_servers: Vec::new(),
-            tcp_session_ips: HashMap::new(),
-            cancel_token: None,
-            interesting_ports: Arc::new(Mutex::new(HashSet::new())),
-            udp_detected_cache: Arc::new(Mutex::new(HashMap::new())),
-            geo_cache: Arc::new(Mutex::new(HashMap::new())),
-            ping_cache: Arc::new(Mutex::new(HashMap::new())),
+impl GamePorts {
+    fn get(&self, transport: Transport) -> &HashSet<u16> {
+        match transport {
+            Transport::Udp => &self.udp,
+            Transport::Tcp => &self.tcp,
         }
     }
 }
 
-impl MonitorState {
-    pub fn new() -> Self {
-        Self::default()
-    }
+/// A remote endpoint the game exchanges traffic with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowKey {
+    transport: Transport,
+    remote: SocketAddr,
 }
 
-#[repr(C, packed)]
-#[derive(Debug, Copy, Clone)]
-struct Ipv4Header {
-    version_ihl: u8,
-    tos: u8,
-    total_len: u16,
-    id: u16,
-    flags_frag_offset: u16,
-    ttl: u8,
-    protocol: u8,
-    checksum: u16,
-    src_addr: u32,
-    dst_addr: u32,
+/// Session used by commands that do not name one.
+const DEFAULT_SESSION_ID: &str = "default";
+
+/// What a sniffer feeds for one session: the ports it credits and where the traffic goes.
+#[derive(Default)]
+struct SessionTap {
+    ports: Mutex<GamePorts>,
+    flow_cache: Mutex<HashMap<FlowKey, FlowStats>>,
+    recording: Mutex<Option<Recording>>,
 }
 
-#[repr(C, packed)]
-#[derive(Debug, Copy, Clone)]
-struct UdpHeader {
-    src_port: u16,
-    dst_port: u16,
-    len: u16,
-    checksum: u16,
+/// Sessions a sniffer feeds, by session id.
+type Taps = Arc<Mutex<HashMap<String, Arc<SessionTap>>>>;
+
+/// One live capture thread and its stop signal.
+struct LiveSource {
+    cancel_token: tokio::sync::watch::Sender<bool>,
+    handle: std::thread::JoinHandle<()>,
+    started_at: Instant,
+    /// Times in a row the source ended on its own soon after starting.
+    failures: u32,
 }
 
-fn ip_to_string(ip: u32) -> String {
-    let bytes = ip.to_ne_bytes();
-    format!("{}.{}.{}.{}", bytes[0], bytes[1], bytes[2], bytes[3])
+impl LiveSource {
+    /// Whether the thread ended on its own and has waited long enough to be reopened: one
+    /// interval at first, doubling with each failure up to [`SOURCE_RETRY_MAX`].
+    fn should_retry(&self, now: Instant) -> bool {
+        let delay = INTERFACE_CHECK_INTERVAL
+            .saturating_mul(1 << self.failures.min(4))
+            .min(SOURCE_RETRY_MAX);
+        self.handle.is_finished() && now.saturating_duration_since(self.started_at) >= delay
+    }
 }
 
-fn port_to_host(port: u32) -> u16 {
-    u16::from_be(port as u16)
+/// The live capture sources, shared by every session that watches a process. They open
+/// with the first such session and close when the last one ends. Sources follow the
+/// interfaces: one that comes up, such as the tunnel adapter, gets its own, and one that
+/// goes away or changes address has its source closed.
+#[derive(Default)]
+struct LiveCapture {
+    taps: Taps,
+    sources: HashMap<CaptureTarget, LiveSource>,
+    /// Interfaces at the last check, by index, for naming packet tags.
+    interfaces: Arc<Mutex<BTreeMap<u32, Interface>>>,
+    checked_at: Option<Instant>,
+    /// Sources closed since, to join on detach.
+    closed: Vec<std::thread::JoinHandle<()>>,
+    warned: bool,
 }
 
-fn is_public_ip(ip: &str) -> bool {
-    if let Ok(addr) = ip.parse::<Ipv4Addr>() {
-        let octets = addr.octets();
+impl LiveCapture {
+    fn attach(&mut self, id: &str, tap: Arc<SessionTap>) {
+        self.taps.lock().unwrap().insert(id.to_string(), tap);
+    }
 
-        if octets[0] == 10
-            || (octets[0] == 172 && (16..=31).contains(&octets[1]))
-            || (octets[0] == 192 && octets[1] == 168)
-            || octets[0] == 127
-            || (octets[0] == 169 && octets[1] == 254)
-            || (octets[0] >= 224 && octets[0] <= 239)
+    /// Matches the sources to the current interfaces, at most every
+    /// [`INTERFACE_CHECK_INTERVAL`]; called every tick.
+    fn ensure_running(&mut self) {
+        if self
+            .checked_at
+            .is_some_and(|at| at.elapsed() < INTERFACE_CHECK_INTERVAL)
         {
-            return false;
+            return;
         }
+        self.checked_at = Some(Instant::now());
 
-        return true;
-    }
-    false
-}
-
-fn get_local_ip() -> Option<Ipv4Addr> {
-    let probe_targets = [
-        "8.8.8.8:80",
-        "1.1.1.1:80",
-        "9.9.9.9:80",
-        "223.5.5.5:80",
-    ];
-
-    for target in probe_targets {
-        let socket = match std::net::UdpSocket::bind("0.0.0.0:0") {
-            Ok(s) => s,
-            Err(_) => continue,
+        let found = match interfaces::list() {
+            Ok(found) => found,
+            Err(e) => {
+                if !self.warned {
+                    tracing::warn!(
+                        error = %e,
+                        "could not list interfaces; sniffing will retry in background"
+                    );
+                    self.warned = true;
+                }
+                return;
+            }
         };
-        if socket.connect(target).is_err() {
-            continue;
+        let targets: HashSet<CaptureTarget> =
+            interfaces::capture_targets(&found, interfaces::PER_INTERFACE)
+                .into_iter()
+                .collect();
+        let names: BTreeMap<u32, Interface> = found.into_iter().map(|i| (i.index, i)).collect();
+        let name_of = |target: &CaptureTarget| {
+            target
+                .interface
+                .and_then(|i| names.get(&i))
+                .map_or("all", |i| i.name.as_str())
+                .to_string()
+        };
+
+        if targets.is_empty() {
+            if !self.warned {
+                tracing::warn!("no interface to capture on; sniffing will retry in background");
+                self.warned = true;
+            }
+        } else {
+            self.warned = false;
         }
-        if let Ok(addr) = socket.local_addr() {
-            if let std::net::SocketAddr::V4(addr_v4) = addr {
-                return Some(*addr_v4.ip());
+
+        let closed: Vec<CaptureTarget> = self
+            .sources
+            .keys()
+            .filter(|t| !targets.contains(t))
+            .cloned()
+            .collect();
+        for target in closed {
+            if let Some(source) = self.sources.remove(&target) {
+                tracing::info!(
+                    interface = %name_of(&target),
+                    ip = %target.bind_ip,
+                    "interface gone; closing sniffer"
+                );
+                let _ = source.cancel_token.send(true);
+                self.closed.push(source.handle);
             }
         }
-    }
 
-    None
-}
+        // A source often fails to bind when its interface has only just appeared, as the
+        // tunnel adapter does on connect, so one that ended is reopened with a backoff.
+        let now = Instant::now();
+        for target in targets {
+            let failures = match self.sources.get(&target) {
+                None => 0,
+                Some(source) if source.should_retry(now) => {
+                    let source = self.sources.remove(&target).expect("source is present");
+                    let _ = source.handle.join();
+                    if now.saturating_duration_since(source.started_at) > SOURCE_RETRY_MAX {
+                        0
+                    } else {
+                        source.failures + 1
+                    }
+                }
+                Some(_) => continue,
+            };
+            tracing::info!(
+                interface = %name_of(&target),
+                ip = %target.bind_ip,
+                failures,
+                "starting sniffer"
+            );
+            let (cancel_token, cancel_rx) = tokio::sync::watch::channel(false);
+            let bind_ip = target.bind_ip;
+            let handle = spawn_sniffer(
+                move || capture::open_live(bind_ip),
+                target.interface,
+                self.taps.clone(),
+                cancel_rx,
+            );
+            self.sources.insert(
+                target,
+                LiveSource {
+                    cancel_token,
+                    handle,
+                    started_at: now,
+                    failures,
+                },
+            );
+        }
 
-fn is_safe_process_name(name: &str) -> bool {
-    !name.is_empty()
-        && name.len() <= 260
-        && name
-            .chars()
-            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ' '))
-}
+        *self.interfaces.lock().unwrap() = names;
+    }
 
-fn get_all_process_ids(process_name: &str) -> Vec<u32> {
-    use std::process::Command;
+    /// Stops feeding session `id`, unless it has been restarted with another tap since.
+    /// Closes the sources once no session is left and returns the threads to join.
+    fn detach(&mut self, id: &str, tap: &Arc<SessionTap>) -> Vec<std::thread::JoinHandle<()>> {
+        {
+            let mut taps = self.taps.lock().unwrap();
+            if taps.get(id).is_some_and(|current| Arc::ptr_eq(current, tap)) {
+                taps.remove(id);
+            }
+            if !taps.is_empty() {
+                return Vec::new();
+            }
+        }
 
-    if !is_safe_process_name(process_name) {
-        tracing::warn!(process = %process_name, "rejected unsafe process name");
-        return Vec::new();
+        self.warned = false;
+        self.checked_at = None;
+        let mut handles = std::mem::take(&mut self.closed);
+        for (_, source) in self.sources.drain() {
+            let _ = source.cancel_token.send(true);
+            handles.push(source.handle);
+        }
+        handles
     }
+}
 
-    let output = match Command::new("tasklist")
-        .args([
-            "/FI",
-            &format!("IMAGENAME eq {}", process_name),
-            "/FO",
-            "CSV",
-            "/NH",
-        ])
-        .output()
-    {
-        Ok(output) => output,
-        Err(_) => return Vec::new(),
-    };
+/// One watched game or replayed capture.
+struct Session {
+    /// Process or capture file name.
+    label: String,
+    is_monitoring: bool,
+    replay: bool,
+    detected_servers: Vec<DetectedServer>,
+    tcp_session_ips: HashMap<String, Instant>,
+    /// Per-flow samples for charts.
+    flow_history: FlowHistory,
+    cancel_token: Option<tokio::sync::watch::Sender<bool>>,
+    loop_handle: Option<tokio::task::JoinHandle<()>>,
+    tap: Arc<SessionTap>,
+    classifier_hints: Arc<classifier::Hints>,
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut pids = Vec::new();
+impl Session {
+    /// Game server IPs plus the IPs of the game's established TCP sessions.
+    fn session_ips(&self) -> impl Iterator<Item = &String> {
+        self.detected_servers
+            .iter()
+            .filter(|s| s.is_game_server)
+            .map(|s| &s.ip)
+            .chain(self.tcp_session_ips.keys())
+    }
 
-    for line in stdout.lines() {
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() >= 2 {
-            let pid_str = parts[1].trim_matches('"');
-            if let Ok(pid) = pid_str.parse::<u32>() {
-                pids.push(pid);
-            }
+    /// Cancels the loop and ends any recording; the results stay until the id is reused.
+    fn stop(&mut self) {
+        if let Some(cancel_tx) = self.cancel_token.take() {
+            let _ = cancel_tx.send(true);
+        }
+        self.is_monitoring = false;
+        self.loop_handle = None;
+        if let Some(recording) = self.tap.recording.lock().unwrap().as_mut() {
+            recording.finish(StopReason::Stopped);
         }
     }
+}
 
-    pids
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorSessionInfo {
+    pub id: String,
+    pub label: String,
+    pub is_monitoring: bool,
+    pub replay: bool,
+    pub server_count: usize,
 }
 
-fn find_game_process_pid(process_name: &str) -> Option<u32> {
-    let pids = get_all_process_ids(process_name);
+/// Monitoring sessions by id. They share the live capture and the ping and reverse DNS
+/// lookups, so an address several sessions talk to is probed once.
+#[derive(Default)]
+pub struct MonitorState {
+    sessions: BTreeMap<String, Session>,
+    capture: LiveCapture,
+    ping_cache: Arc<Mutex<HashMap<String, PingEntry>>>,
+    /// Reverse DNS names of remote peers, looked up only when a profile matches hostnames.
+    hostname_cache: Arc<Mutex<HashMap<IpAddr, Option<String>>>>,
+    /// Subscribers to per-tick diffs over all sessions; outlives sessions.
+    feed: LiveFeed<DetectedServer>,
+    /// Automatic routing, shared so sessions never route the same address twice.
+    auto_router: Arc<Mutex<AutoRouter>>,
+}
 
-    if pids.is_empty() {
-        tracing::debug!(process = %process_name, "no matching processes");
-        return None;
+impl MonitorState {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    tracing::debug!(count = pids.len(), process = %process_name, pids = ?pids, "process candidates");
+    fn is_running(&self, id: &str) -> bool {
+        self.sessions.get(id).is_some_and(|s| s.is_monitoring)
+    }
 
-    if pids.len() == 1 {
-        tracing::debug!(pid = pids[0], "single process selected");
-        return Some(pids[0]);
+    fn active_session(&self, id: &str) -> Result<&Session, String> {
+        self.sessions
+            .get(id)
+            .filter(|s| s.is_monitoring)
+            .ok_or_else(|| to_cmd_err(AppError::NoActiveMonitoring))
     }
 
-    tracing::debug!("multiple processes, scoring by UDP ports");
+    /// Session `id`, or every session when `id` is `None`.
+    fn selected<'a>(&'a self, id: Option<&'a str>) -> impl Iterator<Item = &'a Session> {
+        self.sessions
+            .iter()
+            .filter(move |(session_id, _)| id.is_none_or(|id| id == session_id.as_str()))
+            .map(|(_, session)| session)
+    }
 
-    let mut best_pid = None;
-    let mut max_connections = 0;
+    fn servers(&self, id: Option<&str>) -> Vec<DetectedServer> {
+        self.selected(id)
+            .flat_map(|s| s.detected_servers.iter().cloned())
+            .collect()
+    }
 
-    for &pid in &pids {
-        let udp_count = get_udp_ports(pid).len();
-        let total = udp_count;
+    fn session_ips(&self, id: Option<&str>) -> BTreeSet<String> {
+        self.selected(id)
+            .flat_map(Session::session_ips)
+            .cloned()
+            .collect()
+    }
 
-        tracing::debug!(pid, udp_ports = udp_count, "pid udp port count");
+    fn publish(&mut self) {
+        if self.feed.is_empty() {
+            return;
+        }
+        let (servers, session_ips) = (self.servers(None), self.session_ips(None));
+        self.feed.publish(&servers, &session_ips, Instant::now());
+    }
 
-        if total > max_connections {
-            max_connections = total;
-            best_pid = Some(pid);
+    /// Replaces session `id`, which must not be running.
+    fn begin_session(
+        &mut self,
+        id: &str,
+        label: String,
+        replay: bool,
+        hints: classifier::Hints,
+        cancel_tx: tokio::sync::watch::Sender<bool>,
+    ) -> &mut Session {
+        // Pings and names are kept while anything is watched, and start over after.
+        if !self.sessions.values().any(|s| s.is_monitoring) {
+            self.ping_cache.lock().unwrap().clear();
+            self.hostname_cache.lock().unwrap().clear();
         }
+
+        let session = Session {
+            label,
+            is_monitoring: true,
+            replay,
+            detected_servers: Vec::new(),
+            tcp_session_ips: HashMap::new(),
+            flow_history: FlowHistory::default(),
+            cancel_token: Some(cancel_tx),
+            loop_handle: None,
+            tap: Arc::new(SessionTap::default()),
+            classifier_hints: Arc::new(hints),
+        };
+        self.sessions.insert(id.to_string(), session);
+        self.sessions.get_mut(id).unwrap()
     }
+}
 
-    let selected_pid = best_pid.unwrap_or(pids[0]);
+/// What a session watches: a running game, or a recorded capture replayed through the
+/// same detection pipeline.
+enum MonitorTarget {
+    /// Any of the profile's executables; `game` names the session in the history.
+    Process {
+        game: String,
+        executables: Vec<String>,
+        route_rules: Vec<RouteRule>,
+    },
+    Replay {
+        source: Box<dyn PacketSource>,
+        local_ports: GamePorts,
+        speed: f64,
+    },
+}
 
-    if max_connections > 0 {
-        tracing::debug!(
-            selected_pid,
-            max_connections,
-            "selected pid by connection count"
-        );
-    } else {
-        tracing::debug!(selected_pid, "no connections yet, defaulting to first pid");
+fn ip_to_string(ip: u32) -> String {
+    let bytes = ip.to_ne_bytes();
+    format!("{}.{}.{}.{}", bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+fn port_to_host(port: u32) -> u16 {
+    u16::from_be(port as u16)
+}
+
+/// The pid to report for the game: the matched process with the most UDP sockets, since
+/// the client rather than its launcher talks to game servers.
+fn primary_pid(game: &GameProcesses) -> u32 {
+    if let [pid] = game.matched[..] {
+        return pid;
     }
+    let best = game
+        .matched
+        .iter()
+        .map(|pid| (*pid, get_udp_ports(&HashSet::from([*pid])).len()))
+        .max_by_key(|(pid, ports)| (*ports, std::cmp::Reverse(*pid)));
+    tracing::debug!(matched = ?game.matched, ?best, "selected pid by udp port count");
+    best.map(|(pid, _)| pid).unwrap_or_default()
+}
 
-    Some(selected_pid)
+/// Profile `profile_id` from the bundled set and `<app data>/profiles/`.
+fn load_profile(app: &tauri::AppHandle, profile_id: &str) -> Result<GameProfile, String> {
+    profiles::load(user_profile_dir(app).as_deref())
+        .into_iter()
+        .find(|p| p.id == profile_id)
+        .ok_or_else(|| to_cmd_err(AppError::UnknownProfile(profile_id.to_string())))
 }
 
-fn get_udp_ports(pid: u32) -> HashSet<u16> {
-    let mut ports = HashSet::new();
+pub fn user_profile_dir(app: &tauri::AppHandle) -> Option<PathBuf> {
+    use tauri::Manager;
 
-    unsafe {
-        let mut size: u32 = 0;
-        let _ = GetExtendedUdpTable(
-            None,
+    app.path().app_data_dir().ok().map(|dir| dir.join("profiles"))
+}
+
+const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+
+/// Runs a `GetExtended*Table` query, growing the buffer until the table fits.
+fn extended_table<F>(query: F) -> Option<Vec<u8>>
+where
+    F: Fn(Option<*mut std::ffi::c_void>, &mut u32) -> u32,
+{
+    let mut size: u32 = 0;
+    let mut buffer: Vec<u8> = Vec::new();
+
+    loop {
+        let result = query(
+            if buffer.is_empty() { None } else { Some(buffer.as_mut_ptr() as *mut _) },
             &mut size,
-            false,
-            AF_INET.0 as u32,
-            UDP_TABLE_OWNER_PID,
-            0,
         );
 
-        if size == 0 {
-            return ports;
+        if result == ERROR_INSUFFICIENT_BUFFER || buffer.is_empty() {
+            if size == 0 { return None; }
+            buffer.resize(size as usize, 0);
+            continue;
         }
 
-        let mut buffer = vec![0u8; size as usize];
-        let result = GetExtendedUdpTable(
-            Some(buffer.as_mut_ptr() as *mut _),
-            &mut size,
-            false,
-            AF_INET.0 as u32,
-            UDP_TABLE_OWNER_PID,
-            0,
-        );
+        return (result == 0).then_some(buffer);
+    }
+}
 
-        if result == 0 {
+/// Local UDP ports the processes have bound, over IPv4 and IPv6.
+fn get_udp_ports(pids: &HashSet<u32>) -> HashSet<u16> {
+    let mut ports = HashSet::new();
+
+    unsafe {
+        let query = |family: u32| {
+            extended_table(|buffer, size| {
+                GetExtendedUdpTable(buffer, size, false, family, UDP_TABLE_OWNER_PID, 0)
+            })
+        };
+
+        if let Some(buffer) = query(AF_INET.0 as u32) {
             let table = &*(buffer.as_ptr() as *const MIB_UDPTABLE_OWNER_PID);
             let entries =
                 std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
 
             for entry in entries {
-                if entry.dwOwningPid == pid {
+                if pids.contains(&entry.dwOwningPid) {
+                    ports.insert(port_to_host(entry.dwLocalPort));
+                }
+            }
+        }
+
+        if let Some(buffer) = query(AF_INET6.0 as u32) {
+            let table = &*(buffer.as_ptr() as *const MIB_UDP6TABLE_OWNER_PID);
+            let entries =
+                std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
+
+            for entry in entries {
+                if pids.contains(&entry.dwOwningPid) {
                     ports.insert(port_to_host(entry.dwLocalPort));
                 }
             }
@@ -337,254 +621,325 @@ fn get_udp_ports(pid: u32) -> HashSet<u16> {
 }
 
 const TCP_IP_TIMEOUT_SECS: u64 = 120;
-const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
 
-fn get_tcp_remote_ips(pid: u32) -> HashSet<String> {
+/// Local ports and public remote IPs of the processes' established TCP connections, over
+/// IPv4 and IPv6.
+fn get_tcp_sessions(pids: &HashSet<u32>) -> (HashSet<u16>, HashSet<String>) {
+    let mut ports = HashSet::new();
     let mut ips = HashSet::new();
+    let established = MIB_TCP_STATE_ESTAB.0 as u32;
 
     unsafe {
-        let mut size: u32 = 0;
-        let mut buffer: Vec<u8> = Vec::new();
-
-        loop {
-            let result = GetExtendedTcpTable(
-                if buffer.is_empty() { None } else { Some(buffer.as_mut_ptr() as *mut _) },
-                &mut size,
-                false,
-                AF_INET.0 as u32,
-                TCP_TABLE_OWNER_PID_ALL,
-                0,
-            );
-
-            if result == ERROR_INSUFFICIENT_BUFFER || buffer.is_empty() {
-                if size == 0 { return ips; }
-                buffer.resize(size as usize, 0);
-                continue;
-            }
-
-            if result != 0 { return ips; }
+        let query = |family: u32| {
+            extended_table(|buffer, size| {
+                GetExtendedTcpTable(buffer, size, false, family, TCP_TABLE_OWNER_PID_ALL, 0)
+            })
+        };
 
+        if let Some(buffer) = query(AF_INET.0 as u32) {
             let table = &*(buffer.as_ptr() as *const MIB_TCPTABLE_OWNER_PID);
             let entries =
                 std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
 
             for entry in entries {
-                if entry.dwOwningPid == pid && entry.dwState == MIB_TCP_STATE_ESTAB.0 as u32 {
+                if pids.contains(&entry.dwOwningPid) && entry.dwState == established {
                     let remote_ip = ip_to_string(entry.dwRemoteAddr);
-                    if is_public_ip(&remote_ip) {
+                    if netaddr::is_public_str(&remote_ip) {
+                        ports.insert(port_to_host(entry.dwLocalPort));
                         ips.insert(remote_ip);
                     }
                 }
             }
-            break;
         }
-    }
-    ips
-}
 
-#[derive(Deserialize)]
-struct GeoIpResponse {
-    status: String,
-    country: Option<String>,
-    city: Option<String>,
-}
-
-async fn resolve_ip_location(ip: &str) -> Option<String> {
-    if !is_public_ip(ip) {
-        return None;
-    }
+        if let Some(buffer) = query(AF_INET6.0 as u32) {
+            let table = &*(buffer.as_ptr() as *const MIB_TCP6TABLE_OWNER_PID);
+            let entries =
+                std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
 
-    let url = format!("{}{}", GEOIP_API_URL, ip);
-    match reqwest::get(&url).await {
-        Ok(resp) => {
-            if let Ok(json) = resp.json::<GeoIpResponse>().await {
-                if json.status == "success" {
-                    let country = json.country.unwrap_or_default();
-                    let city = json.city.unwrap_or_default();
-                    if !country.is_empty() {
-                        return Some(format!("{}, {}", city, country));
+            for entry in entries {
+                if pids.contains(&entry.dwOwningPid) && entry.dwState == established {
+                    // Dual-stack sockets list IPv4 peers as ::ffff:a.b.c.d.
+                    let remote_ip = IpAddr::V6(Ipv6Addr::from(entry.ucRemoteAddr)).to_canonical();
+                    if netaddr::is_public(remote_ip) {
+                        ports.insert(port_to_host(entry.dwLocalPort));
+                        ips.insert(remote_ip.to_string());
                     }
                 }
             }
         }
-        Err(e) => tracing::warn!(error = %e, "geoip lookup failed"),
     }
-    None
+    (ports, ips)
 }
 
-fn start_sniffer(
-    bind_ip: Ipv4Addr,
-    interesting_ports: Arc<Mutex<HashSet<u16>>>,
-    udp_cache: Arc<Mutex<HashMap<String, TrafficStats>>>,
-    cancel_rx: tokio::sync::watch::Receiver<bool>,
-) {
-    tracing::debug!(%bind_ip, "sniffer starting");
-
-    const RCVALL_ON: u32 = 1;
+/// Names of the interfaces in `counts`, most packets first; unknown ones by index.
+fn interface_names(counts: &BTreeMap<u32, u64>, known: &BTreeMap<u32, Interface>) -> Vec<String> {
+    let mut by_packets: Vec<(&u32, &u64)> = counts.iter().collect();
+    by_packets.sort_by(|a, b| b.1.cmp(a.1));
+    by_packets
+        .into_iter()
+        .map(|(index, _)| known.get(index).map_or_else(|| format!("#{index}"), |i| i.name.clone()))
+        .collect()
+}
 
-    unsafe {
-        let mut wsa_data: WSADATA = mem::zeroed();
-        if WSAStartup(0x0202, &mut wsa_data) != 0 {
-            tracing::warn!("winsock init failed");
-            return;
+/// Credits a segment to the remote endpoint when one side is a port the game owns, and
+/// counts it against the interface it was captured on. Returns whether the segment belongs
+/// to the game.
+fn record_segment(
+    segment: &dissector::Segment,
+    interface: Option<u32>,
+    interesting_ports: &Mutex<GamePorts>,
+    flow_cache: &Mutex<HashMap<FlowKey, FlowStats>>,
+) -> bool {
+    let game_ports = interesting_ports.lock().unwrap();
+    let ports = game_ports.get(segment.transport);
+    let sent = ports.contains(&segment.src.port());
+    let owned = sent || ports.contains(&segment.dst.port());
+
+    let remote = if sent { segment.dst } else { segment.src };
+    if owned && netaddr::is_public(remote.ip()) {
+        let mut cache = flow_cache.lock().unwrap();
+        let stats = cache
+            .entry(FlowKey { transport: segment.transport, remote })
+            .or_default();
+        if sent {
+            stats.record_sent(segment.payload_len);
+        } else {
+            stats.record_recv(segment.payload_len);
         }
-
-        let sock = match socket(AF_INET.0 as i32, SOCK_RAW, IPPROTO_IP.0) {
-            Ok(s) => s,
-            Err(e) => {
-                tracing::warn!(error = %e, "raw socket create failed");
-                return;
-            }
-        };
-
-        let addr = SOCKADDR_IN {
-            sin_family: AF_INET,
-            sin_port: 0,
-            sin_addr: windows::Win32::Networking::WinSock::IN_ADDR {
-                S_un: windows::Win32::Networking::WinSock::IN_ADDR_0 {
-                    S_addr: u32::from_ne_bytes(bind_ip.octets()),
-                },
-            },
-            sin_zero: [0; 8],
-        };
-
-        if bind(
-            sock,
-            &addr as *const _ as *const SOCKADDR,
-            std::mem::size_of::<SOCKADDR_IN>() as i32,
-        ) != 0
-        {
-            tracing::warn!(
-                code = windows::Win32::Networking::WinSock::WSAGetLastError().0,
-                "raw socket bind failed"
-            );
-            let _ = closesocket(sock);
-            return;
+        if let Some(interface) = interface {
+            *stats.interfaces.entry(interface).or_default() += 1;
         }
+    }
 
-        let mut one: u32 = RCVALL_ON;
+    owned
+}
 
-        if ioctlsocket(sock, SIO_RCVALL as i32, &mut one) != 0 {
-            tracing::warn!(
-                code = windows::Win32::Networking::WinSock::WSAGetLastError().0,
-                "SIO_RCVALL failed"
-            );
-            let _ = closesocket(sock);
+/// Reads `open`'s source until cancelled. Packets the source does not tag are credited to
+/// `interface`.
+fn run_sniffer<F>(
+    open: F,
+    interface: Option<u32>,
+    taps: Taps,
+    cancel_rx: tokio::sync::watch::Receiver<bool>,
+) where
+    F: FnOnce() -> io::Result<Box<dyn PacketSource>>,
+{
+    let mut source = match open() {
+        Ok(source) => source,
+        Err(e) => {
+            tracing::warn!(error = %e, "packet capture unavailable");
             return;
         }
+    };
 
-        let timeout_ms = SNIFFER_RECV_TIMEOUT_MS;
-        if setsockopt(
-            sock,
-            SOL_SOCKET as i32,
-            SO_RCVTIMEO as i32,
-            Some(std::slice::from_raw_parts(
-                &timeout_ms as *const u32 as *const u8,
-                std::mem::size_of::<u32>(),
-            )),
-        ) != 0
-        {
-            tracing::warn!(
-                code = windows::Win32::Networking::WinSock::WSAGetLastError().0,
-                "SO_RCVTIMEO failed; cancel signal may be delayed"
-            );
-        }
-
-        tracing::debug!("sniffer listening");
-
-        let mut buffer = [0u8; 65536];
-
-        loop {
-            if *cancel_rx.borrow() {
-                break;
-            }
-
-            let bytes_read = recv(sock, &mut buffer, SEND_RECV_FLAGS(0));
+    tracing::debug!("sniffer listening");
 
-            if bytes_read > 0 {
-                let len = bytes_read as usize;
+    loop {
+        if *cancel_rx.borrow() {
+            break;
+        }
 
-                if len < mem::size_of::<Ipv4Header>() {
+        match source.next_packet() {
+            Ok(Next::Packet(packet, tagged)) => {
+                let Ok(segment) = dissector::dissect(packet) else {
                     continue;
-                }
-
-                let ip_header = &*(buffer.as_ptr() as *const Ipv4Header);
-                let ip_header_len = (ip_header.version_ihl & 0x0F) as usize * 4;
-
-                if ip_header.protocol == 17 {
-                    if len < ip_header_len + mem::size_of::<UdpHeader>() {
-                        continue;
-                    }
-
-                    let udp_header = &*(buffer.as_ptr().add(ip_header_len) as *const UdpHeader);
-                    let src_port = u16::from_be(udp_header.src_port);
-                    let dst_port = u16::from_be(udp_header.dst_port);
-                    let payload_len = len - ip_header_len - mem::size_of::<UdpHeader>();
-
-                    let src_ip = ip_to_string(ip_header.src_addr);
-                    let dst_ip = ip_to_string(ip_header.dst_addr);
-
-                    let ports = interesting_ports.lock().unwrap();
-
-                    if ports.contains(&src_port) {
-                        if is_public_ip(&dst_ip) {
-                            let key = format!("{}:{}", dst_ip, dst_port);
-                            let mut cache = udp_cache.lock().unwrap();
-                            let entry = cache.entry(key).or_default();
-                            entry.last_seen = Instant::now();
-                            entry.total_sent += payload_len as u64;
-                        }
-                    } else if ports.contains(&dst_port) {
-                        if is_public_ip(&src_ip) {
-                            let key = format!("{}:{}", src_ip, src_port);
-                            let mut cache = udp_cache.lock().unwrap();
-                            let entry = cache.entry(key).or_default();
-                            entry.last_seen = Instant::now();
-                            entry.total_recv += payload_len as u64;
+                };
+                let interface = tagged.or(interface);
+                for tap in taps.lock().unwrap().values() {
+                    if record_segment(&segment, interface, &tap.ports, &tap.flow_cache) {
+                        if let Some(recording) = tap.recording.lock().unwrap().as_mut() {
+                            recording.write(packet);
                         }
                     }
                 }
             }
+            Ok(Next::Timeout) => {}
+            Ok(Next::Finished) => {
+                tracing::info!("capture replay finished");
+                break;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "packet capture failed");
+                break;
+            }
         }
+    }
+}
+
+/// Starts a sniffer thread feeding `taps`; `open` runs on that thread so socket setup never
+/// blocks the caller.
+fn spawn_sniffer<F>(
+    open: F,
+    interface: Option<u32>,
+    taps: Taps,
+    cancel_rx: tokio::sync::watch::Receiver<bool>,
+) -> std::thread::JoinHandle<()>
+where
+    F: FnOnce() -> io::Result<Box<dyn PacketSource>> + Send + 'static,
+{
+    std::thread::spawn(move || run_sniffer(open, interface, taps, cancel_rx))
+}
+
+/// Local ports in a capture that talk to public addresses, for replays where the caller
+/// did not say which ports the game used.
+fn infer_local_ports(path: &Path) -> io::Result<GamePorts> {
+    let mut reader = PcapReader::new(io::BufReader::new(std::fs::File::open(path)?))?;
+    let mut ports = GamePorts::default();
 
-        let _ = closesocket(sock);
+    while let Some(frame) = reader.next_frame()? {
+        let Some(segment) = frame.ip_payload().and_then(|p| dissector::dissect(p).ok()) else {
+            continue;
+        };
+        let set = match segment.transport {
+            Transport::Udp => &mut ports.udp,
+            Transport::Tcp => &mut ports.tcp,
+        };
+        let src_public = netaddr::is_public(segment.src.ip());
+        let dst_public = netaddr::is_public(segment.dst.ip());
+        if !src_public && dst_public {
+            set.insert(segment.src.port());
+        } else if src_public && !dst_public {
+            set.insert(segment.dst.port());
+        }
     }
+
+    Ok(ports)
 }
 
+/// Routes this tick's game servers that the profile's rules select, one at a time since
+/// each addition rewrites the peer's AllowedIPs.
+fn route_matching_servers(
+    app: &tauri::AppHandle,
+    session_id: &str,
+    detected: &[DetectedServer],
+    rules: &[RouteRule],
+    auto_router: &Arc<Mutex<AutoRouter>>,
+) {
+    let candidates: Vec<Candidate> = detected
+        .iter()
+        .filter(|s| s.is_game_server)
+        .filter_map(|s| {
+            Some(Candidate {
+                ip: s.ip.parse().ok()?,
+                port: s.port,
+                confidence: s.confidence,
+                country_code: s.geo.as_ref().and_then(|g| g.country_code.clone()),
+            })
+        })
+        .collect();
+    let due = auto_router.lock().unwrap().due(
+        session_id,
+        &candidates,
+        rules,
+        crate::vpn::routes_cover,
+        Instant::now(),
+    );
+    if due.is_empty() {
+        return;
+    }
+
+    let (app, session_id, auto_router) = (app.clone(), session_id.to_string(), auto_router.clone());
+    tokio::spawn(async move {
+        for due in due {
+            let result =
+                crate::vpn::append_allowed_ip_and_route(&app, &due.server.ip.to_string()).await;
+            let decision =
+                auto_router.lock().unwrap().finish(&session_id, &due, result, Instant::now());
+            tracing::info!(
+                session = %session_id, ip = %decision.ip, rule = %decision.rule,
+                outcome = ?decision.outcome, detail = %decision.detail, "automatic route"
+            );
+        }
+    });
+}
 
-#[tracing::instrument(level = "debug", skip(cancel_rx, state), fields(process_name = %process_name))]
+#[tracing::instrument(level = "debug", skip(app, target, cancel_rx, state, tap))]
 async fn monitoring_loop(
-    process_name: String,
+    app: tauri::AppHandle,
+    id: String,
+    target: MonitorTarget,
     mut cancel_rx: tokio::sync::watch::Receiver<bool>,
     state: Arc<AsyncMonitorMutex<MonitorState>>,
+    tap: Arc<SessionTap>,
 ) {
+    use tauri::{Emitter, Manager};
+
     let mut interval = tokio::time::interval(Duration::from_secs(MONITORING_INTERVAL_SECS));
 
-    let mut prev_stats: HashMap<String, (u64, u64)> = HashMap::new();
+    // Byte, packet, and tunnel and other interface packet totals at the previous tick.
+    let mut prev_stats: HashMap<FlowKey, [u64; 6]> = HashMap::new();
+    // Whether the tunnel was meant to carry each flow at the previous tick, and its status.
+    let mut tunnel_states: HashMap<FlowKey, (bool, Option<TunnelStatus>)> = HashMap::new();
+    let mut peer_counters: Option<CounterSnapshot> = None;
     let mut last_check = Instant::now();
-    let mut sniffer_started = false;
-    let mut sniffer_warned = false;
-
-    if let Some(ip) = get_local_ip() {
-        tracing::debug!(%ip, "local interface for sniffer");
-
-        let (interesting_ports, udp_cache) = {
-            let guard = state.lock().await;
-            (
-                guard.interesting_ports.clone(),
-                guard.udp_detected_cache.clone(),
-            )
-        };
-        let sniffer_cancel = cancel_rx.clone();
+    // Replays read their own source; live sessions share the capture in `MonitorState`.
+    let mut replay_handle: Option<std::thread::JoinHandle<()>> = None;
+
+    // Live sessions are kept in the history; replays are not.
+    let mut recorder: Option<SessionRecorder> = None;
+    // The session's history row, and when its totals were last written.
+    let mut history_row: Option<(Arc<SessionHistory>, i64)> = None;
+    let mut last_flush = Instant::now();
+
+    // Replayed servers are never routed.
+    let mut route_rules = Vec::new();
+
+    // A replay's ports are fixed up front and its clock runs `speed` times faster than ours.
+    let (executables, time_scale) = match target {
+        MonitorTarget::Process {
+            game,
+            executables,
+            route_rules: rules,
+        } => {
+            let started = SessionRecorder::new(&game, history::now_utc());
+            if let Some(db) = app.try_state::<Arc<SessionHistory>>().map(|db| db.inner().clone()) {
+                let (db_clone, row) = (db.clone(), started.clone());
+                if let Ok(Some(row_id)) = tokio::task::spawn_blocking(move || db_clone.start(&row)).await {
+                    history_row = Some((db, row_id));
+                }
+            }
+            recorder = Some(started);
+            route_rules = rules;
+            let mut st = state.lock().await;
+            st.capture.attach(&id, tap.clone());
+            st.capture.ensure_running();
+            (Some(executables), 1.0)
+        }
+        MonitorTarget::Replay {
+            source,
+            local_ports,
+            speed,
+        } => {
+            *tap.ports.lock().unwrap() = local_ports;
+            let taps: Taps = Arc::new(Mutex::new(HashMap::from([(id.clone(), tap.clone())])));
+            replay_handle = Some(spawn_sniffer(move || Ok(source), None, taps, cancel_rx.clone()));
+            (None, speed)
+        }
+    };
+    let flow_cache_timeout = Duration::from_secs(FLOW_CACHE_TIMEOUT_SECS).div_f64(time_scale);
+
+    let (hints, ping_cache_arc, hostname_cache_arc, auto_router, known_interfaces) = {
+        let st = state.lock().await;
+        (
+            st.sessions.get(&id).map(|s| s.classifier_hints.clone()).unwrap_or_default(),
+            st.ping_cache.clone(),
+            st.hostname_cache.clone(),
+            st.auto_router.clone(),
+            st.capture.interfaces.clone(),
+        )
+    };
 
-        std::thread::spawn(move || {
-            start_sniffer(ip, interesting_ports, udp_cache, sniffer_cancel);
-        });
-        sniffer_started = true;
+    // Reverse DNS is only worth its queries when the profile has hostname patterns to match.
+    let resolver = if hints.hostname_patterns.is_empty() {
+        None
     } else {
-        tracing::warn!("could not determine local IP; UDP sniffing will retry in background");
-        sniffer_warned = true;
-    }
+        crate::hop_probe::create_resolver().map(Arc::new)
+    };
+
+    let geo_resolver = app.try_state::<Arc<GeoResolver>>().map(|s| s.inner().clone());
+    let cloud_labeler = app.try_state::<Arc<CloudLabeler>>().map(|s| s.inner().clone());
+    let mut process_finder = ProcessFinder::default();
 
     loop {
         tokio::select! {
@@ -593,146 +948,258 @@ async fn monitoring_loop(
                     break;
                 }
 
-                if !sniffer_started {
-                    if let Some(ip) = get_local_ip() {
-                        tracing::info!(%ip, "local interface detected later; starting UDP sniffer");
-
-                        let (interesting_ports, udp_cache) = {
-                            let guard = state.lock().await;
-                            (
-                                guard.interesting_ports.clone(),
-                                guard.udp_detected_cache.clone(),
-                            )
-                        };
-                        let sniffer_cancel = cancel_rx.clone();
-
-                        std::thread::spawn(move || {
-                            start_sniffer(ip, interesting_ports, udp_cache, sniffer_cancel);
-                        });
-                        sniffer_started = true;
-                        sniffer_warned = false;
-                    } else if !sniffer_warned {
-                        tracing::warn!("still unable to determine local IP; UDP sniffer not started yet");
-                        sniffer_warned = true;
-                    }
+                if executables.is_some() {
+                    state.lock().await.capture.ensure_running();
                 }
 
+                let replay_finished = replay_handle.as_ref().is_some_and(|h| h.is_finished());
+
                 let now = Instant::now();
-                let time_diff = now.duration_since(last_check).as_secs_f64();
+                let time_diff = now.duration_since(last_check).as_secs_f64() * time_scale;
                 last_check = now;
 
-                if let Some(pid) = find_game_process_pid(&process_name) {
-                    let udp_ports = get_udp_ports(pid);
-                    let tcp_ips = get_tcp_remote_ips(pid);
-
-                    let (interesting_ports_arc, udp_detected_cache_arc, geo_cache_arc, ping_cache_arc) = {
-                        let mut guard = state.lock().await;
-                        let now = Instant::now();
-                        for ip in &tcp_ips {
-                            guard.tcp_session_ips.insert(ip.clone(), now);
+                let observed = match &executables {
+                    Some(executables) => process_finder.find(executables).map(|game| {
+                        let pid = primary_pid(&game);
+                        let pids: HashSet<u32> = game.tree.iter().copied().collect();
+                        let (tcp_ports, tcp_ips) = get_tcp_sessions(&pids);
+                        let ports = GamePorts { udp: get_udp_ports(&pids), tcp: tcp_ports };
+                        (Some((game.name, pid)), Some(ports), tcp_ips)
+                    }),
+                    None => Some((None, None, HashSet::new())),
+                };
+
+                {
+                    let mut recording = tap.recording.lock().unwrap();
+                    if let Some(recording) = recording.as_mut() {
+                        if let Some((Some((name, pid)), _, _)) = &observed {
+                            recording.set_process(name.as_str(), *pid);
                         }
-                        guard.tcp_session_ips.retain(|_, last_seen| {
-                            last_seen.elapsed() < Duration::from_secs(TCP_IP_TIMEOUT_SECS)
-                        });
-                        (
-                            guard.interesting_ports.clone(),
-                            guard.udp_detected_cache.clone(),
-                            guard.geo_cache.clone(),
-                            guard.ping_cache.clone(),
-                        )
-                    };
+                        recording.enforce_limits();
+                    }
+                }
 
-                    {
-                        let mut ports_lock = interesting_ports_arc.lock().unwrap();
-                        ports_lock.clear();
-                        ports_lock.extend(udp_ports.iter());
+                if let Some((_, game_ports, tcp_ips)) = observed {
+                    if let Some(game_ports) = game_ports {
+                        *tap.ports.lock().unwrap() = game_ports;
+                    }
+
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.set_vpn_profile(crate::usage::active_profile(&app).await);
                     }
+                    let tick_at = history::now_utc();
+
+                    // The peer's counters confirm that what the tunnel adapter saw went through it.
+                    let peer_moved = if executables.is_some() && crate::vpn::active_config().is_some() {
+                        let counters = crate::usage::peer_counters(&app).await;
+                        let moved = match (&peer_counters, &counters) {
+                            (Some(before), Some(now))
+                                if before.public_key == now.public_key && before.listen_port == now.listen_port =>
+                            {
+                                Some(now.rx_bytes > before.rx_bytes || now.tx_bytes > before.tx_bytes)
+                            }
+                            _ => None,
+                        };
+                        peer_counters = counters;
+                        moved
+                    } else {
+                        peer_counters = None;
+                        None
+                    };
+                    let known = known_interfaces.lock().unwrap().clone();
+                    let tunnel_interfaces: HashSet<u32> =
+                        known.values().filter(|i| i.is_tunnel()).map(|i| i.index).collect();
 
                     let mut detected = Vec::new();
+                    let mut samples = Vec::new();
+                    let mut leaks = Vec::new();
+                    let sampled_at = chrono::Utc::now().timestamp_millis() as u64;
 
                     {
-                        let mut cache_lock = udp_detected_cache_arc.lock().unwrap();
-                        cache_lock.retain(|_, stats| stats.last_seen.elapsed() < Duration::from_secs(UDP_CACHE_TIMEOUT_SECS));
+                        let mut cache_lock = tap.flow_cache.lock().unwrap();
+                        cache_lock.retain(|_, stats| stats.last_seen.elapsed() < flow_cache_timeout);
 
                         for (key, stats) in cache_lock.iter() {
-                            let parts: Vec<&str> = key.split(':').collect();
-                            if parts.len() == 2 {
-                                let ip = parts[0].to_string();
-                                let port = parts[1].parse().unwrap_or(0);
+                            let ip = key.remote.ip().to_string();
+                            let port = key.remote.port();
+
+                            let paths = PathCounts::split(&stats.interfaces, &tunnel_interfaces);
+                            let totals = [
+                                stats.total_sent, stats.total_recv, stats.packets_sent, stats.packets_recv,
+                                paths.tunnel, paths.other,
+                            ];
+                            let prev = prev_stats.insert(*key, totals).unwrap_or_default();
+                            // A total below its previous value means the flow expired and came back.
+                            let [sent_diff, recv_diff, sent_packets, recv_packets, tunnel_packets, other_packets] =
+                                std::array::from_fn(|i| if totals[i] >= prev[i] { totals[i] - prev[i] } else { totals[i] });
+
+                            // Packets sent before a route was added are no leak; a quiet tick keeps the last status.
+                            let seen = PathCounts { tunnel: tunnel_packets, other: other_packets };
+                            let expected = tunnel_check::expected_in_tunnel(key.remote.ip());
+                            let (was_expected, previous) = tunnel_states.get(key).copied().unwrap_or_default();
+                            let tunnel = tunnel_check::status(expected && was_expected, seen, peer_moved).or(previous);
+                            tunnel_states.insert(*key, (expected, tunnel));
+
+                            let send_rate = (sent_diff as f64 / time_diff) as u64;
+                            let recv_rate = (recv_diff as f64 / time_diff) as u64;
+
+                            let hostname = resolver.as_ref().and_then(|resolver| {
+                                let mut names = hostname_cache_arc.lock().unwrap();
+                                match names.get(&key.remote.ip()) {
+                                    Some(name) => name.clone(),
+                                    None => {
+                                        names.insert(key.remote.ip(), None);
+                                        let (names_arc, resolver, remote_ip) =
+                                            (hostname_cache_arc.clone(), resolver.clone(), key.remote.ip());
+                                        tokio::spawn(async move {
+                                            let name = tokio::time::timeout(
+                                                PTR_LOOKUP_TIMEOUT,
+                                                crate::hop_probe::reverse_resolve_ip(remote_ip, &resolver),
+                                            )
+                                            .await
+                                            .ok()
+                                            .flatten();
+                                            names_arc.lock().unwrap().insert(remote_ip, name);
+                                        });
+                                        None
+                                    }
+                                }
+                            });
 
-                                let (prev_sent, prev_recv) = prev_stats.get(key).unwrap_or(&(0, 0));
+                            let quality = stats.arrivals.quality(time_scale);
+                            let geo = geo_resolver.as_ref().and_then(|r| r.cached(key.remote.ip()));
+                            let cloud = cloud_labeler
+                                .as_ref()
+                                .and_then(|labeler| labeler.label(key.remote.ip(), geo.as_ref()));
+                            let class = classifier::classify(
+                                key.transport,
+                                key.remote,
+                                hostname.as_deref(),
+                                &stats.features(time_scale),
+                                &hints,
+                            );
+
+                            let server = DetectedServer {
+                                session_id: id.clone(),
+                                ip: ip.clone(),
+                                port,
+                                protocol: key.transport.as_str().to_string(),
+                                send_rate,
+                                recv_rate,
+                                country: geo.as_ref().and_then(GeoInfo::label),
+                                geo,
+                                cloud,
+                                ping_ms: {
+                                    let cache = ping_cache_arc.lock().unwrap();
+                                    ping_ms_for_ip(&cache, &ip)
+                                },
+                                detected_at: chrono::Local::now().to_rfc3339(),
+                                category: class.category,
+                                confidence: class.confidence,
+                                is_game_server: class.is_game_server(&hints.thresholds),
+                                jitter_ms: quality.map(|q| q.jitter_ms),
+                                tick_rate_hz: quality.map(|q| q.tick_rate_hz),
+                                loss_pct: quality.map(|q| q.loss_pct),
+                                interfaces: interface_names(&stats.interfaces, &known),
+                                tunnel,
+                            };
+                            if tunnel == Some(TunnelStatus::Leaking) && previous != Some(TunnelStatus::Leaking) {
+                                leaks.push(server.clone());
+                            }
+                            if let Some(recorder) = recorder.as_mut() {
+                                recorder.observe(
+                                    &ServerSample {
+                                        ip: &server.ip,
+                                        port,
+                                        protocol: &server.protocol,
+                                        country: server.country.as_deref(),
+                                        category: server.category.as_str(),
+                                        ping_ms: server.ping_ms,
+                                        bytes_sent: sent_diff,
+                                        bytes_recv: recv_diff,
+                                    },
+                                    &tick_at,
+                                );
+                            }
+                            samples.push((key.transport, key.remote, FlowSample {
+                                at_ms: sampled_at,
+                                send_rate: sent_diff as f64 / time_diff,
+                                recv_rate: recv_diff as f64 / time_diff,
+                                send_pps: sent_packets as f64 / time_diff,
+                                recv_pps: recv_packets as f64 / time_diff,
+                                ping_ms: server.ping_ms.map(|ms| ms as f32),
+                                jitter_ms: server.jitter_ms,
+                            }));
+                            detected.push(server);
+                            tracing::trace!(
+                                %ip, port, protocol = key.transport.as_str(), send_rate, recv_rate,
+                                category = ?class.category, confidence = class.confidence, "endpoint rates"
+                            );
+                        }
 
-                                let sent_diff = if stats.total_sent >= *prev_sent { stats.total_sent - *prev_sent } else { stats.total_sent };
-                                let recv_diff = if stats.total_recv >= *prev_recv { stats.total_recv - *prev_recv } else { stats.total_recv };
+                        prev_stats.retain(|key, _| cache_lock.contains_key(key));
+                        tunnel_states.retain(|key, _| cache_lock.contains_key(key));
+                    }
 
-                                let send_rate = (sent_diff as f64 / time_diff) as u64;
-                                let recv_rate = (recv_diff as f64 / time_diff) as u64;
+                    for server in &leaks {
+                        tracing::warn!(
+                            ip = %server.ip, port = server.port, interfaces = ?server.interfaces,
+                            "server should go through the tunnel but went direct"
+                        );
+                        if let Err(e) = app.emit(tunnel_check::SERVER_LEAKING_EVENT, server) {
+                            tracing::warn!(error = %e, "failed to emit leak event");
+                        }
+                    }
 
-                                prev_stats.insert(key.clone(), (stats.total_sent, stats.total_recv));
+                    if let Some(geo_resolver) = &geo_resolver {
+                        geo_resolver.refresh(detected.iter().filter_map(|s| s.ip.parse::<IpAddr>().ok()));
+                    }
 
-                                detected.push(DetectedServer {
-                                    ip: ip.clone(),
-                                    port,
-                                    protocol: "UDP".to_string(),
-                                    send_rate,
-                                    recv_rate,
-                                    country: {
-                                        geo_cache_arc.lock().unwrap().get(&ip).cloned().flatten()
-                                    },
-                                    ping_ms: {
-                                        let cache = ping_cache_arc.lock().unwrap();
-                                        ping_ms_for_ip(&cache, &ip)
-                                    },
-                                    detected_at: chrono::Local::now().to_rfc3339(),
-                                    is_game_server: true,
-                                });
-                                tracing::trace!(%ip, port, send_rate, recv_rate, "udp endpoint rates");
+                    {
+                        let mut st = state.lock().await;
+                        // Stopping cancels under this lock; a stopped session keeps what it last showed.
+                        if *cancel_rx.borrow() {
+                            break;
+                        }
+                        if let Some(session) = st.sessions.get_mut(&id) {
+                            let now = Instant::now();
+                            for ip in &tcp_ips {
+                                session.tcp_session_ips.insert(ip.clone(), now);
                             }
+                            session.tcp_session_ips.retain(|_, last_seen| {
+                                last_seen.elapsed() < Duration::from_secs(TCP_IP_TIMEOUT_SECS)
+                            });
+                            session.detected_servers = detected.clone();
+                            for (transport, remote, sample) in samples {
+                                session.flow_history.record(transport, remote, sample);
+                            }
+                            session.flow_history.prune(sampled_at);
                         }
+                        st.publish();
                     }
 
-                    let (geo_cache_ref, ping_cache_ref) = {
-                        let mut st = state.lock().await;
-                        st.detected_servers = detected.clone();
-                        (st.geo_cache.clone(), st.ping_cache.clone())
-                    };
+                    if !route_rules.is_empty() && crate::vpn::active_config().is_some() {
+                        route_matching_servers(&app, &id, &detected, &route_rules, &auto_router);
+                    }
 
                     let now_mono = Instant::now();
                     for server in detected {
-                        if !is_public_ip(&server.ip) {
+                        if !netaddr::is_public_str(&server.ip) {
                             continue;
                         }
 
-                        let should_spawn_geo = {
-                            let mut cache = geo_cache_ref.lock().unwrap();
-                            if cache.contains_key(&server.ip) {
-                                false
-                            } else {
-                                cache.insert(server.ip.clone(), None);
-                                true
-                            }
-                        };
-
                         let should_spawn_ping = {
-                            let mut cache = ping_cache_ref.lock().unwrap();
+                            let mut cache = ping_cache_arc.lock().unwrap();
                             try_mark_ping_inflight(&mut cache, &server.ip, now_mono)
                         };
 
-                        if should_spawn_geo {
-                            let cache_clone = geo_cache_ref.clone();
-                            let ip_clone = server.ip.clone();
-                            tokio::spawn(async move {
-                                let loc = resolve_ip_location(&ip_clone).await;
-                                cache_clone.lock().unwrap().insert(ip_clone, loc);
-                            });
-                        }
-
                         if should_spawn_ping {
-                            let cache_clone = ping_cache_ref.clone();
+                            let cache_clone = ping_cache_arc.clone();
                             let ip_clone = server.ip.clone();
 
                             tokio::spawn(async move {
                                 let measured_at = Instant::now();
+                                // ICMP probing is IPv4-only; IPv6 servers report no ping.
                                 let rtt_ms = if let Ok(ip_addr) = ip_clone.parse::<Ipv4Addr>() {
                                     let join = tokio::task::spawn_blocking(move || {
                                         match crate::hop_probe::sync_ping_icmp(ip_addr, 128, 1000) {
@@ -758,7 +1225,26 @@ async fn monitoring_loop(
                         }
                     }
                 } else {
-                    tracing::debug!(process = %process_name, "process not found this tick");
+                    tracing::debug!(executables = ?executables, "process not found this tick");
+                }
+
+                if let (Some(recorder), Some((db, row_id))) = (&recorder, &history_row) {
+                    if last_flush.elapsed() >= HISTORY_FLUSH_INTERVAL {
+                        last_flush = Instant::now();
+                        let (db, row_id, snapshot) = (db.clone(), *row_id, recorder.clone());
+                        let _ = tokio::task::spawn_blocking(move || db.save(row_id, &snapshot)).await;
+                    }
+                }
+
+                if replay_finished {
+                    let mut st = state.lock().await;
+                    if *cancel_rx.borrow() {
+                        break;
+                    }
+                    if let Some(session) = st.sessions.get_mut(&id) {
+                        session.stop();
+                    }
+                    break;
                 }
             }
             _ = cancel_rx.changed() => {
@@ -769,110 +1255,571 @@ async fn monitoring_loop(
         }
     }
 
+    let mut sniffers: Vec<_> = replay_handle.into_iter().collect();
+    if executables.is_some() {
+        sniffers.extend(state.lock().await.capture.detach(&id, &tap));
+    }
+    // The sniffers notice the cancel flag within one receive timeout.
+    for handle in sniffers {
+        let _ = tokio::task::spawn_blocking(move || handle.join()).await;
+    }
+
+    if let (Some(recorder), Some((db, row_id))) = (recorder, history_row) {
+        let _ = tokio::task::spawn_blocking(move || db.finish(row_id, &recorder)).await;
+    }
+
     tracing::debug!("monitoring loop stopped");
 }
 
-#[tracing::instrument(level = "info", skip(state), fields(process_name = %process_name))]
+/// Watches the game described by profile `profile_id`. A bare `process_name` without a
+/// profile still works and classifies with default thresholds. Sessions with different
+/// `session_id`s (default `"default"`) run side by side.
+#[tracing::instrument(level = "info", skip(app, state))]
 #[tauri::command]
 pub async fn start_monitoring(
-    process_name: String,
+    app: tauri::AppHandle,
+    profile_id: Option<String>,
+    process_name: Option<String>,
+    session_id: Option<String>,
     state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
 ) -> Result<String, String> {
+    let profile = match (profile_id, process_name) {
+        (Some(profile_id), _) => load_profile(&app, &profile_id)?,
+        (None, Some(process_name)) => GameProfile::for_executable(&process_name),
+        (None, None) => {
+            return Err(to_cmd_err(AppError::Msg(
+                "a profile id or process name is required".to_string(),
+            )))
+        }
+    };
+    let id = session_id.unwrap_or_else(|| DEFAULT_SESSION_ID.to_string());
+
+    start_session(app, state.inner(), id, profile).await?;
+
+    Ok("Monitoring started successfully".to_string())
+}
+
+/// Starts session `id` watching `profile`'s game, which must be running.
+pub async fn start_session(
+    app: tauri::AppHandle,
+    state: &Arc<AsyncMonitorMutex<MonitorState>>,
+    id: String,
+    profile: GameProfile,
+) -> Result<(), String> {
+    let mut monitor_state = state.lock().await;
+
+    if monitor_state.is_running(&id) {
+        return Err(to_cmd_err(AppError::AlreadyMonitoring));
+    }
+
+    let Some(game) = ProcessFinder::default().find(&profile.executables) else {
+        return Err(to_cmd_err(AppError::ProcessNotFound(profile.executables.join(", "))));
+    };
+    let process_name = game.name.as_str();
+    tracing::info!(session = %id, profile = %profile.id, process = %process_name, pids = ?game.tree, "game process found");
+
+    let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+
+    let session =
+        monitor_state.begin_session(&id, process_name.to_string(), false, profile.hints(), cancel_tx);
+
+    let state_clone = Arc::clone(state);
+    let tap = session.tap.clone();
+
+    // The loop blocks on the state lock first, so it can't observe a missing handle.
+    session.loop_handle = Some(tokio::spawn(async move {
+        let target = MonitorTarget::Process {
+            game: profile.id,
+            executables: profile.executables,
+            route_rules: profile.route_rules,
+        };
+        monitoring_loop(app, id, target, cancel_rx, state_clone, tap).await;
+    }));
+
+    Ok(())
+}
+
+/// Replays a pcap/pcapng capture through the detection pipeline instead of watching a live
+/// game. `speed` scales the recorded pace (default 1.0); `local_ports` are the game's ports
+/// (UDP and TCP alike) and are inferred from the capture when omitted. `profile_id` selects
+/// the classifier hints. The session ends on its own when the capture runs out, leaving the
+/// last results in place.
+#[tracing::instrument(level = "info", skip(app, state))]
+#[tauri::command]
+pub async fn start_monitoring_from_capture(
+    app: tauri::AppHandle,
+    path: String,
+    profile_id: Option<String>,
+    speed: Option<f64>,
+    local_ports: Option<Vec<u16>>,
+    session_id: Option<String>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<String, String> {
+    let hints = match profile_id {
+        Some(profile_id) => load_profile(&app, &profile_id)?.hints(),
+        None => classifier::Hints::default(),
+    };
+    let id = session_id.unwrap_or_else(|| DEFAULT_SESSION_ID.to_string());
+
     let mut monitor_state = state.lock().await;
 
-    if monitor_state.is_monitoring {
+    if monitor_state.is_running(&id) {
         return Err(to_cmd_err(AppError::AlreadyMonitoring));
     }
 
-    if find_game_process_pid(&process_name).is_none() {
-        return Err(to_cmd_err(AppError::ProcessNotFound(process_name.clone())));
+    let speed = speed.unwrap_or(1.0);
+    if !capture::REPLAY_SPEEDS.contains(&speed) {
+        return Err(to_cmd_err(AppError::Msg(format!(
+            "replay speed must be between {} and {}",
+            capture::REPLAY_SPEEDS.start(),
+            capture::REPLAY_SPEEDS.end()
+        ))));
+    }
+
+    let path = PathBuf::from(path);
+    let label = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let opened = tokio::task::spawn_blocking(move || -> io::Result<_> {
+        let local_ports = match local_ports {
+            Some(ports) if !ports.is_empty() => GamePorts {
+                udp: ports.iter().copied().collect(),
+                tcp: ports.into_iter().collect(),
+            },
+            _ => infer_local_ports(&path)?,
+        };
+        let source: Box<dyn PacketSource> = Box::new(capture::open_replay(&path, speed)?);
+        Ok((source, local_ports))
+    })
+    .await
+    .map_err(|e| to_cmd_err(AppError::Msg(e.to_string())))?;
+    let (source, local_ports) = opened
+        .map_err(|e| to_cmd_err(AppError::Msg(format!("cannot replay capture: {}", e))))?;
+
+    if local_ports.udp.is_empty() && local_ports.tcp.is_empty() {
+        return Err(to_cmd_err(AppError::Msg(
+            "no traffic to public addresses found in capture".to_string(),
+        )));
     }
 
     let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
 
-    monitor_state.is_monitoring = true;
-    monitor_state.process_name = Some(process_name.clone());
-    monitor_state.cancel_token = Some(cancel_tx);
-    monitor_state.detected_servers.clear();
-    monitor_state.tcp_session_ips.clear();
-    monitor_state.interesting_ports = Arc::new(Mutex::new(HashSet::new()));
-    monitor_state.udp_detected_cache = Arc::new(Mutex::new(HashMap::new()));
-    monitor_state.geo_cache = Arc::new(Mutex::new(HashMap::new()));
-    monitor_state.ping_cache = Arc::new(Mutex::new(HashMap::new()));
+    let session = monitor_state.begin_session(&id, label, true, hints, cancel_tx);
 
     let state_clone = Arc::clone(&state.inner());
+    let tap = session.tap.clone();
+    let target = MonitorTarget::Replay {
+        source,
+        local_ports,
+        speed,
+    };
+
+    session.loop_handle = Some(tokio::spawn(async move {
+        monitoring_loop(app, id, target, cancel_rx, state_clone, tap).await;
+    }));
 
     drop(monitor_state);
 
-    tokio::spawn(async move {
-        monitoring_loop(process_name, cancel_rx, state_clone).await;
-    });
+    Ok("Capture replay started".to_string())
+}
 
-    Ok("Monitoring started successfully".to_string())
+/// Bundled game profiles plus any in `<app data>/profiles/`.
+#[tracing::instrument(level = "debug", skip(app))]
+#[tauri::command]
+pub async fn list_game_profiles(app: tauri::AppHandle) -> Result<Vec<GameProfile>, String> {
+    let dir = user_profile_dir(&app);
+    tokio::task::spawn_blocking(move || profiles::load(dir.as_deref()))
+        .await
+        .map_err(|e| to_cmd_err(AppError::Msg(e.to_string())))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureInterface {
+    pub index: u32,
+    pub name: String,
+    pub addrs: Vec<String>,
+    pub tunnel: bool,
+    /// A live source is open on it.
+    pub capturing: bool,
+}
+
+/// Interfaces as of the capture's last check, or listed now when nothing is captured.
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_capture_interfaces(
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Vec<CaptureInterface>, String> {
+    let monitor_state = state.lock().await;
+    let capture = &monitor_state.capture;
+    let known: Vec<Interface> = if capture.sources.is_empty() {
+        interfaces::list().map_err(|e| to_cmd_err(AppError::Msg(e.to_string())))?
+    } else {
+        capture.interfaces.lock().unwrap().values().cloned().collect()
+    };
+
+    Ok(known
+        .into_iter()
+        .map(|i| CaptureInterface {
+            index: i.index,
+            tunnel: i.is_tunnel(),
+            // A source without an interface sees all of them.
+            capturing: capture.sources.keys().any(|t| {
+                t.interface.map_or(i.up && !i.loopback, |index| index == i.index)
+            }),
+            addrs: i.addrs.iter().map(IpAddr::to_string).collect(),
+            name: i.name,
+        })
+        .collect())
+}
+
+/// Running sessions and stopped ones whose results are still kept.
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn list_monitor_sessions(
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Vec<MonitorSessionInfo>, String> {
+    let monitor_state = state.lock().await;
+    Ok(monitor_state
+        .sessions
+        .iter()
+        .map(|(id, session)| MonitorSessionInfo {
+            id: id.clone(),
+            label: session.label.clone(),
+            is_monitoring: session.is_monitoring,
+            replay: session.replay,
+            server_count: session.detected_servers.len(),
+        })
+        .collect())
 }
 
+/// Servers of session `session_id`, or of every session.
 #[tracing::instrument(level = "debug", skip(state))]
 #[tauri::command]
 pub async fn get_detected_servers(
+    session_id: Option<String>,
     state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
 ) -> Result<Vec<DetectedServer>, String> {
     let monitor_state = state.lock().await;
-    Ok(monitor_state.detected_servers.clone())
+    Ok(monitor_state.servers(session_id.as_deref()))
+}
+
+/// Samples of the flow to `ip`:`port` over the last `window_secs` (default: 5 minutes),
+/// averaged down to at most `max_points` (default: all). Without `protocol`, UDP is
+/// preferred; without `session_id`, the session that saw the flow last is used. Empty when
+/// no session has seen the flow.
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_flow_history(
+    ip: String,
+    port: u16,
+    window_secs: Option<u64>,
+    protocol: Option<Transport>,
+    max_points: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Vec<FlowSample>, String> {
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|_| to_cmd_err(AppError::Msg(format!("invalid IP address '{ip}'"))))?;
+    let remote = SocketAddr::new(ip, port);
+    let window = Duration::from_secs(window_secs.unwrap_or(FLOW_HISTORY_WINDOW_SECS));
+    let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+    let st = state.lock().await;
+    let history = match session_id {
+        Some(id) => st.sessions.get(&id).map(|s| &s.flow_history),
+        None => st
+            .sessions
+            .values()
+            .map(|s| &s.flow_history)
+            .max_by_key(|h| h.last_sample_at(remote)),
+    };
+    Ok(history
+        .and_then(|h| h.query(protocol, remote, window, max_points.unwrap_or(0), now_ms))
+        .unwrap_or_default())
+}
+
+/// Streams what changed after each monitor tick to `on_update`, at most once every
+/// `interval_ms` (default: every tick, i.e. every 2 s). Updates cover every session; each
+/// server names its `session_id`. The first update lists everything currently detected.
+/// Returns the id to pass to `unsubscribe_monitor_updates`; subscriptions also end when the
+/// webview drops the channel.
+#[tracing::instrument(level = "info", skip(on_update, state))]
+#[tauri::command]
+pub async fn subscribe_monitor_updates(
+    on_update: tauri::ipc::Channel<MonitorUpdate<DetectedServer>>,
+    interval_ms: Option<u64>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<u64, String> {
+    let interval = Duration::from_millis(interval_ms.unwrap_or(0));
+    let sink = Box::new(move |update: &MonitorUpdate<DetectedServer>| {
+        on_update.send(update.clone()).is_ok()
+    });
+    let mut monitor_state = state.lock().await;
+    let id = monitor_state.feed.subscribe(sink, interval);
+    // Late subscribers get the current picture right away instead of after the next tick.
+    monitor_state.publish();
+    Ok(id)
 }
 
+#[tracing::instrument(level = "info", skip(state))]
+#[tauri::command]
+pub async fn unsubscribe_monitor_updates(
+    id: u64,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<bool, String> {
+    Ok(state.lock().await.feed.unsubscribe(id))
+}
+
+/// Stops session `session_id`, or every running session when none is named.
 #[tracing::instrument(level = "info", skip(state))]
 #[tauri::command]
 pub async fn stop_monitoring(
+    session_id: Option<String>,
     state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
 ) -> Result<String, String> {
     let mut monitor_state = state.lock().await;
 
-    if !monitor_state.is_monitoring {
+    let mut stopped = 0;
+    for (id, session) in monitor_state.sessions.iter_mut() {
+        if session.is_monitoring && session_id.as_deref().is_none_or(|wanted| wanted == id.as_str()) {
+            session.stop();
+            stopped += 1;
+        }
+    }
+
+    if stopped == 0 {
         return Err(to_cmd_err(AppError::NoActiveMonitoring));
     }
 
-    if let Some(cancel_tx) = &monitor_state.cancel_token {
-        let _ = cancel_tx.send(true);
+    Ok("Monitoring stopped successfully".to_string())
+}
+
+/// Stops session `id`; returns whether it was running.
+pub async fn stop_session(state: &Arc<AsyncMonitorMutex<MonitorState>>, id: &str) -> bool {
+    let mut monitor_state = state.lock().await;
+    match monitor_state.sessions.get_mut(id) {
+        Some(session) if session.is_monitoring => {
+            session.stop();
+            true
+        }
+        _ => false,
     }
+}
 
-    monitor_state.is_monitoring = false;
-    monitor_state.process_name = None;
-    monitor_state.cancel_token = None;
+/// Cancels every active session and waits (bounded) for the loops and sniffer threads to exit.
+pub async fn shutdown(state: &Arc<AsyncMonitorMutex<MonitorState>>) {
+    let handles: Vec<_> = {
+        let mut monitor_state = state.lock().await;
+        monitor_state
+            .sessions
+            .values_mut()
+            .filter_map(|session| {
+                let handle = session.loop_handle.take();
+                session.stop();
+                handle
+            })
+            .collect()
+    };
 
-    Ok("Monitoring stopped successfully".to_string())
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_JOIN_TIMEOUT;
+    for handle in handles {
+        if tokio::time::timeout_at(deadline, handle).await.is_err() {
+            tracing::warn!("monitoring loop did not stop in time");
+        }
+    }
+}
+
+/// Starts writing session `session_id`'s game packets to a pcapng file (by default under
+/// the app data `captures` folder). Replaces any finished recording.
+#[tracing::instrument(level = "info", skip(app, state))]
+#[tauri::command]
+pub async fn start_capture_recording(
+    app: tauri::AppHandle,
+    path: Option<String>,
+    max_bytes: Option<u64>,
+    max_duration_secs: Option<u64>,
+    session_id: Option<String>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<RecordingStatus, String> {
+    use tauri::Manager;
+
+    let monitor_state = state.lock().await;
+    let session =
+        monitor_state.active_session(session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID))?;
+    let label = session.label.clone();
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let dir = app
+                .path()
+                .app_data_dir()
+                .map_err(|e| to_cmd_err(AppError::Msg(e.to_string())))?
+                .join("captures");
+            recording::default_path(&dir, &label)
+        }
+    };
+
+    let mut slot = session.tap.recording.lock().unwrap();
+    if slot.as_ref().is_some_and(|r| r.is_active()) {
+        return Err(to_cmd_err(AppError::Msg(
+            "a capture recording is already running".to_string(),
+        )));
+    }
+
+    let new_recording = Recording::create(
+        &path,
+        &label,
+        max_bytes.unwrap_or(recording::DEFAULT_MAX_BYTES),
+        max_duration_secs
+            .map(Duration::from_secs)
+            .unwrap_or(recording::DEFAULT_MAX_DURATION),
+    )
+    .map_err(|e| {
+        to_cmd_err(AppError::Msg(format!(
+            "cannot create {}: {}",
+            path.display(),
+            e
+        )))
+    })?;
+    tracing::info!(path = %path.display(), "capture recording started");
+
+    let status = new_recording.status();
+    *slot = Some(new_recording);
+    Ok(status)
+}
+
+/// Stops the recording (if still running) and returns its final status.
+#[tracing::instrument(level = "info", skip(state))]
+#[tauri::command]
+pub async fn stop_capture_recording(
+    session_id: Option<String>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Option<RecordingStatus>, String> {
+    let monitor_state = state.lock().await;
+    let Some(session) = monitor_state
+        .sessions
+        .get(session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID))
+    else {
+        return Ok(None);
+    };
+    let mut slot = session.tap.recording.lock().unwrap();
+    Ok(slot.as_mut().map(|recording| {
+        recording.finish(StopReason::Stopped);
+        recording.status()
+    }))
 }
 
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_capture_recording_status(
+    session_id: Option<String>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Option<RecordingStatus>, String> {
+    let monitor_state = state.lock().await;
+    let Some(session) = monitor_state
+        .sessions
+        .get(session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID))
+    else {
+        return Ok(None);
+    };
+    let slot = session.tap.recording.lock().unwrap();
+    Ok(slot.as_ref().map(Recording::status))
+}
+
+/// Game-server IPs plus the public peers of the game's TCP sessions, for session
+/// `session_id` or all of them. With a category or confidence filter, only classified
+/// flows that pass it are returned.
 #[tracing::instrument(level = "debug", skip(state))]
 #[tauri::command]
 pub async fn get_all_session_ips(
     state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+    session_id: Option<String>,
+    categories: Option<Vec<FlowCategory>>,
+    min_confidence: Option<f32>,
 ) -> Result<Vec<String>, String> {
+    let filter = FlowFilter {
+        categories,
+        min_confidence,
+    };
     let monitor_state = state.lock().await;
-    let mut ips: HashSet<String> = monitor_state
-        .detected_servers
-        .iter()
-        .filter(|s| s.is_game_server)
-        .map(|s| s.ip.clone())
-        .collect();
-    for ip in monitor_state.tcp_session_ips.keys() {
-        ips.insert(ip.clone());
+    if !filter.is_empty() {
+        let ips: BTreeSet<String> = monitor_state
+            .servers(session_id.as_deref())
+            .into_iter()
+            .filter(|s| filter.matches(&s.classification()))
+            .map(|s| s.ip)
+            .collect();
+        return Ok(ips.into_iter().collect());
     }
-    Ok(ips.into_iter().collect())
+
+    Ok(monitor_state
+        .session_ips(session_id.as_deref())
+        .into_iter()
+        .collect())
 }
 
-#[tracing::instrument(level = "info", skip(app), fields(ip = %ip))]
+/// Automatic routing decisions, newest first (default: the last 100).
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_route_audit_log(
+    limit: Option<usize>,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
+) -> Result<Vec<RouteDecision>, String> {
+    let monitor_state = state.lock().await;
+    let log = monitor_state.auto_router.lock().unwrap().log(limit.unwrap_or(100));
+    Ok(log)
+}
+
+/// Routes `ip` through the tunnel. With a category or confidence filter, the IP must
+/// belong to a detected flow that passes it.
+#[tracing::instrument(level = "info", skip(app, state), fields(ip = %ip))]
 #[tauri::command]
 pub async fn add_detected_ip_to_routes(
     app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<AsyncMonitorMutex<MonitorState>>>,
     ip: String,
+    categories: Option<Vec<FlowCategory>>,
+    min_confidence: Option<f32>,
 ) -> Result<String, String> {
-    if !is_public_ip(&ip) {
+    let addr: IpAddr = match ip.parse() {
+        Ok(addr) if netaddr::is_public(addr) => addr,
+        _ => {
+            return Err(to_cmd_err(AppError::Msg(format!(
+                "invalid or private IP address: {}",
+                ip
+            ))))
+        }
+    };
+    // The tunnel carries IPv4 only: AllowedIPs and the injected routes are host /32s.
+    if addr.is_ipv6() {
         return Err(to_cmd_err(AppError::Msg(format!(
-            "invalid or private IP address: {}",
+            "IPv6 address {} cannot be routed through the tunnel",
             ip
         ))));
     }
 
+    let filter = FlowFilter {
+        categories,
+        min_confidence,
+    };
+    if !filter.is_empty() {
+        let monitor_state = state.lock().await;
+        let passes = monitor_state
+            .servers(None)
+            .iter()
+            .any(|s| s.ip.parse() == Ok(addr) && filter.matches(&s.classification()));
+        if !passes {
+            return Err(to_cmd_err(AppError::Msg(format!(
+                "{} is not a detected flow matching the route filter",
+                ip
+            ))));
+        }
+    }
+
     if crate::vpn::is_full_tunnel_active() {
         tracing::debug!(ip = %ip, "full tunnel active; skipping monitor route update");
         return Ok("Full tunnel active; no extra route needed.".to_string());
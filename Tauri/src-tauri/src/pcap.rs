@@ -0,0 +1,641 @@
+//! Minimal reader for classic pcap and pcapng captures, and a pcapng writer.
+//!
+//! Only what the monitor needs: timestamps and the IP packet inside each frame. Frames
+//! are unwrapped from Ethernet (with VLAN tags), Linux cooked (SLL), BSD loopback and raw
+//! IP link types; anything else, and any frame that is neither IPv4 nor IPv6, is skipped.
+//! The writer produces a single-interface `LINKTYPE_RAW` section that Wireshark and
+//! [`PcapReader`] both open.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_SPB: u32 = 0x0000_0003;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+pub const LINKTYPE_NULL: u16 = 0;
+pub const LINKTYPE_ETHERNET: u16 = 1;
+pub const LINKTYPE_RAW: u16 = 101;
+pub const LINKTYPE_LINUX_SLL: u16 = 113;
+pub const LINKTYPE_IPV4: u16 = 228;
+pub const LINKTYPE_IPV6: u16 = 229;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+const BSD_AF_INET: u32 = 2;
+// AF_INET6 differs between the BSDs: NetBSD/OpenBSD, FreeBSD and macOS.
+const BSD_AF_INET6: [u32; 3] = [24, 28, 30];
+
+// Guards against absurd length fields in corrupt files.
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Time since the Unix epoch; `None` for pcapng simple packet blocks.
+    pub timestamp: Option<Duration>,
+    pub link_type: u16,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// The IPv4 or IPv6 packet carried by this frame, if there is one.
+    pub fn ip_payload(&self) -> Option<&[u8]> {
+        ip_payload(self.link_type, &self.data)
+    }
+}
+
+pub fn ip_payload(link_type: u16, data: &[u8]) -> Option<&[u8]> {
+    let packet = match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => data,
+        LINKTYPE_NULL => {
+            let family = data.get(..4)?;
+            let family_le = u32::from_le_bytes(family.try_into().ok()?);
+            let family_be = u32::from_be_bytes(family.try_into().ok()?);
+            let is_ip = |f: u32| f == BSD_AF_INET || BSD_AF_INET6.contains(&f);
+            if !is_ip(family_le) && !is_ip(family_be) {
+                return None;
+            }
+            &data[4..]
+        }
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            loop {
+                let ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+                match ethertype {
+                    ETHERTYPE_VLAN | ETHERTYPE_QINQ => offset += 4,
+                    ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => break data.get(offset + 2..)?,
+                    _ => return None,
+                }
+            }
+        }
+        LINKTYPE_LINUX_SLL => {
+            let protocol = u16::from_be_bytes(data.get(14..16)?.try_into().ok()?);
+            if protocol != ETHERTYPE_IPV4 && protocol != ETHERTYPE_IPV6 {
+                return None;
+            }
+            &data[16..]
+        }
+        _ => return None,
+    };
+    matches!(packet.first()? >> 4, 4 | 6).then_some(packet)
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanos: bool,
+        link_type: u16,
+    },
+    PcapNg {
+        big_endian: bool,
+        /// (link type, timestamp units per second) per interface id.
+        interfaces: Vec<(u16, u64)>,
+    },
+}
+
+pub struct PcapReader<R> {
+    inner: R,
+    format: Format,
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let b: [u8; 4] = bytes[..4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let b: [u8; 2] = bytes[..2].try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads exactly `buf.len()` bytes; `Ok(false)` on a clean EOF before the first byte.
+fn read_or_eof<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn tsresol_units(value: u8) -> u64 {
+    let exp = u32::from(value & 0x7F);
+    let base: u64 = if value & 0x80 == 0 { 10 } else { 2 };
+    base.checked_pow(exp).unwrap_or(1_000_000)
+}
+
+fn units_to_duration(ts: u64, units_per_sec: u64) -> Duration {
+    let units_per_sec = units_per_sec.max(1);
+    let nanos = (ts % units_per_sec) as u128 * 1_000_000_000 / units_per_sec as u128;
+    Duration::new(ts / units_per_sec, nanos as u32)
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        let le = u32::from_le_bytes(magic);
+        let be = u32::from_be_bytes(magic);
+
+        let format = if le == PCAPNG_SHB {
+            // The SHB magic is a palindrome; its byte-order magic tells the endianness.
+            let mut rest = [0u8; 8];
+            inner.read_exact(&mut rest)?;
+            let big_endian = match u32::from_le_bytes(rest[4..8].try_into().unwrap()) {
+                PCAPNG_BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("bad pcapng byte-order magic")),
+            };
+            let block_len = read_u32(&rest[..4], big_endian) as usize;
+            if !(28..=MAX_BLOCK_LEN).contains(&block_len) {
+                return Err(invalid("bad pcapng section header length"));
+            }
+            let mut body = vec![0u8; block_len - 12];
+            inner.read_exact(&mut body)?;
+            Format::PcapNg {
+                big_endian,
+                interfaces: Vec::new(),
+            }
+        } else {
+            let (big_endian, nanos) = match (le, be) {
+                (PCAP_MAGIC_MICROS, _) => (false, false),
+                (PCAP_MAGIC_NANOS, _) => (false, true),
+                (_, PCAP_MAGIC_MICROS) => (true, false),
+                (_, PCAP_MAGIC_NANOS) => (true, true),
+                _ => return Err(invalid("not a pcap or pcapng file")),
+            };
+            let mut header = [0u8; 20];
+            inner.read_exact(&mut header)?;
+            Format::Pcap {
+                big_endian,
+                nanos,
+                // The upper bits of the link-type field hold FCS flags.
+                link_type: read_u32(&header[16..20], big_endian) as u16,
+            }
+        };
+
+        Ok(Self { inner, format })
+    }
+
+    /// Next frame in file order, or `None` at the end of the capture.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        match &mut self.format {
+            Format::Pcap {
+                big_endian,
+                nanos,
+                link_type,
+            } => {
+                let mut header = [0u8; 16];
+                if !read_or_eof(&mut self.inner, &mut header)? {
+                    return Ok(None);
+                }
+                let secs = read_u32(&header[0..4], *big_endian);
+                let frac = read_u32(&header[4..8], *big_endian);
+                let incl_len = read_u32(&header[8..12], *big_endian) as usize;
+                if incl_len > MAX_BLOCK_LEN {
+                    return Err(invalid("bad pcap record length"));
+                }
+                let mut data = vec![0u8; incl_len];
+                self.inner.read_exact(&mut data)?;
+                let frac_nanos = if *nanos {
+                    frac
+                } else {
+                    frac.saturating_mul(1000)
+                };
+                Ok(Some(Frame {
+                    timestamp: Some(Duration::new(u64::from(secs), frac_nanos.min(999_999_999))),
+                    link_type: *link_type,
+                    data,
+                }))
+            }
+            Format::PcapNg {
+                big_endian,
+                interfaces,
+            } => loop {
+                let mut header = [0u8; 8];
+                if !read_or_eof(&mut self.inner, &mut header)? {
+                    return Ok(None);
+                }
+                let block_type = read_u32(&header[0..4], *big_endian);
+                if block_type == PCAPNG_SHB {
+                    return Err(invalid("multiple pcapng sections are not supported"));
+                }
+                let block_len = read_u32(&header[4..8], *big_endian) as usize;
+                if block_len < 12 || !block_len.is_multiple_of(4) || block_len > MAX_BLOCK_LEN {
+                    return Err(invalid("bad pcapng block length"));
+                }
+                let mut body = vec![0u8; block_len - 8];
+                self.inner.read_exact(&mut body)?;
+                // Drop the trailing copy of the block length.
+                let body = &body[..body.len() - 4];
+                let be = *big_endian;
+
+                match block_type {
+                    BLOCK_IDB => {
+                        if body.len() < 8 {
+                            return Err(invalid("short pcapng interface block"));
+                        }
+                        let link_type = read_u16(&body[0..2], be);
+                        interfaces.push((link_type, idb_tsresol(&body[8..], be)));
+                    }
+                    BLOCK_EPB => {
+                        if body.len() < 20 {
+                            return Err(invalid("short pcapng packet block"));
+                        }
+                        let interface = read_u32(&body[0..4], be) as usize;
+                        let ts = (u64::from(read_u32(&body[4..8], be)) << 32)
+                            | u64::from(read_u32(&body[8..12], be));
+                        let cap_len = read_u32(&body[12..16], be) as usize;
+                        let data = body[20..]
+                            .get(..cap_len)
+                            .ok_or_else(|| invalid("pcapng packet overruns its block"))?;
+                        let &(link_type, units) = interfaces
+                            .get(interface)
+                            .ok_or_else(|| invalid("pcapng packet on unknown interface"))?;
+                        return Ok(Some(Frame {
+                            timestamp: Some(units_to_duration(ts, units)),
+                            link_type,
+                            data: data.to_vec(),
+                        }));
+                    }
+                    BLOCK_SPB => {
+                        let &(link_type, _) = interfaces
+                            .first()
+                            .ok_or_else(|| invalid("pcapng packet on unknown interface"))?;
+                        if body.len() < 4 {
+                            return Err(invalid("short pcapng packet block"));
+                        }
+                        let orig_len = read_u32(&body[0..4], be) as usize;
+                        let data = &body[4..];
+                        return Ok(Some(Frame {
+                            timestamp: None,
+                            link_type,
+                            data: data[..orig_len.min(data.len())].to_vec(),
+                        }));
+                    }
+                    // Name resolution, statistics, custom blocks and friends.
+                    _ => {}
+                }
+            },
+        }
+    }
+}
+
+fn idb_tsresol(mut options: &[u8], big_endian: bool) -> u64 {
+    let mut units = 1_000_000;
+    while options.len() >= 4 {
+        let code = read_u16(&options[0..2], big_endian);
+        let len = usize::from(read_u16(&options[2..4], big_endian));
+        if code == OPT_END {
+            break;
+        }
+        let Some(value) = options.get(4..4 + len) else {
+            break;
+        };
+        if code == OPT_IF_TSRESOL && len == 1 {
+            units = tsresol_units(value[0]);
+        }
+        let padded = (len + 3) & !3;
+        options = options.get(4 + padded..).unwrap_or_default();
+    }
+    units
+}
+
+/// Writes a pcapng section with one raw-IP interface and microsecond timestamps.
+pub struct PcapNgWriter<W: Write> {
+    inner: W,
+    bytes_written: u64,
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    // Option lengths are 16-bit; longer comments are cut.
+    let value = &value[..value.len().min(usize::from(u16::MAX) & !3)];
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+fn end_options(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&OPT_END.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Writes the section and interface headers. `comment` lands on the section, where
+    /// Wireshark shows it under Statistics > Capture File Properties.
+    pub fn new(inner: W, application: &str, comment: Option<&str>) -> io::Result<Self> {
+        let mut writer = Self {
+            inner,
+            bytes_written: 0,
+        };
+
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown.
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        if let Some(comment) = comment {
+            push_option(&mut shb, OPT_COMMENT, comment.as_bytes());
+        }
+        push_option(&mut shb, OPT_SHB_USERAPPL, application.as_bytes());
+        end_options(&mut shb);
+        writer.write_block(PCAPNG_SHB, &shb)?;
+
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&0u32.to_le_bytes());
+        push_option(&mut idb, OPT_IF_NAME, application.as_bytes());
+        end_options(&mut idb);
+        writer.write_block(BLOCK_IDB, &idb)?;
+
+        Ok(writer)
+    }
+
+    /// Appends one IPv4 or IPv6 packet, optionally with a per-packet comment.
+    pub fn write_packet(
+        &mut self,
+        timestamp: Duration,
+        packet: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let micros = timestamp.as_micros() as u64;
+        let mut epb = Vec::with_capacity(packet.len() + 32);
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(micros as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        epb.extend_from_slice(packet);
+        epb.resize(epb.len().next_multiple_of(4), 0);
+        if let Some(comment) = comment {
+            push_option(&mut epb, OPT_COMMENT, comment.as_bytes());
+            end_options(&mut epb);
+        }
+        self.write_block(BLOCK_EPB, &epb)
+    }
+
+    /// Size of the file so far, headers included.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let len = (body.len() + 12) as u32;
+        self.inner.write_all(&block_type.to_le_bytes())?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(body)?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.bytes_written += u64::from(len);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_packet() -> Vec<u8> {
+        let mut p = vec![0u8; 28];
+        p[0] = 0x45;
+        p[2..4].copy_from_slice(&28u16.to_be_bytes());
+        p[9] = 17;
+        p
+    }
+
+    fn ethernet(ethertypes: &[u16], payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![0u8; 12];
+        for (i, t) in ethertypes.iter().enumerate() {
+            f.extend_from_slice(&t.to_be_bytes());
+            if i + 1 < ethertypes.len() {
+                f.extend_from_slice(&[0, 1]);
+            }
+        }
+        f.extend_from_slice(payload);
+        f
+    }
+
+    fn classic_pcap(big_endian: bool, link_type: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let w32 = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut f = Vec::new();
+        f.extend_from_slice(&w32(PCAP_MAGIC_MICROS));
+        // Version, zone and sigfigs are not checked by the reader.
+        f.extend_from_slice(&[0; 12]);
+        f.extend_from_slice(&w32(65535));
+        f.extend_from_slice(&w32(link_type));
+        for (secs, micros, data) in records {
+            f.extend_from_slice(&w32(*secs));
+            f.extend_from_slice(&w32(*micros));
+            f.extend_from_slice(&w32(data.len() as u32));
+            f.extend_from_slice(&w32(data.len() as u32));
+            f.extend_from_slice(data);
+        }
+        f
+    }
+
+    fn ng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut padded = body.to_vec();
+        padded.resize((body.len() + 3) & !3, 0);
+        let len = (padded.len() + 12) as u32;
+        let mut b = Vec::new();
+        b.extend_from_slice(&block_type.to_le_bytes());
+        b.extend_from_slice(&len.to_le_bytes());
+        b.extend_from_slice(&padded);
+        b.extend_from_slice(&len.to_le_bytes());
+        b
+    }
+
+    fn pcapng(tsresol: Option<u8>, packets: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&u64::MAX.to_le_bytes());
+        let mut f = ng_block(PCAPNG_SHB, &shb);
+
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        idb.extend_from_slice(&[0, 0]);
+        idb.extend_from_slice(&0u32.to_le_bytes());
+        if let Some(res) = tsresol {
+            idb.extend_from_slice(&OPT_IF_TSRESOL.to_le_bytes());
+            idb.extend_from_slice(&1u16.to_le_bytes());
+            idb.extend_from_slice(&[res, 0, 0, 0]);
+            idb.extend_from_slice(&[0; 4]);
+        }
+        f.extend(ng_block(BLOCK_IDB, &idb));
+        // Unknown blocks are skipped.
+        f.extend(ng_block(0x0000_0005, &[0; 8]));
+
+        for (ts, data) in packets {
+            let mut epb = Vec::new();
+            epb.extend_from_slice(&0u32.to_le_bytes());
+            epb.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+            epb.extend_from_slice(&(*ts as u32).to_le_bytes());
+            epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            epb.extend_from_slice(data);
+            f.extend(ng_block(BLOCK_EPB, &epb));
+        }
+        f
+    }
+
+    #[test]
+    fn reads_classic_pcap_in_both_byte_orders() {
+        let frame = ethernet(&[ETHERTYPE_IPV4], &ipv4_packet());
+        for big_endian in [false, true] {
+            let file = classic_pcap(
+                big_endian,
+                u32::from(LINKTYPE_ETHERNET),
+                &[(10, 250_000, &frame), (11, 0, &frame)],
+            );
+            let mut reader = PcapReader::new(file.as_slice()).unwrap();
+            let first = reader.next_frame().unwrap().unwrap();
+            assert_eq!(first.timestamp, Some(Duration::from_millis(10_250)));
+            assert_eq!(first.ip_payload(), Some(ipv4_packet().as_slice()));
+            assert!(reader.next_frame().unwrap().is_some());
+            assert!(reader.next_frame().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn reads_pcapng_with_custom_resolution() {
+        let packet = ipv4_packet();
+        let file = pcapng(Some(9), &[(1_500_000_000, &packet)]);
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.timestamp, Some(Duration::from_millis(1500)));
+        assert_eq!(frame.ip_payload(), Some(packet.as_slice()));
+        assert!(reader.next_frame().unwrap().is_none());
+
+        let file = pcapng(None, &[(2_000_001, &packet)]);
+        let frame = PcapReader::new(file.as_slice())
+            .unwrap()
+            .next_frame()
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.timestamp, Some(Duration::from_micros(2_000_001)));
+    }
+
+    #[test]
+    fn unwraps_link_layers() {
+        let ip = ipv4_packet();
+        let vlan = ethernet(&[ETHERTYPE_VLAN, ETHERTYPE_IPV4], &ip);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &vlan), Some(ip.as_slice()));
+        let arp = ethernet(&[0x0806], &ip);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &arp), None);
+
+        let mut null = 2u32.to_le_bytes().to_vec();
+        null.extend_from_slice(&ip);
+        assert_eq!(ip_payload(LINKTYPE_NULL, &null), Some(ip.as_slice()));
+
+        let mut sll = vec![0u8; 14];
+        sll.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        sll.extend_from_slice(&ip);
+        assert_eq!(ip_payload(LINKTYPE_LINUX_SLL, &sll), Some(ip.as_slice()));
+
+        let mut v6 = vec![0u8; 48];
+        v6[0] = 0x60;
+        let eth6 = ethernet(&[ETHERTYPE_IPV6], &v6);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &eth6), Some(v6.as_slice()));
+        let mut null6 = 30u32.to_be_bytes().to_vec();
+        null6.extend_from_slice(&v6);
+        assert_eq!(ip_payload(LINKTYPE_NULL, &null6), Some(v6.as_slice()));
+        assert_eq!(ip_payload(LINKTYPE_IPV6, &v6), Some(v6.as_slice()));
+
+        let mut not_ip = ip.clone();
+        not_ip[0] = 0x50;
+        assert_eq!(ip_payload(LINKTYPE_RAW, &not_ip), None);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &[0; 13]), None);
+    }
+
+    #[test]
+    fn rejects_garbage_and_truncated_files() {
+        assert!(PcapReader::new(&b"not a capture"[..]).is_err());
+
+        let frame = ipv4_packet();
+        let file = classic_pcap(false, u32::from(LINKTYPE_RAW), &[(1, 0, &frame)]);
+        let mut reader = PcapReader::new(&file[..file.len() - 3]).unwrap();
+        assert!(reader.next_frame().is_err());
+
+        let mut bad_len = pcapng(None, &[(1, &frame)]);
+        let epb_len_at = bad_len.len() - (frame.len() + 32) + 4;
+        bad_len[epb_len_at..epb_len_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = PcapReader::new(bad_len.as_slice()).unwrap();
+        assert!(reader.next_frame().is_err());
+    }
+
+    #[test]
+    fn writer_output_reads_back() {
+        let packet = ipv4_packet();
+        let mut file = Vec::new();
+        let mut writer =
+            PcapNgWriter::new(&mut file, "PingPal", Some("TslGame.exe pid 4242")).unwrap();
+        writer
+            .write_packet(Duration::from_micros(1_700_000_000_123_456), &packet, None)
+            .unwrap();
+        // Odd-length packet and comment exercise the padding.
+        writer
+            .write_packet(
+                Duration::from_secs(1_700_000_001),
+                &packet[..27],
+                Some("pid 7"),
+            )
+            .unwrap();
+        let written = writer.bytes_written();
+        assert_eq!(written as usize, file.len());
+        assert_eq!(file.len() % 4, 0);
+
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let first = reader.next_frame().unwrap().unwrap();
+        assert_eq!(
+            first.timestamp,
+            Some(Duration::from_micros(1_700_000_000_123_456))
+        );
+        assert_eq!(first.link_type, LINKTYPE_RAW);
+        assert_eq!(first.ip_payload(), Some(packet.as_slice()));
+        let second = reader.next_frame().unwrap().unwrap();
+        assert_eq!(second.data, packet[..27]);
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+}
@@ -0,0 +1,272 @@
+//! Finding a game's processes.
+//!
+//! Profile executables are patterns, compared case-insensitively with `*` as a wildcard.
+//! A bare name such as `TslGame.exe` is checked against the process name, the executable's
+//! file name and the file name of the first command-line argument; the last one is what
+//! identifies Wine and Proton games on Linux, where the process itself is the Wine loader.
+//! A pattern with a `/` or `\` is checked against those full paths instead. Descendants of
+//! a matched process belong to the game too, so a profile may name the launcher.
+
+use crate::classifier::wildcard_match;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+/// How long a found game is trusted before the full process list is read again, which
+/// is also how soon new child processes are noticed.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What process matching needs to know about one process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcInfo {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    pub exe: Option<String>,
+    /// First command-line argument.
+    pub arg0: Option<String>,
+}
+
+/// Forward slashes only, so Windows and Unix paths compare alike.
+fn normalize(s: &str) -> String {
+    s.trim().replace('\\', "/").to_lowercase()
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+enum Pattern {
+    Name(String),
+    Path(String),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let normalized = normalize(pattern);
+        if normalized.contains('/') {
+            Pattern::Path(normalized)
+        } else {
+            Pattern::Name(normalized)
+        }
+    }
+
+    /// The name the process matched under, in its original case.
+    fn matched_name<'a>(&self, proc: &'a ProcInfo) -> Option<&'a str> {
+        let paths = || proc.exe.iter().chain(&proc.arg0).map(String::as_str);
+        match self {
+            Pattern::Name(pattern) => std::iter::once(proc.name.as_str())
+                .chain(paths().map(file_name))
+                .find(|name| !name.is_empty() && wildcard_match(pattern, &normalize(name))),
+            Pattern::Path(pattern) => paths()
+                .find(|path| wildcard_match(pattern, &normalize(path)))
+                .map(file_name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameProcesses {
+    /// Executable name of the first matched process.
+    pub name: String,
+    /// Processes matching the pattern, in pid order.
+    pub matched: Vec<u32>,
+    /// `matched` and all their descendants, in pid order.
+    pub tree: Vec<u32>,
+}
+
+/// Processes of the first pattern that matches anything; earlier patterns are preferred.
+pub fn select(patterns: &[String], procs: &[ProcInfo]) -> Option<GameProcesses> {
+    let (name, mut matched) = patterns
+        .iter()
+        .map(|p| Pattern::parse(p))
+        .find_map(|pattern| {
+            let mut hits = procs
+                .iter()
+                .filter_map(|proc| Some((pattern.matched_name(proc)?, proc.pid)))
+                .collect::<Vec<_>>();
+            hits.sort_by_key(|(_, pid)| *pid);
+            let name = hits.first()?.0.to_string();
+            Some((
+                name,
+                hits.into_iter().map(|(_, pid)| pid).collect::<Vec<_>>(),
+            ))
+        })?;
+    matched.dedup();
+
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for proc in procs {
+        if let Some(parent) = proc.parent.filter(|parent| *parent != proc.pid) {
+            children.entry(parent).or_default().push(proc.pid);
+        }
+    }
+    let mut tree: HashSet<u32> = HashSet::new();
+    let mut queue = matched.clone();
+    while let Some(pid) = queue.pop() {
+        if tree.insert(pid) {
+            queue.extend(children.get(&pid).into_iter().flatten());
+        }
+    }
+    let mut tree: Vec<u32> = tree.into_iter().collect();
+    tree.sort_unstable();
+
+    Some(GameProcesses {
+        name,
+        matched,
+        tree,
+    })
+}
+
+fn refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::nothing()
+        .with_exe(UpdateKind::OnlyIfNotSet)
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+}
+
+/// Reads the full process list.
+pub fn scan(system: &mut System) -> Vec<ProcInfo> {
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh_kind());
+    system
+        .processes()
+        .values()
+        .map(|proc| ProcInfo {
+            pid: proc.pid().as_u32(),
+            parent: proc.parent().map(Pid::as_u32),
+            name: proc.name().to_string_lossy().into_owned(),
+            exe: proc.exe().map(|exe| exe.to_string_lossy().into_owned()),
+            arg0: proc
+                .cmd()
+                .first()
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        })
+        .collect()
+}
+
+/// Looks up a game's processes, remembering the answer between calls: while the matched
+/// processes live, only they are checked, and the full list is read every
+/// [`RESCAN_INTERVAL`] or when the game is not running.
+#[derive(Default)]
+pub struct ProcessFinder {
+    system: System,
+    last: Option<(Vec<String>, GameProcesses, Instant)>,
+}
+
+impl ProcessFinder {
+    pub fn find(&mut self, patterns: &[String]) -> Option<GameProcesses> {
+        let now = Instant::now();
+        if let Some((last_patterns, found, scanned_at)) = &self.last {
+            if last_patterns.as_slice() == patterns
+                && now.saturating_duration_since(*scanned_at) < RESCAN_INTERVAL
+            {
+                let pids: Vec<Pid> = found
+                    .matched
+                    .iter()
+                    .map(|pid| Pid::from_u32(*pid))
+                    .collect();
+                self.system.refresh_processes_specifics(
+                    ProcessesToUpdate::Some(&pids),
+                    true,
+                    refresh_kind(),
+                );
+                if pids.iter().all(|pid| self.system.process(*pid).is_some()) {
+                    return Some(found.clone());
+                }
+            }
+        }
+
+        let procs = scan(&mut self.system);
+        let found = select(patterns, &procs);
+        self.last = found.clone().map(|found| (patterns.to_vec(), found, now));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc(
+        pid: u32,
+        parent: Option<u32>,
+        name: &str,
+        exe: Option<&str>,
+        arg0: Option<&str>,
+    ) -> ProcInfo {
+        ProcInfo {
+            pid,
+            parent,
+            name: name.to_string(),
+            exe: exe.map(str::to_string),
+            arg0: arg0.map(str::to_string),
+        }
+    }
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_names_case_insensitively_and_with_globs() {
+        let procs = [
+            proc(10, None, "explorer.exe", None, None),
+            proc(
+                42,
+                Some(10),
+                "TSLGAME.EXE",
+                Some(r"C:\Games\PUBG\TslGame.exe"),
+                None,
+            ),
+        ];
+        let found = select(&patterns(&["tslgame.exe"]), &procs).unwrap();
+        assert_eq!(found.name, "TSLGAME.EXE");
+        assert_eq!(found.matched, [42]);
+        assert_eq!(
+            select(&patterns(&["Tsl*.exe"]), &procs).unwrap().matched,
+            [42]
+        );
+        assert_eq!(select(&patterns(&["Tsl*.dll"]), &procs), None);
+
+        let by_path = select(&patterns(&[r"*\PUBG\*.exe"]), &procs).unwrap();
+        assert_eq!(by_path.name, "TslGame.exe");
+        assert_eq!(select(&patterns(&["*/Other/*.exe"]), &procs), None);
+    }
+
+    #[test]
+    fn finds_wine_and_proton_games_by_their_windows_path() {
+        let procs = [proc(
+            3100,
+            Some(3000),
+            "wine64-preload",
+            Some("/home/me/.steam/steam/steamapps/common/Proton 9.0/files/bin/wine64-preloader"),
+            Some(
+                r"Z:\home\me\.steam\steam\steamapps\common\PUBG\TslGame\Binaries\Win64\TslGame.exe",
+            ),
+        )];
+        let found = select(&patterns(&["TslGame.exe"]), &procs).unwrap();
+        assert_eq!(
+            (found.name.as_str(), found.matched.as_slice()),
+            ("TslGame.exe", [3100].as_slice())
+        );
+        assert!(select(&patterns(&["*/Binaries/Win64/TslGame.exe"]), &procs).is_some());
+    }
+
+    #[test]
+    fn follows_children_and_prefers_earlier_patterns() {
+        let procs = [
+            proc(1, None, "init", None, None),
+            proc(20, Some(1), "Launcher.exe", None, None),
+            proc(21, Some(20), "Client.exe", None, None),
+            proc(22, Some(21), "CrashReporter.exe", None, None),
+            proc(30, Some(1), "Unrelated.exe", None, None),
+            // A process claiming itself as parent must not loop.
+            proc(40, Some(40), "Odd.exe", None, None),
+        ];
+        let launcher = select(&patterns(&["launcher.exe", "client.exe"]), &procs).unwrap();
+        assert_eq!(launcher.matched, [20]);
+        assert_eq!(launcher.tree, [20, 21, 22]);
+
+        let client = select(&patterns(&["missing.exe", "client.exe"]), &procs).unwrap();
+        assert_eq!(client.tree, [21, 22]);
+        assert_eq!(select(&patterns(&["odd.exe"]), &procs).unwrap().tree, [40]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Per-game detection profiles.
+//!
+//! Profiles ship in `profiles/games.json`. More can be added, or bundled ones replaced by
+//! id, by dropping JSON files with the same layout into `<app data>/profiles/`; adding a
+//! game needs no code change.
+
+use crate::classifier::{Hints, Thresholds};
+use crate::dissector::Transport;
+use crate::netaddr::{Cidr, PortRange};
+use crate::route_policy::RouteRule;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Layout version of profile files; files with any other version are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+const BUNDLED: &str = include_str!("../profiles/games.json");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameProfile {
+    pub id: String,
+    pub name: String,
+    /// Process names or paths, e.g. `TslGame.exe` or `*/Binaries/Win64/*.exe`, matched as
+    /// described in [`crate::processes`]; earlier entries are preferred.
+    pub executables: Vec<String>,
+    /// Remote ports game servers listen on, e.g. `"7000-7999"`.
+    #[serde(default)]
+    pub server_ports: Vec<PortRange>,
+    #[serde(default)]
+    pub server_cidrs: Vec<Cidr>,
+    /// `*` wildcards matched against the reverse DNS name of remote peers.
+    #[serde(default)]
+    pub hostname_patterns: Vec<String>,
+    /// Transports that carry game state; any when empty.
+    #[serde(default)]
+    pub protocols: Vec<Transport>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// Game servers matching any of these are routed through the tunnel automatically;
+    /// see [`crate::route_policy`].
+    #[serde(default)]
+    pub route_rules: Vec<RouteRule>,
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    version: u32,
+    profiles: Vec<GameProfile>,
+}
+
+impl GameProfile {
+    /// Profile for a bare executable name: no server knowledge, default thresholds.
+    pub fn for_executable(name: &str) -> Self {
+        Self {
+            id: name.to_string(),
+            name: name.to_string(),
+            executables: vec![name.to_string()],
+            server_ports: Vec::new(),
+            server_cidrs: Vec::new(),
+            hostname_patterns: Vec::new(),
+            protocols: Vec::new(),
+            thresholds: Thresholds::default(),
+            route_rules: Vec::new(),
+        }
+    }
+
+    pub fn hints(&self) -> Hints {
+        Hints {
+            server_ports: self.server_ports.clone(),
+            server_cidrs: self.server_cidrs.clone(),
+            hostname_patterns: self
+                .hostname_patterns
+                .iter()
+                .map(|p| p.to_ascii_lowercase())
+                .collect(),
+            game_transports: self.protocols.clone(),
+            thresholds: self.thresholds.clone(),
+        }
+    }
+}
+
+pub fn parse(json: &str) -> Result<Vec<GameProfile>, String> {
+    let file: ProfileFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if file.version != FORMAT_VERSION {
+        return Err(format!(
+            "unsupported profile format version {} (expected {})",
+            file.version, FORMAT_VERSION
+        ));
+    }
+    for profile in &file.profiles {
+        if profile.id.trim().is_empty() {
+            return Err("profile with an empty id".to_string());
+        }
+        if profile.executables.iter().all(|e| e.trim().is_empty()) {
+            return Err(format!("profile {} lists no executables", profile.id));
+        }
+    }
+    Ok(file.profiles)
+}
+
+/// Bundled profiles overlaid with every `*.json` file in `user_dir`, in file name order.
+/// Files that fail to parse are logged and skipped.
+pub fn load(user_dir: Option<&Path>) -> Vec<GameProfile> {
+    let mut profiles = parse(BUNDLED).unwrap_or_else(|e| {
+        tracing::error!(error = %e, "bundled game profiles are invalid");
+        Vec::new()
+    });
+
+    let Some(dir) = user_dir else {
+        return profiles;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return profiles;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let loaded = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| parse(&json));
+        match loaded {
+            Ok(loaded) => {
+                for profile in loaded {
+                    tracing::debug!(id = %profile.id, path = %path.display(), "loaded game profile");
+                    profiles.retain(|p| p.id != profile.id);
+                    profiles.push(profile);
+                }
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "skipping game profile file")
+            }
+        }
+    }
+    profiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_profiles_parse() {
+        let profiles = parse(BUNDLED).unwrap();
+        let pubg = profiles.iter().find(|p| p.id == "pubg").unwrap();
+        assert_eq!(pubg.executables, ["TslGame.exe"]);
+        assert_eq!(pubg.protocols, [Transport::Udp]);
+        assert!(pubg.server_ports.iter().any(|r| r.contains(7086)));
+        // Unlisted thresholds keep their defaults.
+        assert_eq!(pubg.thresholds.game_min_pps, 20.0);
+        assert_eq!(
+            pubg.thresholds.min_confidence,
+            Thresholds::default().min_confidence
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions_and_incomplete_profiles() {
+        let v2 = r#"{"version": 2, "profiles": []}"#;
+        assert!(parse(v2).unwrap_err().contains("version 2"));
+        let no_exe = r#"{"version": 1, "profiles": [{"id": "x", "name": "X", "executables": []}]}"#;
+        assert!(parse(no_exe).is_err());
+        let bad_cidr = r#"{"version": 1, "profiles": [
+            {"id": "x", "name": "X", "executables": ["x.exe"], "server_cidrs": ["10.0.0.0/40"]}
+        ]}"#;
+        assert!(parse(bad_cidr).is_err());
+    }
+
+    #[test]
+    fn user_files_add_and_replace_profiles() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("a.json"),
+            r#"{"version": 1, "profiles": [
+                {"id": "pubg", "name": "PUBG test", "executables": ["TslGame.exe", "TslGame_UC.exe"]},
+                {"id": "cs2", "name": "Counter-Strike 2", "executables": ["cs2.exe"],
+                 "server_ports": ["27015-27050"], "hostname_patterns": ["*.Valve.net"],
+                 "route_rules": [{"min_confidence": 0.9, "countries": ["SE"]}]}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let profiles = load(Some(dir));
+
+        let pubg: Vec<_> = profiles.iter().filter(|p| p.id == "pubg").collect();
+        assert_eq!(pubg.len(), 1);
+        assert_eq!(pubg[0].name, "PUBG test");
+        let cs2 = profiles.iter().find(|p| p.id == "cs2").unwrap();
+        let hints = cs2.hints();
+        assert!(hints.matches_hostname("ams1.valve.net"));
+        assert!(hints.server_ports[0].contains(27015));
+        assert_eq!(cs2.route_rules[0].countries, ["SE"]);
+        assert!(pubg[0].route_rules.is_empty());
+    }
+
+    #[test]
+    fn executable_profile_carries_no_server_knowledge() {
+        let profile = GameProfile::for_executable("game.exe");
+        assert_eq!(profile.executables, ["game.exe"]);
+        let hints = profile.hints();
+        assert!(hints.server_ports.is_empty() && hints.game_transports.is_empty());
+        assert_eq!(hints.thresholds, Thresholds::default());
+    }
+}
@@ -0,0 +1,208 @@
+//! Passive link quality from the timing of server-to-client packets.
+//!
+//! Game servers send a snapshot every tick, so their packets arrive in bursts at a steady
+//! rate. From the gaps between bursts we estimate the tick rate (median gap), RFC 3550
+//! interarrival jitter against that rate, and loss: ticks that never arrived, discounting
+//! ones that turned up late inside a bigger-than-usual burst. A long silence is the server
+//! pausing (a loading screen, an idle lobby), not loss. None of this needs ICMP.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Packets closer together than this belong to the same tick.
+const BURST_GAP: Duration = Duration::from_millis(2);
+/// Bursts kept for the estimates; a few seconds at common tick rates.
+const WINDOW: usize = 256;
+/// Fewer bursts than this give no estimate.
+const MIN_BURSTS: usize = 16;
+/// Gaps longer than this many ticks are a pause in the traffic and count for nothing.
+const PAUSE_TICKS: f64 = 32.0;
+/// RFC 3550 smoothing: J += (|D| - J) / 16.
+const JITTER_GAIN: f64 = 1.0 / 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkQuality {
+    pub jitter_ms: f32,
+    pub tick_rate_hz: f32,
+    /// Share of expected ticks that never arrived, 0-100.
+    pub loss_pct: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Burst {
+    /// Seconds since the previous burst started.
+    gap: f64,
+    packets: u32,
+}
+
+/// Arrival history of one flow's inbound packets.
+#[derive(Debug, Clone, Default)]
+pub struct ArrivalTiming {
+    last_packet: Option<Instant>,
+    burst_start: Option<Instant>,
+    /// Gap before the burst in progress and its packets so far.
+    current: Option<Burst>,
+    bursts: VecDeque<Burst>,
+}
+
+impl ArrivalTiming {
+    pub fn record(&mut self, at: Instant) {
+        let continues_burst = self
+            .last_packet
+            .is_some_and(|last| at.saturating_duration_since(last) < BURST_GAP);
+        self.last_packet = Some(at);
+
+        if continues_burst {
+            if let Some(current) = self.current.as_mut() {
+                current.packets += 1;
+            }
+            return;
+        }
+
+        if let Some(done) = self.current.take() {
+            if self.bursts.len() == WINDOW {
+                self.bursts.pop_front();
+            }
+            self.bursts.push_back(done);
+        }
+        if let Some(start) = self.burst_start {
+            self.current = Some(Burst {
+                gap: at.saturating_duration_since(start).as_secs_f64(),
+                packets: 1,
+            });
+        }
+        self.burst_start = Some(at);
+    }
+
+    /// Estimates over the recent window; `time_scale` converts our clock to the
+    /// traffic's clock (replay speed).
+    pub fn quality(&self, time_scale: f64) -> Option<LinkQuality> {
+        if self.bursts.len() < MIN_BURSTS {
+            return None;
+        }
+
+        let period = median(self.bursts.iter().map(|b| b.gap))?;
+        let typical_packets = median(self.bursts.iter().map(|b| f64::from(b.packets)))?;
+        if period <= 0.0 {
+            return None;
+        }
+
+        let mut jitter = 0.0;
+        let mut expected = 0.0;
+        let mut lost = 0.0;
+        for burst in &self.bursts {
+            let ticks = (burst.gap / period).round().max(1.0);
+            if ticks > PAUSE_TICKS {
+                continue;
+            }
+            // Deviation from the tick grid; a missed tick is loss, not jitter.
+            let d = burst.gap - ticks * period;
+            jitter += (d.abs() - jitter) * JITTER_GAIN;
+
+            let missing = ticks - 1.0;
+            let late =
+                ((f64::from(burst.packets) / typical_packets).round() - 1.0).clamp(0.0, missing);
+            expected += ticks;
+            lost += missing - late;
+        }
+
+        Some(LinkQuality {
+            jitter_ms: (jitter * time_scale * 1000.0) as f32,
+            tick_rate_hz: (1.0 / (period * time_scale)) as f32,
+            loss_pct: (lost / expected * 100.0) as f32,
+        })
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut values: Vec<f64> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds bursts of `packets` packets 0.5 ms apart starting at each offset (ms).
+    fn timing(
+        starts_ms: impl IntoIterator<Item = f64>,
+        packets: impl Fn(usize) -> u32,
+    ) -> ArrivalTiming {
+        let t0 = Instant::now();
+        let mut timing = ArrivalTiming::default();
+        for (i, start) in starts_ms.into_iter().enumerate() {
+            for p in 0..packets(i) {
+                let offset = start + f64::from(p) * 0.5;
+                timing.record(t0 + Duration::from_secs_f64(offset / 1000.0));
+            }
+        }
+        timing
+    }
+
+    #[test]
+    fn steady_ticks_have_no_jitter_or_loss() {
+        let q = timing((0..100).map(|i| f64::from(i) * 1000.0 / 64.0), |_| 3)
+            .quality(1.0)
+            .unwrap();
+        assert!((q.tick_rate_hz - 64.0).abs() < 0.5, "{q:?}");
+        assert!(q.jitter_ms < 0.01, "{q:?}");
+        assert_eq!(q.loss_pct, 0.0);
+    }
+
+    #[test]
+    fn late_ticks_show_as_jitter() {
+        // 30 Hz with every fourth tick 4 ms late.
+        let starts = (0..100).map(|i| f64::from(i) * 33.0 + if i % 4 == 1 { 4.0 } else { 0.0 });
+        let q = timing(starts, |_| 1).quality(1.0).unwrap();
+        assert!((q.tick_rate_hz - 1000.0 / 33.0).abs() < 0.1, "{q:?}");
+        assert!(q.jitter_ms > 1.0, "{q:?}");
+        assert_eq!(q.loss_pct, 0.0);
+    }
+
+    #[test]
+    fn missing_ticks_count_as_loss() {
+        // 20 Hz, with ticks 30-39 never arriving: 10 of 100 lost.
+        let starts = (0..100)
+            .filter(|i| !(30..40).contains(i))
+            .map(|i| f64::from(i) * 50.0);
+        let q = timing(starts, |_| 2).quality(1.0).unwrap();
+        assert!((q.tick_rate_hz - 20.0).abs() < 0.1, "{q:?}");
+        assert!((q.loss_pct - 100.0 * 10.0 / 99.0).abs() < 0.5, "{q:?}");
+        assert!(q.jitter_ms < 0.01, "{q:?}");
+    }
+
+    #[test]
+    fn a_flow_that_goes_idle_and_resumes_has_no_loss() {
+        // 20 Hz, silent for 30 s after tick 50, then back at the same rate.
+        let starts = (0..100).map(|i| f64::from(i) * 50.0 + if i >= 50 { 30_000.0 } else { 0.0 });
+        let q = timing(starts, |_| 2).quality(1.0).unwrap();
+        assert!((q.tick_rate_hz - 20.0).abs() < 0.1, "{q:?}");
+        assert_eq!(q.loss_pct, 0.0, "{q:?}");
+        assert!(q.jitter_ms < 0.01, "{q:?}");
+    }
+
+    #[test]
+    fn late_ticks_in_a_catch_up_burst_are_not_loss() {
+        // Tick 50 is held back and delivered together with tick 51.
+        let starts = (0..100).filter(|i| *i != 50).map(|i| f64::from(i) * 50.0);
+        let q = timing(starts, |i| if i == 50 { 4 } else { 2 })
+            .quality(1.0)
+            .unwrap();
+        assert_eq!(q.loss_pct, 0.0, "{q:?}");
+    }
+
+    #[test]
+    fn needs_enough_bursts_and_scales_replays() {
+        let few = timing((0..10).map(|i| f64::from(i) * 10.0), |_| 1);
+        assert_eq!(few.quality(1.0), None);
+
+        // A 4x replay compresses 16 Hz into 64 Hz of wall clock.
+        let fast = timing((0..100).map(|i| f64::from(i) * 1000.0 / 64.0), |_| 1);
+        let q = fast.quality(4.0).unwrap();
+        assert!((q.tick_rate_hz - 16.0).abs() < 0.2, "{q:?}");
+    }
+}
@@ -0,0 +1,293 @@
+//! Capture-to-file for bug reports: the monitor's game packets written to pcapng.
+//!
+//! A recording stops itself at its size or duration cap; the file stays valid at any point
+//! because every packet is a complete block.
+
+use crate::pcap::PcapNgWriter;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(30 * 60);
+const APPLICATION: &str = "PingPal";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Stopped,
+    SizeLimit,
+    DurationLimit,
+    WriteError,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingStatus {
+    pub path: String,
+    pub active: bool,
+    pub packets: u64,
+    pub bytes: u64,
+    pub elapsed_secs: u64,
+    pub stop_reason: Option<StopReason>,
+}
+
+pub struct Recording<W: Write = BufWriter<File>> {
+    writer: Option<PcapNgWriter<W>>,
+    path: PathBuf,
+    started: Instant,
+    max_bytes: u64,
+    max_duration: Duration,
+    packets: u64,
+    /// Frozen size and duration once the writer is closed.
+    bytes_final: u64,
+    elapsed: Duration,
+    stop_reason: Option<StopReason>,
+    process: Option<(String, u32)>,
+    pending_comment: Option<String>,
+}
+
+impl Recording {
+    pub fn create(
+        path: &Path,
+        label: &str,
+        max_bytes: u64,
+        max_duration: Duration,
+    ) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(file, path, label, max_bytes, max_duration)
+    }
+}
+
+impl<W: Write> Recording<W> {
+    pub fn new(
+        inner: W,
+        path: &Path,
+        label: &str,
+        max_bytes: u64,
+        max_duration: Duration,
+    ) -> io::Result<Self> {
+        let comment = format!("{} capture of {}", APPLICATION, label);
+        Ok(Self {
+            writer: Some(PcapNgWriter::new(inner, APPLICATION, Some(&comment))?),
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            max_bytes,
+            max_duration,
+            packets: 0,
+            bytes_final: 0,
+            elapsed: Duration::ZERO,
+            stop_reason: None,
+            process: None,
+            pending_comment: None,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Notes which process the following packets belong to; a change is written as a
+    /// comment on the next packet so restarts of the game are visible in the file.
+    pub fn set_process(&mut self, name: &str, pid: u32) {
+        let changed = self
+            .process
+            .as_ref()
+            .is_none_or(|(n, p)| n != name || *p != pid);
+        if changed {
+            self.process = Some((name.to_string(), pid));
+            self.pending_comment = Some(format!("process {} pid {}", name, pid));
+        }
+    }
+
+    pub fn write(&mut self, packet: &[u8]) {
+        if !self.enforce_limits() {
+            return;
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        // EPB framing and padding, plus the comment option and its terminator.
+        let comment_len = self
+            .pending_comment
+            .as_ref()
+            .map_or(0, |c| c.len() as u64 + 11);
+        let projected = writer.bytes_written() + packet.len() as u64 + 35 + comment_len;
+        if projected > self.max_bytes {
+            self.finish(StopReason::SizeLimit);
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let comment = self.pending_comment.take();
+        match writer.write_packet(timestamp, packet, comment.as_deref()) {
+            Ok(()) => self.packets += 1,
+            Err(e) => {
+                tracing::warn!(error = %e, path = %self.path.display(), "capture write failed");
+                self.finish(StopReason::WriteError);
+            }
+        }
+    }
+
+    /// Stops the recording once its duration cap has passed. Returns whether it is still active.
+    pub fn enforce_limits(&mut self) -> bool {
+        if self.is_active() && self.started.elapsed() >= self.max_duration {
+            self.finish(StopReason::DurationLimit);
+        }
+        self.is_active()
+    }
+
+    pub fn finish(&mut self, reason: StopReason) {
+        let Some(mut writer) = self.writer.take() else {
+            return;
+        };
+        self.elapsed = self.started.elapsed();
+        self.stop_reason = Some(reason);
+        if let Err(e) = writer.flush() {
+            tracing::warn!(error = %e, path = %self.path.display(), "capture flush failed");
+            self.stop_reason = Some(StopReason::WriteError);
+        }
+        self.bytes_final = writer.bytes_written();
+        tracing::info!(
+            path = %self.path.display(),
+            packets = self.packets,
+            reason = ?self.stop_reason,
+            "capture recording finished"
+        );
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        let (bytes, elapsed) = match &self.writer {
+            Some(writer) => (writer.bytes_written(), self.started.elapsed()),
+            None => (self.bytes_final, self.elapsed),
+        };
+        RecordingStatus {
+            path: self.path.display().to_string(),
+            active: self.is_active(),
+            packets: self.packets,
+            bytes,
+            elapsed_secs: elapsed.as_secs(),
+            stop_reason: self.stop_reason,
+        }
+    }
+}
+
+/// `<dir>/<label>-<local time>.pcapng`, with the label reduced to filename-safe characters.
+pub fn default_path(dir: &Path, label: &str) -> PathBuf {
+    let safe: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    dir.join(format!("{}-{}.pcapng", safe, stamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcap::PcapReader;
+
+    fn packet(len: usize) -> Vec<u8> {
+        let mut p = vec![0u8; len];
+        p[0] = 0x45;
+        p
+    }
+
+    fn recording(
+        buf: &mut Vec<u8>,
+        max_bytes: u64,
+        max_duration: Duration,
+    ) -> Recording<&mut Vec<u8>> {
+        Recording::new(
+            buf,
+            Path::new("test.pcapng"),
+            "TslGame.exe",
+            max_bytes,
+            max_duration,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn records_packets_that_replay() {
+        let mut buf = Vec::new();
+        let mut rec = recording(&mut buf, DEFAULT_MAX_BYTES, DEFAULT_MAX_DURATION);
+        rec.set_process("TslGame.exe", 4242);
+        rec.write(&packet(40));
+        rec.set_process("TslGame.exe", 4242);
+        rec.write(&packet(60));
+        rec.finish(StopReason::Stopped);
+        rec.write(&packet(60));
+
+        let status = rec.status();
+        assert!(!status.active);
+        assert_eq!(status.packets, 2);
+        assert_eq!(status.stop_reason, Some(StopReason::Stopped));
+        drop(rec);
+        assert_eq!(status.bytes as usize, buf.len());
+
+        let mut reader = PcapReader::new(buf.as_slice()).unwrap();
+        assert_eq!(reader.next_frame().unwrap().unwrap().data.len(), 40);
+        assert_eq!(reader.next_frame().unwrap().unwrap().data.len(), 60);
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn only_announces_process_changes() {
+        let mut buf = Vec::new();
+        let mut rec = recording(&mut buf, DEFAULT_MAX_BYTES, DEFAULT_MAX_DURATION);
+        rec.set_process("TslGame.exe", 1);
+        assert!(rec.pending_comment.is_some());
+        rec.write(&packet(20));
+        rec.set_process("TslGame.exe", 1);
+        assert!(rec.pending_comment.is_none());
+        rec.set_process("TslGame.exe", 2);
+        assert_eq!(
+            rec.pending_comment.as_deref(),
+            Some("process TslGame.exe pid 2")
+        );
+    }
+
+    #[test]
+    fn stops_at_size_cap() {
+        let mut buf = Vec::new();
+        let mut rec = recording(&mut buf, 400, DEFAULT_MAX_DURATION);
+        for _ in 0..10 {
+            rec.write(&packet(100));
+        }
+        let status = rec.status();
+        assert_eq!(status.stop_reason, Some(StopReason::SizeLimit));
+        assert!(status.bytes <= 400);
+        assert!(status.packets >= 1);
+    }
+
+    #[test]
+    fn stops_at_duration_cap() {
+        let mut buf = Vec::new();
+        let mut rec = recording(&mut buf, DEFAULT_MAX_BYTES, Duration::ZERO);
+        assert!(!rec.enforce_limits());
+        rec.write(&packet(20));
+        assert_eq!(rec.status().packets, 0);
+        assert_eq!(rec.status().stop_reason, Some(StopReason::DurationLimit));
+    }
+
+    #[test]
+    fn default_path_sanitizes_label() {
+        let path = default_path(Path::new("caps"), "My Game/x.exe");
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("My_Game_x.exe-"));
+        assert!(name.ends_with(".pcapng"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Routing detected game servers through the tunnel without user action.
+//!
+//! A profile may list `route_rules`. Each tick, the monitor hands the game servers it
+//! classified to [`AutoRouter::due`], and any server matching a rule is routed. A rule's
+//! conditions must all hold; a condition left out holds for everything:
+//!
+//! ```json
+//! "route_rules": [
+//!   { "name": "EU servers", "min_confidence": 0.8, "countries": ["DE", "NL"] },
+//!   { "cidrs": ["203.0.113.0/24"], "ports": ["7000-7999"] }
+//! ]
+//! ```
+//!
+//! A server must keep matching for [`DEBOUNCE`] before it is routed, so a flow that looks
+//! like a game for one tick is left alone. Addresses the tunnel already covers, or that
+//! are being routed, are skipped; failures are retried after [`RETRY_AFTER`]. Every
+//! attempt is kept in an audit log.
+
+use crate::netaddr::{Cidr, PortRange};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// How long a server must keep matching a rule before it is routed.
+pub const DEBOUNCE: Duration = Duration::from_secs(4);
+pub const RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Oldest decisions are dropped beyond this many.
+const MAX_LOG_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RouteRule {
+    /// Shown in the audit log; the rule's position when absent.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Overrides the profile's game-server confidence threshold when higher.
+    #[serde(default)]
+    pub min_confidence: Option<f32>,
+    /// ISO country codes of the server, e.g. `"DE"`.
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub cidrs: Vec<Cidr>,
+    /// Remote ports of the server.
+    #[serde(default)]
+    pub ports: Vec<PortRange>,
+}
+
+impl RouteRule {
+    pub fn matches(&self, server: &Candidate) -> bool {
+        self.min_confidence
+            .is_none_or(|min| server.confidence >= min)
+            && (self.countries.is_empty()
+                || server.country_code.as_deref().is_some_and(|code| {
+                    self.countries.iter().any(|c| c.eq_ignore_ascii_case(code))
+                }))
+            && (self.cidrs.is_empty()
+                || self
+                    .cidrs
+                    .iter()
+                    .any(|cidr| cidr.contains(server.ip.into())))
+            && (self.ports.is_empty() || self.ports.iter().any(|r| r.contains(server.port)))
+    }
+
+    fn label(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("rule {}", index + 1))
+    }
+}
+
+/// A classified game server the tunnel could carry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub confidence: f32,
+    pub country_code: Option<String>,
+}
+
+/// A server to route now, and the rule that selected it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Due {
+    pub server: Candidate,
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteOutcome {
+    Routed,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteDecision {
+    /// RFC 3339 local time.
+    pub at: String,
+    pub session_id: String,
+    pub ip: String,
+    pub port: u16,
+    pub rule: String,
+    pub confidence: f32,
+    pub country_code: Option<String>,
+    pub outcome: RouteOutcome,
+    /// What the routing step reported.
+    pub detail: String,
+}
+
+#[derive(Default)]
+pub struct AutoRouter {
+    /// When each session's server started matching, for the debounce.
+    pending: HashMap<(String, Ipv4Addr), Instant>,
+    in_flight: HashSet<Ipv4Addr>,
+    retry_at: HashMap<Ipv4Addr, Instant>,
+    log: VecDeque<RouteDecision>,
+}
+
+impl AutoRouter {
+    /// Servers of `session` to route now. `servers` are the session's game servers this
+    /// tick; those that stopped matching start their debounce over. Returned servers are
+    /// in flight until [`AutoRouter::finish`].
+    pub fn due(
+        &mut self,
+        session: &str,
+        servers: &[Candidate],
+        rules: &[RouteRule],
+        covered: impl Fn(Ipv4Addr) -> bool,
+        now: Instant,
+    ) -> Vec<Due> {
+        let mut matched: Vec<Due> = Vec::new();
+        for server in servers {
+            if matched.iter().any(|m| m.server.ip == server.ip) {
+                continue;
+            }
+            if let Some((index, rule)) = rules.iter().enumerate().find(|(_, r)| r.matches(server)) {
+                matched.push(Due {
+                    server: server.clone(),
+                    rule: rule.label(index),
+                });
+            }
+        }
+
+        self.pending.retain(|(pending_session, ip), _| {
+            pending_session != session || matched.iter().any(|m| m.server.ip == *ip)
+        });
+        self.retry_at.retain(|_, at| *at > now);
+
+        let mut due = Vec::new();
+        for candidate in matched {
+            let ip = candidate.server.ip;
+            let key = (session.to_string(), ip);
+            if covered(ip) {
+                self.pending.remove(&key);
+                continue;
+            }
+            if self.in_flight.contains(&ip) || self.retry_at.contains_key(&ip) {
+                continue;
+            }
+            let since = *self.pending.entry(key.clone()).or_insert(now);
+            if now.saturating_duration_since(since) >= DEBOUNCE {
+                self.pending.remove(&key);
+                self.in_flight.insert(ip);
+                due.push(candidate);
+            }
+        }
+        due
+    }
+
+    /// Records how routing `due` went.
+    pub fn finish(
+        &mut self,
+        session: &str,
+        due: &Due,
+        result: Result<String, String>,
+        now: Instant,
+    ) -> RouteDecision {
+        let ip = due.server.ip;
+        self.in_flight.remove(&ip);
+        let (outcome, detail) = match result {
+            Ok(detail) => (RouteOutcome::Routed, detail),
+            Err(detail) => {
+                self.retry_at.insert(ip, now + RETRY_AFTER);
+                (RouteOutcome::Failed, detail)
+            }
+        };
+        let decision = RouteDecision {
+            at: chrono::Local::now().to_rfc3339(),
+            session_id: session.to_string(),
+            ip: ip.to_string(),
+            port: due.server.port,
+            rule: due.rule.clone(),
+            confidence: due.server.confidence,
+            country_code: due.server.country_code.clone(),
+            outcome,
+            detail,
+        };
+        if self.log.len() == MAX_LOG_ENTRIES {
+            self.log.pop_front();
+        }
+        self.log.push_back(decision.clone());
+        decision
+    }
+
+    /// Newest decisions first.
+    pub fn log(&self, limit: usize) -> Vec<RouteDecision> {
+        self.log.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(ip: &str, port: u16, confidence: f32, country: Option<&str>) -> Candidate {
+        Candidate {
+            ip: ip.parse().unwrap(),
+            port,
+            confidence,
+            country_code: country.map(str::to_string),
+        }
+    }
+
+    fn rules(json: &str) -> Vec<RouteRule> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn rules_need_every_condition() {
+        let rules = rules(
+            r#"[{"name": "EU", "min_confidence": 0.8, "countries": ["de", "NL"],
+                 "cidrs": ["203.0.113.0/24"], "ports": ["7000-7999"]}]"#,
+        );
+        let rule = &rules[0];
+        assert!(rule.matches(&server("203.0.113.7", 7086, 0.9, Some("DE"))));
+        assert!(!rule.matches(&server("203.0.113.7", 7086, 0.7, Some("DE"))));
+        assert!(!rule.matches(&server("203.0.113.7", 7086, 0.9, Some("US"))));
+        assert!(!rule.matches(&server("203.0.113.7", 7086, 0.9, None)));
+        assert!(!rule.matches(&server("198.51.100.7", 7086, 0.9, Some("DE"))));
+        assert!(!rule.matches(&server("203.0.113.7", 443, 0.9, Some("DE"))));
+        assert!(RouteRule::default().matches(&server("198.51.100.7", 1, 0.0, None)));
+    }
+
+    #[test]
+    fn routes_after_the_debounce_once() {
+        let mut router = AutoRouter::default();
+        let rules = rules(r#"[{"min_confidence": 0.5}, {"name": "any"}]"#);
+        let t0 = Instant::now();
+        let servers = [
+            server("203.0.113.7", 7086, 0.9, None),
+            server("203.0.113.7", 7087, 0.9, None),
+            server("198.51.100.2", 7086, 0.1, None),
+        ];
+
+        assert!(router
+            .due("pubg", &servers, &rules, |_| false, t0)
+            .is_empty());
+        let due = router.due("pubg", &servers, &rules, |_| false, t0 + DEBOUNCE);
+        assert_eq!(
+            due.iter()
+                .map(|d| (d.server.ip.to_string(), d.rule.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("203.0.113.7".to_string(), "rule 1"),
+                ("198.51.100.2".to_string(), "any")
+            ]
+        );
+
+        // In flight, then covered by the new route: never routed twice.
+        let later = t0 + DEBOUNCE * 3;
+        assert!(router
+            .due("pubg", &servers, &rules, |_| false, later)
+            .is_empty());
+        let decision = router.finish("pubg", &due[0], Ok("added".to_string()), later);
+        assert_eq!(decision.outcome, RouteOutcome::Routed);
+        let covered = |ip: Ipv4Addr| ip == due[0].server.ip;
+        assert!(router
+            .due("pubg", &servers, &rules, covered, later)
+            .is_empty());
+        assert_eq!(router.log(10), [decision]);
+    }
+
+    #[test]
+    fn flapping_servers_restart_the_debounce_and_failures_wait() {
+        let mut router = AutoRouter::default();
+        let rules = rules(r#"[{"countries": ["JP"]}]"#);
+        let t0 = Instant::now();
+        let tokyo = [server("203.0.113.7", 7086, 0.9, Some("JP"))];
+
+        router.due("pubg", &tokyo, &rules, |_| false, t0);
+        router.due("pubg", &[], &rules, |_| false, t0 + DEBOUNCE / 2);
+        assert!(router
+            .due("pubg", &tokyo, &rules, |_| false, t0 + DEBOUNCE)
+            .is_empty());
+        let t1 = t0 + DEBOUNCE * 2;
+        let due = router.due("pubg", &tokyo, &rules, |_| false, t1);
+        assert_eq!(due.len(), 1);
+
+        let decision = router.finish("pubg", &due[0], Err("tunnel down".to_string()), t1);
+        assert_eq!(decision.outcome, RouteOutcome::Failed);
+        assert!(router
+            .due("pubg", &tokyo, &rules, |_| false, t1 + DEBOUNCE)
+            .is_empty());
+        let retry = t1 + RETRY_AFTER;
+        router.due("pubg", &tokyo, &rules, |_| false, retry);
+        assert_eq!(
+            router
+                .due("pubg", &tokyo, &rules, |_| false, retry + DEBOUNCE)
+                .len(),
+            1
+        );
+    }
+}
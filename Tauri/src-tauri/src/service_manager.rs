@@ -1,4 +1,8 @@
-use std::process::Command;
+use crate::cmd_parsers::{
+    self, ServiceState, ERROR_SERVICE_ALREADY_RUNNING, ERROR_SERVICE_DOES_NOT_EXIST,
+    ERROR_SERVICE_NOT_ACTIVE,
+};
+use std::process::{Command, Output};
 
 const SERVICE_NAME: &str = "PingPalWGEngine";
 #[allow(dead_code)]
@@ -25,6 +29,25 @@ fn sc_says_service_missing(stdout: &str, stderr: &str) -> bool {
     c.contains("1060") || c.contains("does not exist as an installed service")
 }
 
+// sc.exe exits with the Win32 error code, which unlike its message text is not localized.
+fn sc_exit_code_is(output: &Output, code: i32) -> bool {
+    output.status.code() == Some(code)
+}
+
+fn query_service_state() -> Result<Option<ServiceState>, String> {
+    let output = Command::new("sc.exe")
+        .args(["query", SERVICE_NAME])
+        .output()
+        .map_err(|e| format!("sc query failed: {}", e))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(cmd_parsers::parse_sc_query_state(&cmd_parsers::decode_console_output(
+        &output.stdout,
+    )))
+}
+
 pub fn create_service(
     wrapper_path: &str,
     engine_path: &str,
@@ -42,10 +65,12 @@ pub fn create_service(
             .map_err(|e| format!("sc.exe delete failed: {}", e))?;
 
         if !delete_output.status.success() {
-            let stderr = String::from_utf8_lossy(&delete_output.stderr);
-            let stdout = String::from_utf8_lossy(&delete_output.stdout);
+            let stderr = cmd_parsers::decode_console_output(&delete_output.stderr);
+            let stdout = cmd_parsers::decode_console_output(&delete_output.stdout);
             
-            if !sc_says_service_missing(&stdout, &stderr) {
+            if !sc_exit_code_is(&delete_output, ERROR_SERVICE_DOES_NOT_EXIST)
+                && !sc_says_service_missing(&stdout, &stderr)
+            {
                 tracing::warn!("could not delete existing service, will reuse: {} {}", stdout, stderr);
                 return Ok(());
             }
@@ -76,8 +101,8 @@ pub fn create_service(
         .output()
         .map_err(|e| format!("sc.exe create failed: {}", e))?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = cmd_parsers::decode_console_output(&output.stdout);
+    let stderr = cmd_parsers::decode_console_output(&output.stderr);
 
     tracing::trace!(%stdout, %stderr, code = ?output.status.code(), "sc.exe create output");
 
@@ -100,13 +125,15 @@ pub fn start_service() -> Result<(), String> {
         .output()
         .map_err(|e| format!("sc.exe start failed: {}", e))?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = cmd_parsers::decode_console_output(&output.stdout);
+    let stderr = cmd_parsers::decode_console_output(&output.stderr);
 
     tracing::trace!(%stdout, %stderr, code = ?output.status.code(), "sc.exe start output");
 
     if !output.status.success() {
-        if sc_says_already_running(&stdout, &stderr) {
+        if sc_exit_code_is(&output, ERROR_SERVICE_ALREADY_RUNNING)
+            || sc_says_already_running(&stdout, &stderr)
+        {
             tracing::debug!("service already running");
             return Ok(());
         }
@@ -130,10 +157,12 @@ pub fn stop_service() -> Result<(), String> {
         .map_err(|e| format!("sc stop failed: {}", e))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = cmd_parsers::decode_console_output(&output.stderr);
+        let stdout = cmd_parsers::decode_console_output(&output.stdout);
 
-        if sc_says_not_running(&stdout, &stderr) {
+        if sc_exit_code_is(&output, ERROR_SERVICE_NOT_ACTIVE)
+            || sc_says_not_running(&stdout, &stderr)
+        {
             tracing::debug!("service was not running");
             return Ok(());
         }
@@ -154,14 +183,9 @@ pub fn delete_service() -> Result<(), String> {
     std::thread::sleep(std::time::Duration::from_millis(1500));
 
     for attempt in 0..5 {
-        let output = Command::new("sc.exe")
-            .args(["query", SERVICE_NAME])
-            .output()
-            .map_err(|e| format!("sc query failed: {}", e))?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        let state = query_service_state()?;
 
-        if stdout.contains("STOPPED") || !output.status.success() {
+        if matches!(state, None | Some(ServiceState::Stopped)) {
             tracing::debug!(attempt, "service confirmed stopped");
             break;
         }
@@ -178,10 +202,12 @@ pub fn delete_service() -> Result<(), String> {
         .map_err(|e| format!("sc delete failed: {}", e))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = cmd_parsers::decode_console_output(&output.stderr);
+        let stdout = cmd_parsers::decode_console_output(&output.stdout);
 
-        if sc_says_service_missing(&stdout, &stderr) {
+        if sc_exit_code_is(&output, ERROR_SERVICE_DOES_NOT_EXIST)
+            || sc_says_service_missing(&stdout, &stderr)
+        {
             tracing::debug!("service did not exist");
             return Ok(());
         }
@@ -202,21 +228,17 @@ pub fn service_exists() -> Result<bool, String> {
     Ok(output.status.success())
 }
 
+pub fn is_service_running() -> Result<bool, String> {
+    Ok(query_service_state()? == Some(ServiceState::Running))
+}
+
 pub fn wait_for_service_running(timeout_secs: u64) -> Result<(), String> {
     let start = std::time::Instant::now();
 
     while start.elapsed().as_secs() < timeout_secs {
-        let output = Command::new("sc.exe")
-            .args(["query", SERVICE_NAME])
-            .output()
-            .map_err(|e| format!("sc query failed: {}", e))?;
-
-        if output.status.success() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if stdout.contains("RUNNING") {
-                tracing::debug!("service is RUNNING");
-                return Ok(());
-            }
+        if is_service_running()? {
+            tracing::debug!("service is RUNNING");
+            return Ok(());
         }
 
         std::thread::sleep(std::time::Duration::from_millis(500));
@@ -0,0 +1,108 @@
+use crate::{game_watcher, hop_probe, network_monitor, vpn};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::sync::Mutex;
+
+// Windows kills the process about 5 s after CTRL_CLOSE_EVENT or CTRL_SHUTDOWN_EVENT.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(4);
+
+const IDLE: u8 = 0;
+const RUNNING: u8 = 1;
+const DONE: u8 = 2;
+
+static PHASE: AtomicU8 = AtomicU8::new(IDLE);
+
+async fn stop_monitoring<R: Runtime>(app: &AppHandle<R>) {
+    // Otherwise it could start a session for a running game while the others stop.
+    if let Some(watcher) = app.try_state::<Arc<game_watcher::GameWatcher>>() {
+        watcher.shutdown().await;
+    }
+    if let Some(state) = app.try_state::<Arc<Mutex<network_monitor::MonitorState>>>() {
+        network_monitor::shutdown(state.inner()).await;
+    }
+    if let Some(state) = app.try_state::<Arc<Mutex<hop_probe::HopProbeState>>>() {
+        hop_probe::shutdown(state.inner()).await;
+    }
+}
+
+async fn shutdown_all<R: Runtime>(app: &AppHandle<R>) {
+    // The tunnel comes down alongside, so joining slow monitor threads cannot use up the
+    // time its routes need to be removed.
+    tokio::join!(stop_monitoring(app), vpn::shutdown(app));
+}
+
+async fn run_bounded<R: Runtime>(app: &AppHandle<R>) {
+    tracing::info!("shutting down");
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, shutdown_all(app))
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            timeout_secs = SHUTDOWN_TIMEOUT.as_secs(),
+            "shutdown timed out; exiting anyway"
+        );
+    }
+    PHASE.store(DONE, Ordering::SeqCst);
+    tracing::info!("shutdown complete");
+}
+
+/// Called on `RunEvent::ExitRequested`. Returns `true` once cleanup has finished and the
+/// exit may proceed; otherwise cleanup is started (once) and the app exits when it is done.
+pub fn handle_exit_requested<R: Runtime>(app: &AppHandle<R>) -> bool {
+    match PHASE.compare_exchange(IDLE, RUNNING, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                run_bounded(&app).await;
+                app.exit(0);
+            });
+            false
+        }
+        Err(phase) => phase == DONE,
+    }
+}
+
+/// Last-chance cleanup on `RunEvent::Exit` for exits that bypassed `ExitRequested`.
+pub fn handle_exit<R: Runtime>(app: &AppHandle<R>) {
+    if PHASE
+        .compare_exchange(IDLE, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        tauri::async_runtime::block_on(run_bounded(app));
+    }
+}
+
+async fn wait_for_signal() {
+    #[cfg(windows)]
+    {
+        use tokio::signal::windows::{ctrl_close, ctrl_shutdown};
+
+        let (Ok(mut close), Ok(mut shutdown)) = (ctrl_close(), ctrl_shutdown()) else {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = close.recv() => {}
+            _ = shutdown.recv() => {}
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Routes Ctrl+C and console close/shutdown signals through the same cleanup path.
+pub fn listen_for_signals<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!("termination signal received");
+        if handle_exit_requested(&app) {
+            app.exit(0);
+        }
+    });
+}
@@ -0,0 +1,116 @@
+//! Whether a detected server's traffic actually goes through the tunnel.
+//!
+//! The capture tags every packet with the interface it was seen on. A server the tunnel
+//! should carry, because one of our routes covers it while connected, is `vpn` when its
+//! packets show up on the tunnel adapter and `leaking` when any show up elsewhere. A server
+//! outside our routes is `direct`. The WireGuard peer's transfer counters back up the
+//! tunnel side: packets seen on the adapter while the peer transferred nothing are not
+//! reported as tunneled.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+
+/// Emitted with the server when one the tunnel should carry is seen going direct.
+pub const SERVER_LEAKING_EVENT: &str = "server-leaking";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelStatus {
+    /// Carried by the tunnel.
+    Vpn,
+    /// Not routed through the tunnel, and not meant to be.
+    Direct,
+    /// Routed through the tunnel, yet seen on another interface.
+    Leaking,
+}
+
+/// A server's packets, by whether the tunnel adapter carried them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathCounts {
+    pub tunnel: u64,
+    pub other: u64,
+}
+
+impl PathCounts {
+    /// Splits per-interface packet counts; `tunnel` holds the tunnel adapter's indexes.
+    pub fn split(interfaces: &BTreeMap<u32, u64>, tunnel: &HashSet<u32>) -> Self {
+        interfaces
+            .iter()
+            .fold(Self::default(), |mut counts, (index, packets)| {
+                if tunnel.contains(index) {
+                    counts.tunnel += packets;
+                } else {
+                    counts.other += packets;
+                }
+                counts
+            })
+    }
+}
+
+/// Whether the tunnel should carry `ip`: it is connected and one of our routes covers the
+/// address. Our routes are IPv4, so only a full tunnel is meant to carry IPv6.
+pub fn expected_in_tunnel(ip: IpAddr) -> bool {
+    crate::vpn::active_config().is_some()
+        && match ip {
+            IpAddr::V4(ip) => crate::vpn::routes_cover(ip),
+            IpAddr::V6(_) => crate::vpn::is_full_tunnel_active(),
+        }
+}
+
+/// Status from the packets seen since the last check. `peer_moved` is whether the peer's
+/// transfer counters grew meanwhile, `None` when unknown. `None` when nothing was seen.
+pub fn status(expected: bool, seen: PathCounts, peer_moved: Option<bool>) -> Option<TunnelStatus> {
+    if seen.other > 0 {
+        return Some(if expected {
+            TunnelStatus::Leaking
+        } else {
+            TunnelStatus::Direct
+        });
+    }
+    (seen.tunnel > 0 && peer_moved != Some(false)).then_some(TunnelStatus::Vpn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_packets_by_interface() {
+        let interfaces = BTreeMap::from([(7, 10), (23, 90), (31, 5)]);
+        let tunnel = HashSet::from([23, 31]);
+        assert_eq!(
+            PathCounts::split(&interfaces, &tunnel),
+            PathCounts {
+                tunnel: 95,
+                other: 10
+            }
+        );
+        assert_eq!(PathCounts::split(&interfaces, &HashSet::new()).other, 105);
+    }
+
+    #[test]
+    fn any_packet_off_the_tunnel_is_a_leak_when_it_should_be_on_it() {
+        let tunneled = PathCounts {
+            tunnel: 50,
+            other: 0,
+        };
+        let mixed = PathCounts {
+            tunnel: 50,
+            other: 1,
+        };
+        let direct = PathCounts {
+            tunnel: 0,
+            other: 50,
+        };
+
+        assert_eq!(status(true, tunneled, Some(true)), Some(TunnelStatus::Vpn));
+        assert_eq!(status(true, tunneled, None), Some(TunnelStatus::Vpn));
+        assert_eq!(status(true, mixed, Some(true)), Some(TunnelStatus::Leaking));
+        assert_eq!(status(true, direct, None), Some(TunnelStatus::Leaking));
+        assert_eq!(status(false, direct, None), Some(TunnelStatus::Direct));
+        // The adapter saw the packets but the peer transferred nothing: not confirmed.
+        assert_eq!(status(true, tunneled, Some(false)), None);
+        assert_eq!(status(true, PathCounts::default(), Some(true)), None);
+    }
+}
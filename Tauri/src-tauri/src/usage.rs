@@ -0,0 +1,508 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::sync::{watch, Mutex};
+
+pub const USAGE_FILE: &str = "vpn_usage.json";
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const UNKNOWN_KEY: &str = "unknown";
+/// How long a counter reading is shared before `wg` is asked again. Below the monitor
+/// tick, so each tick of a session sees a newer reading than its last.
+const COUNTERS_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// The newest counter reading and when it was taken. Held across a read, so callers that
+/// arrive meanwhile wait for it instead of spawning `wg` themselves.
+static LATEST_COUNTERS: Mutex<Option<(Instant, CounterSnapshot)>> = Mutex::const_new(None);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteCount {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl ByteCount {
+    fn add(&mut self, rx: u64, tx: u64) {
+        self.rx_bytes += rx;
+        self.tx_bytes += tx;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DayUsage {
+    pub total: ByteCount,
+    pub by_profile: BTreeMap<String, ByteCount>,
+    pub by_game: BTreeMap<String, ByteCount>,
+}
+
+/// Last transfer counters seen for a peer. WireGuard counters restart at zero whenever the
+/// engine restarts, so the listen port (randomized per engine start) is part of the identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterSnapshot {
+    pub public_key: String,
+    pub listen_port: u16,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLedger {
+    pub days: BTreeMap<String, DayUsage>,
+    pub last_counter: Option<CounterSnapshot>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageContext {
+    pub profile_id: Option<String>,
+    pub game_id: Option<String>,
+}
+
+impl UsageLedger {
+    /// Folds a new counter reading into the day's totals and returns the attributed (rx, tx) delta.
+    pub fn apply_sample(
+        &mut self,
+        snapshot: CounterSnapshot,
+        context: &UsageContext,
+        day: &str,
+    ) -> (u64, u64) {
+        let (rx, tx) = match &self.last_counter {
+            Some(last)
+                if last.public_key == snapshot.public_key
+                    && last.listen_port == snapshot.listen_port
+                    && snapshot.rx_bytes >= last.rx_bytes
+                    && snapshot.tx_bytes >= last.tx_bytes =>
+            {
+                (
+                    snapshot.rx_bytes - last.rx_bytes,
+                    snapshot.tx_bytes - last.tx_bytes,
+                )
+            }
+            // New engine or reset counters: everything on the counter is new traffic.
+            _ => (snapshot.rx_bytes, snapshot.tx_bytes),
+        };
+        self.last_counter = Some(snapshot);
+
+        if rx == 0 && tx == 0 {
+            return (0, 0);
+        }
+
+        let entry = self.days.entry(day.to_string()).or_default();
+        entry.total.add(rx, tx);
+        let profile = context.profile_id.as_deref().unwrap_or(UNKNOWN_KEY);
+        entry
+            .by_profile
+            .entry(profile.to_string())
+            .or_default()
+            .add(rx, tx);
+        let game = context.game_id.as_deref().unwrap_or(UNKNOWN_KEY);
+        entry
+            .by_game
+            .entry(game.to_string())
+            .or_default()
+            .add(rx, tx);
+        (rx, tx)
+    }
+
+    /// Forgets the last reading ahead of a new engine, whose counters start at zero even
+    /// when a fixed `ListenPort` and key make it look like the previous one.
+    pub fn start_engine(&mut self) {
+        self.last_counter = None;
+    }
+
+    pub fn report(&self, last_days: Option<usize>) -> UsageReport {
+        let skip = last_days
+            .map(|n| self.days.len().saturating_sub(n))
+            .unwrap_or(0);
+
+        let mut report = UsageReport::default();
+        for (date, usage) in self.days.iter().skip(skip) {
+            report.total.add(usage.total.rx_bytes, usage.total.tx_bytes);
+            for (profile, bytes) in &usage.by_profile {
+                report
+                    .by_profile
+                    .entry(profile.clone())
+                    .or_default()
+                    .add(bytes.rx_bytes, bytes.tx_bytes);
+            }
+            for (game, bytes) in &usage.by_game {
+                report
+                    .by_game
+                    .entry(game.clone())
+                    .or_default()
+                    .add(bytes.rx_bytes, bytes.tx_bytes);
+            }
+            report.days.push(DailyUsageEntry {
+                date: date.clone(),
+                usage: usage.clone(),
+            });
+        }
+        report
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUsageEntry {
+    pub date: String,
+    #[serde(flatten)]
+    pub usage: DayUsage,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub total: ByteCount,
+    pub by_profile: BTreeMap<String, ByteCount>,
+    pub by_game: BTreeMap<String, ByteCount>,
+    pub days: Vec<DailyUsageEntry>,
+}
+
+/// Reads the listen port and first peer's transfer counters from `wg show <if> dump`.
+pub fn parse_wg_dump_counters(dump: &str) -> Option<CounterSnapshot> {
+    let mut lines = dump.lines().filter(|l| !l.trim().is_empty());
+    let iface: Vec<&str> = lines.next()?.split('\t').collect();
+    let listen_port = iface.get(2)?.trim().parse().ok()?;
+
+    for line in lines {
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() >= 8 {
+            return Some(CounterSnapshot {
+                public_key: cols[0].to_string(),
+                listen_port,
+                rx_bytes: cols[5].trim().parse().ok()?,
+                tx_bytes: cols[6].trim().parse().ok()?,
+            });
+        }
+    }
+    None
+}
+
+pub struct UsageTracker {
+    ledger: UsageLedger,
+    path: Option<PathBuf>,
+    context: UsageContext,
+    cancel_token: Option<watch::Sender<bool>>,
+}
+
+impl UsageTracker {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let ledger = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|text| match serde_json::from_str(&text) {
+                Ok(ledger) => Some(ledger),
+                Err(e) => {
+                    tracing::warn!(error = %e, "usage ledger is corrupt; starting fresh");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            ledger,
+            path,
+            context: UsageContext::default(),
+            cancel_token: None,
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let json = match serde_json::to_string(&self.ledger) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to serialize usage ledger");
+                return;
+            }
+        };
+        let tmp = path.with_extension("json.tmp");
+        if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, path)) {
+            tracing::warn!(error = %e, "failed to persist usage ledger");
+        }
+    }
+
+    fn record(&mut self, snapshot: CounterSnapshot) {
+        let day = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let (rx, tx) = self.ledger.apply_sample(snapshot, &self.context, &day);
+        tracing::trace!(rx, tx, "usage sample");
+        self.save();
+    }
+}
+
+async fn read_counters<R: Runtime>(
+    app: &AppHandle<R>,
+    max_age: Duration,
+) -> Result<Option<CounterSnapshot>, String> {
+    let mut latest = LATEST_COUNTERS.lock().await;
+    if let Some((read_at, snapshot)) = latest.as_ref() {
+        if read_at.elapsed() < max_age {
+            return Ok(Some(snapshot.clone()));
+        }
+    }
+    let snapshot = parse_wg_dump_counters(&crate::vpn::wg_show_dump(app).await?);
+    *latest = snapshot.clone().map(|s| (Instant::now(), s));
+    Ok(snapshot)
+}
+
+/// The tunnel peer's transfer counters. One `wg` read serves every caller within
+/// [`COUNTERS_MAX_AGE`], however many sessions ask.
+pub async fn peer_counters<R: Runtime>(app: &AppHandle<R>) -> Option<CounterSnapshot> {
+    match read_counters(app, COUNTERS_MAX_AGE).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            tracing::debug!(error = %e, "could not read peer counters");
+            None
+        }
+    }
+}
+
+async fn sample_once<R: Runtime>(
+    app: &AppHandle<R>,
+    tracker: &Arc<Mutex<UsageTracker>>,
+    max_age: Duration,
+) {
+    match read_counters(app, max_age).await {
+        Ok(Some(snapshot)) => tracker.lock().await.record(snapshot),
+        Ok(None) => tracing::debug!("wg dump had no peer counters"),
+        Err(e) => tracing::debug!(error = %e, "usage sample skipped"),
+    }
+}
+
+/// Starts periodic sampling for a freshly connected tunnel.
+pub async fn start_tracking<R: Runtime>(app: &AppHandle<R>, context: UsageContext) {
+    let Some(state) = app.try_state::<Arc<Mutex<UsageTracker>>>() else {
+        return;
+    };
+    let tracker = state.inner().clone();
+
+    let (cancel_tx, mut cancel_rx) = watch::channel(false);
+    {
+        let mut guard = tracker.lock().await;
+        if let Some(old) = guard.cancel_token.take() {
+            let _ = old.send(true);
+        }
+        guard.context = context;
+        guard.cancel_token = Some(cancel_tx);
+        guard.ledger.start_engine();
+    }
+    // A reading shared before the connect came from the previous engine.
+    *LATEST_COUNTERS.lock().await = None;
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if *cancel_rx.borrow() {
+                        break;
+                    }
+                    sample_once(&app, &tracker, COUNTERS_MAX_AGE).await;
+                }
+                _ = cancel_rx.changed() => {
+                    if *cancel_rx.borrow() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Takes a final sample and stops the sampler; call before the engine is stopped.
+pub async fn stop_tracking<R: Runtime>(app: &AppHandle<R>) {
+    let Some(state) = app.try_state::<Arc<Mutex<UsageTracker>>>() else {
+        return;
+    };
+    let tracker = state.inner().clone();
+
+    let was_tracking = {
+        let mut guard = tracker.lock().await;
+        match guard.cancel_token.take() {
+            Some(cancel_tx) => {
+                let _ = cancel_tx.send(true);
+                true
+            }
+            None => false,
+        }
+    };
+    if was_tracking {
+        // A fresh read, so the bytes of the last moments are counted.
+        sample_once(app, &tracker, Duration::ZERO).await;
+    }
+}
+
+/// Profile id of the tunnel being tracked, if one is up.
+pub async fn active_profile<R: Runtime>(app: &AppHandle<R>) -> Option<String> {
+    let state = app.try_state::<Arc<Mutex<UsageTracker>>>()?;
+    let tracker = state.lock().await;
+    tracker
+        .cancel_token
+        .as_ref()
+        .and(tracker.context.profile_id.clone())
+}
+
+#[tracing::instrument(level = "debug", skip(state))]
+#[tauri::command]
+pub async fn get_vpn_usage(
+    days: Option<usize>,
+    state: tauri::State<'_, Arc<Mutex<UsageTracker>>>,
+) -> Result<UsageReport, String> {
+    let tracker = state.lock().await;
+    Ok(tracker.ledger.report(days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(port: u16, rx: u64, tx: u64) -> CounterSnapshot {
+        CounterSnapshot {
+            public_key: "peer".to_string(),
+            listen_port: port,
+            rx_bytes: rx,
+            tx_bytes: tx,
+        }
+    }
+
+    fn ctx(profile: &str, game: &str) -> UsageContext {
+        UsageContext {
+            profile_id: Some(profile.to_string()),
+            game_id: Some(game.to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_dump_reads_listen_port_and_peer_counters() {
+        let dump = "privkey\tpubkey\t51820\toff\n\
+                    peerkey\t(none)\t203.0.113.10:51820\t0.0.0.0/0\t1700000000\t1234\t5678\t25\n";
+        assert_eq!(
+            parse_wg_dump_counters(dump),
+            Some(CounterSnapshot {
+                public_key: "peerkey".to_string(),
+                listen_port: 51820,
+                rx_bytes: 1234,
+                tx_bytes: 5678,
+            })
+        );
+        assert_eq!(
+            parse_wg_dump_counters("privkey\tpubkey\t51820\toff\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn samples_accumulate_deltas_without_double_counting() {
+        let mut ledger = UsageLedger::default();
+        let c = ctx("p1", "pubg");
+
+        assert_eq!(
+            ledger.apply_sample(snap(1000, 100, 50), &c, "2026-01-01"),
+            (100, 50)
+        );
+        assert_eq!(
+            ledger.apply_sample(snap(1000, 150, 80), &c, "2026-01-01"),
+            (50, 30)
+        );
+        // Same reading again (e.g. app restarted while the engine kept running).
+        assert_eq!(
+            ledger.apply_sample(snap(1000, 150, 80), &c, "2026-01-01"),
+            (0, 0)
+        );
+
+        let day = &ledger.days["2026-01-01"];
+        assert_eq!(
+            day.total,
+            ByteCount {
+                rx_bytes: 150,
+                tx_bytes: 80
+            }
+        );
+        assert_eq!(day.by_profile["p1"], day.total);
+        assert_eq!(day.by_game["pubg"], day.total);
+    }
+
+    #[test]
+    fn engine_restart_counts_new_counter_from_zero() {
+        let mut ledger = UsageLedger::default();
+        let c = ctx("p1", "pubg");
+        ledger.apply_sample(snap(1000, 500, 500), &c, "2026-01-01");
+
+        // New listen port, counters restarted.
+        assert_eq!(
+            ledger.apply_sample(snap(2000, 20, 10), &c, "2026-01-01"),
+            (20, 10)
+        );
+        // Same port but counters went backwards.
+        assert_eq!(
+            ledger.apply_sample(snap(2000, 5, 5), &c, "2026-01-01"),
+            (5, 5)
+        );
+        assert_eq!(ledger.days["2026-01-01"].total.rx_bytes, 525);
+    }
+
+    #[test]
+    fn reconnect_with_a_fixed_listen_port_counts_from_zero() {
+        let mut ledger = UsageLedger::default();
+        let c = ctx("p1", "pubg");
+        ledger.apply_sample(snap(51820, 500, 500), &c, "2026-01-01");
+
+        // Same key and port after a reconnect; the new engine already moved 700 bytes.
+        ledger.start_engine();
+        assert_eq!(
+            ledger.apply_sample(snap(51820, 700, 600), &c, "2026-01-01"),
+            (700, 600)
+        );
+        assert_eq!(ledger.days["2026-01-01"].total.rx_bytes, 1200);
+    }
+
+    #[test]
+    fn report_breaks_down_by_day_and_profile() {
+        let mut ledger = UsageLedger::default();
+        ledger.apply_sample(snap(1, 100, 10), &ctx("p1", "pubg"), "2026-01-01");
+        ledger.apply_sample(snap(1, 300, 30), &ctx("p2", "valorant"), "2026-01-02");
+        ledger.apply_sample(snap(1, 400, 40), &UsageContext::default(), "2026-01-03");
+
+        let all = ledger.report(None);
+        assert_eq!(all.days.len(), 3);
+        assert_eq!(
+            all.total,
+            ByteCount {
+                rx_bytes: 400,
+                tx_bytes: 40
+            }
+        );
+        assert_eq!(
+            all.by_profile["p2"],
+            ByteCount {
+                rx_bytes: 200,
+                tx_bytes: 20
+            }
+        );
+        assert_eq!(
+            all.by_game["unknown"],
+            ByteCount {
+                rx_bytes: 100,
+                tx_bytes: 10
+            }
+        );
+
+        let recent = ledger.report(Some(2));
+        assert_eq!(
+            recent.days.first().map(|d| d.date.as_str()),
+            Some("2026-01-02")
+        );
+        assert_eq!(
+            recent.total,
+            ByteCount {
+                rx_bytes: 300,
+                tx_bytes: 30
+            }
+        );
+    }
+}
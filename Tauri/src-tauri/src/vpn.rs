@@ -1,6 +1,12 @@
+use crate::cmd_parsers;
+use crate::kill_switch::{self, KillSwitchPlan};
 use crate::privileges;
+use crate::usage::{self, UsageContext};
+use crate::netaddr::Cidr;
+use crate::wg_config::WgConfig;
 use std::sync::Mutex;
 use std::fs::File;
+use std::net::{IpAddr, Ipv4Addr};
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
@@ -9,6 +15,9 @@ use tauri_plugin_shell::ShellExt;
 
 struct TempConfigGuard(PathBuf);
 static ACTIVE_ROUTES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+static ACTIVE_CONFIG: Mutex<Option<String>> = Mutex::new(None);
+/// Serializes AllowedIPs read-modify-write cycles so concurrent additions are not lost.
+static ALLOWED_IPS_UPDATE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
 
 impl Drop for TempConfigGuard {
     fn drop(&mut self) {
@@ -17,7 +26,8 @@ impl Drop for TempConfigGuard {
 }
 
 // Tunnel interface name (fixed to avoid clashes with other VPN software)
-const INTERFACE_NAME: &str = "PingPalAdapter";
+pub const INTERFACE_NAME: &str = "PingPalAdapter";
+const TEMP_CONFIG_NAME: &str = "pingpal.conf";
 
 fn route_entry_exists(destination: &str, netmask: &str) -> Result<bool, String> {
     let output = Command::new("route")
@@ -25,11 +35,24 @@ fn route_entry_exists(destination: &str, netmask: &str) -> Result<bool, String>
         .output()
         .map_err(|e| format!("failed to inspect route table: {}", e))?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    Ok(stdout.lines().any(|line| {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        parts.len() >= 2 && parts[0] == destination && parts[1] == netmask
-    }))
+    let (Ok(destination), Ok(netmask)) = (
+        destination.parse::<std::net::Ipv4Addr>(),
+        netmask.parse::<std::net::Ipv4Addr>(),
+    ) else {
+        return Ok(false);
+    };
+    let rows = cmd_parsers::parse_route_print(&cmd_parsers::decode_console_output(&output.stdout));
+    Ok(cmd_parsers::route_exists(&rows, destination, netmask))
+}
+
+fn tunnel_interface_index() -> Result<Option<u32>, String> {
+    let output = Command::new("netsh")
+        .args(["interface", "ipv4", "show", "interfaces"])
+        .output()
+        .map_err(|e| format!("failed to list interfaces: {}", e))?;
+
+    let rows = cmd_parsers::parse_netsh_interfaces(&cmd_parsers::decode_console_output(&output.stdout));
+    Ok(cmd_parsers::interface_index(&rows, INTERFACE_NAME))
 }
 
 fn full_tunnel_routes_present() -> Result<bool, String> {
@@ -37,6 +60,11 @@ fn full_tunnel_routes_present() -> Result<bool, String> {
         && route_entry_exists("128.0.0.0", "128.0.0.0")?)
 }
 
+/// wg-quick text of the config the current tunnel was brought up with.
+pub fn active_config() -> Option<String> {
+    ACTIVE_CONFIG.lock().ok().and_then(|c| c.clone())
+}
+
 pub fn is_full_tunnel_active() -> bool {
     match ACTIVE_ROUTES.lock() {
         Ok(routes) => {
@@ -52,13 +80,65 @@ pub fn is_full_tunnel_active() -> bool {
     }
 }
 
+/// Whether one of the routes we installed already sends `ip` through the tunnel.
+pub fn routes_cover(ip: Ipv4Addr) -> bool {
+    let Ok(routes) = ACTIVE_ROUTES.lock() else {
+        return false;
+    };
+    routes.iter().any(|(network, netmask)| {
+        match (network.parse::<Ipv4Addr>(), netmask.parse::<Ipv4Addr>()) {
+            (Ok(network), Ok(netmask)) => {
+                u32::from(ip) & u32::from(netmask) == u32::from(network) & u32::from(netmask)
+            }
+            _ => false,
+        }
+    })
+}
+
 #[tracing::instrument(level = "info", skip(app, config_content), fields(ipv4_address = %ipv4_address, config_len = config_content.len()))]
 #[tauri::command]
 pub async fn connect_vpn<R: Runtime>(
     app: AppHandle<R>,
     config_content: String,
     ipv4_address: String,
+    profile_id: Option<String>,
+    game_id: Option<String>,
 ) -> Result<String, String> {
+    // The previous tunnel is about to be torn down on purpose; don't let it trip the kill switch,
+    // but keep a block it already raised until the new tunnel is up.
+    kill_switch::hold();
+    kill_switch::recover_stale_rules();
+    usage::stop_tracking(&app).await;
+
+    let parsed_config = match bring_up_tunnel(&app, &config_content, &ipv4_address).await {
+        Ok(config) => config,
+        Err(e) => {
+            // No new tunnel to watch; lift a block the previous one left held.
+            kill_switch::disarm();
+            return Err(e);
+        }
+    };
+
+    if let Ok(mut active) = ACTIVE_CONFIG.lock() {
+        *active = Some(parsed_config.to_wg_quick());
+    }
+
+    if kill_switch::is_enabled() {
+        kill_switch::arm(KillSwitchPlan::from_config(&parsed_config));
+    }
+
+    usage::start_tracking(&app, UsageContext { profile_id, game_id }).await;
+
+    Ok("VPN connected successfully.".to_string())
+}
+
+/// Everything `connect_vpn` does to bring the tunnel and its routes up; returns the parsed
+/// config.
+async fn bring_up_tunnel<R: Runtime>(
+    app: &AppHandle<R>,
+    config_content: &str,
+    ipv4_address: &str,
+) -> Result<WgConfig, String> {
     clean_vpn_routes();
     // Step 0: require elevated session (admin)
     let admin_check = Command::new("net")
@@ -81,25 +161,11 @@ pub async fn connect_vpn<R: Runtime>(
 
     // Step 1: write temp config (wg-tool only accepts a file path)
     let temp_dir = std::env::temp_dir();
-    let config_path = temp_dir.join("pingpal.conf");
-
-    // Filter out unsupported lines
-    let filtered_config: String = config_content
-        .lines()
-        .filter(|line| {
-            let trim = line.trim();
-            !trim.starts_with("Address")
-                && !trim.starts_with("DNS")
-                && !trim.starts_with("MTU")
-                && !trim.starts_with("PreUp")
-                && !trim.starts_with("PostUp")
-                && !trim.starts_with("PreDown")
-                && !trim.starts_with("PostDown")
-                && !trim.starts_with("Table")
-                && !trim.starts_with("SaveConfig")
-        })
-        .collect::<Vec<&str>>()
-        .join("\n");
+    let config_path = temp_dir.join(TEMP_CONFIG_NAME);
+
+    // wg setconf rejects the wg-quick only fields (Address, DNS, MTU, hooks)
+    let parsed_config = WgConfig::parse(config_content)?;
+    let filtered_config = parsed_config.to_setconf();
 
     {
         let mut file =
@@ -221,86 +287,66 @@ pub async fn connect_vpn<R: Runtime>(
             "address",
             &format!("name=\"{}\"", INTERFACE_NAME),
             "static",
-            &ipv4_address,
+            ipv4_address,
             "255.255.255.255",
         ])
         .output()
         .map_err(|e| format!("netsh failed: {}", e))?;
 
     if !netsh_output.status.success() {
-        let err_msg = String::from_utf8_lossy(&netsh_output.stderr);
+        let err_msg = cmd_parsers::decode_console_output(&netsh_output.stderr);
         return Err(format!(
             "failed to set IP (admin rights required): {}",
             err_msg
         ));
     }
 
-    // Step 5: split-tunnel routes from AllowedIPs
+    // Step 5: routes from every peer's AllowedIPs (the tunnel address is IPv4 only)
     tracing::debug!("adding split-tunnel routes");
-    let allowed_ips_line = config_content
-        .lines()
-        .find(|line| line.trim().starts_with("AllowedIPs"))
-        .unwrap_or("");
-
-    if !allowed_ips_line.is_empty() {
-        let ips: Vec<&str> = allowed_ips_line
-            .split('=')
-            .nth(1)
-            .unwrap_or("")
-            .split(',')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        let if_index_output = Command::new("netsh")
-            .args(["interface", "ipv4", "show", "interfaces"])
-            .output()
-            .map_err(|e| format!("failed to list interfaces: {}", e))?;
-
-        let if_output_text = String::from_utf8_lossy(&if_index_output.stdout);
-        let mut interface_idx = None;
-
-        for line in if_output_text.lines() {
-            if line.contains(INTERFACE_NAME) {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if !parts.is_empty() {
-                    if let Ok(idx) = parts[0].parse::<u32>() {
-                        interface_idx = Some(idx);
-                        tracing::debug!(idx, "resolved interface index");
-                        break;
-                    }
-                }
-            }
+    let (allowed_v4, allowed_v6): (Vec<Cidr>, Vec<Cidr>) = parsed_config
+        .allowed_networks()
+        .into_iter()
+        .partition(|cidr| cidr.network().is_ipv4());
+    if !allowed_v6.is_empty() {
+        tracing::debug!(count = allowed_v6.len(), "IPv6 AllowedIPs get no routes");
+    }
+
+    if !allowed_v4.is_empty() {
+        let interface_idx = tunnel_interface_index()?;
+        if let Some(idx) = interface_idx {
+            tracing::debug!(idx, "resolved interface index");
         }
 
         let if_idx = interface_idx.ok_or("could not resolve VPN interface index")?;
         
-        if ips.len() == 1 && ips[0] == "0.0.0.0/0" {
+        if allowed_v4.iter().any(|cidr| cidr.prefix_len() == 0) {
             
-            let endpoint_ip = config_content
-                .lines()
-                .find(|l| l.trim().starts_with("Endpoint"))
-                .and_then(|l| l.split('=').nth(1))
-                .and_then(|s| s.trim().split(':').next())
-                .map(|s| s.trim().to_string());
+            let endpoint_ips: Vec<Ipv4Addr> = parsed_config
+                .peers
+                .iter()
+                .flat_map(|peer| peer.endpoint_addrs())
+                .filter_map(|ip| match ip {
+                    IpAddr::V4(ip) => Some(ip),
+                    IpAddr::V6(_) => None,
+                })
+                .collect();
             let gw_output = Command::new("route")
                 .args(["print", "0.0.0.0"])
                 .output();
-          
+
             let current_gateway: Option<String> = gw_output.ok().and_then(|o| {
-                String::from_utf8_lossy(&o.stdout)
-                    .lines()
-                    .find(|l| l.trim().starts_with("0.0.0.0") && l.contains("0.0.0.0"))
-                    .and_then(|l| l.split_whitespace().nth(2))
-                    .map(|s| s.to_string())
+                let rows = cmd_parsers::parse_route_print(&cmd_parsers::decode_console_output(&o.stdout));
+                cmd_parsers::default_gateway(&rows).map(|gw| gw.to_string())
             });
-            if let (Some(ep_ip), Some(gateway)) = (endpoint_ip, current_gateway) {
-                let _ = Command::new("route")
-                    .args(["add", &ep_ip, "mask", "255.255.255.255", &gateway, "METRIC", "1"])
-                    .output();
-                
-                if let Ok(mut routes) = ACTIVE_ROUTES.lock() {
-                    routes.push((ep_ip.clone(), "255.255.255.255".to_string()));
+            if let Some(gateway) = current_gateway {
+                for ep_ip in endpoint_ips.iter().map(Ipv4Addr::to_string) {
+                    let _ = Command::new("route")
+                        .args(["add", &ep_ip, "mask", "255.255.255.255", &gateway, "METRIC", "1"])
+                        .output();
+                    
+                    if let Ok(mut routes) = ACTIVE_ROUTES.lock() {
+                        routes.push((ep_ip, "255.255.255.255".to_string()));
+                    }
                 }
             }
             
@@ -318,7 +364,7 @@ pub async fn connect_vpn<R: Runtime>(
                 "10.0.0.0".to_string()
             };
             let _ = Command::new("route")
-                .args(["add", &vpn_subnet, "mask", "255.255.255.0", &ipv4_address])
+                .args(["add", &vpn_subnet, "mask", "255.255.255.0", ipv4_address])
                 .output();
             if let Ok(mut routes) = ACTIVE_ROUTES.lock() {
                 routes.push((vpn_subnet.clone(), "255.255.255.0".to_string()));
@@ -352,30 +398,16 @@ pub async fn connect_vpn<R: Runtime>(
             }
         } else {
 
-            for ip_cidr in ips {
-                let parts: Vec<&str> = ip_cidr.split('/').collect();
-                if parts.len() != 2 {
-                    tracing::warn!(%ip_cidr, "skip invalid CIDR");
-                    continue;
-                }
-    
-                let network = parts[0];
-                let prefix_len: u32 = match parts[1].parse() {
-                    Ok(p) => p,
-                    Err(_) => {
-                        tracing::warn!(prefix = %parts[1], "invalid prefix length");
-                        continue;
-                    }
-                };
-    
-                let netmask = prefix_to_netmask(prefix_len);
+            for cidr in &allowed_v4 {
+                let network = cidr.network().to_string();
+                let netmask = prefix_to_netmask(u32::from(cidr.prefix_len()));
     
                 tracing::debug!(%network, %netmask, "route add");
     
                 let route_output = Command::new("route")
                     .args([
                         "add",
-                        network,
+                        &network,
                         "mask",
                         &netmask,
                         "0.0.0.0",
@@ -385,25 +417,25 @@ pub async fn connect_vpn<R: Runtime>(
                         "1",
                     ])
                     .output()
-                    .map_err(|e| format!("route add failed for {}: {}", ip_cidr, e))?;
+                    .map_err(|e| format!("route add failed for {}: {}", cidr, e))?;
     
                 if !route_output.status.success() {
-                    let err_msg = String::from_utf8_lossy(&route_output.stderr);
-                    tracing::warn!(%ip_cidr, stderr = %err_msg, "route add failed (may already exist)");
+                    let err_msg = cmd_parsers::decode_console_output(&route_output.stderr);
+                    tracing::warn!(%cidr, stderr = %err_msg, "route add failed (may already exist)");
                 } else {
-                    tracing::debug!(%ip_cidr, "route added");
+                    tracing::debug!(%cidr, "route added");
                     if let Ok(mut routes) = ACTIVE_ROUTES.lock() {
-                        routes.push((network.to_string(), netmask.clone()));
+                        routes.push((network, netmask));
                     }
                 }
             }
         }
        
     } else {
-        tracing::warn!("no AllowedIPs in config; split tunnel routes skipped");
+        tracing::warn!("no IPv4 AllowedIPs in config; split tunnel routes skipped");
     }
 
-    Ok("VPN connected successfully.".to_string())
+    Ok(parsed_config)
 }
 
 fn wg_dump_first_peer(dump: &str) -> Option<(String, String)> {
@@ -439,16 +471,8 @@ fn wg_merge_host32(existing: &str, host: &str) -> String {
     parts.join(", ")
 }
 
-/// Ensures WireGuard will encapsulate traffic to `ip`, then adds a Windows /32 route via the tunnel.
-pub async fn append_allowed_ip_and_route<R: Runtime>(
-    app: &AppHandle<R>,
-    ip: &str,
-) -> Result<String, String> {
-    if is_full_tunnel_active() {
-        tracing::debug!(target = %ip, "full tunnel active; skipping extra route injection");
-        return Ok("Full tunnel active; no extra route needed.".to_string());
-    }
-
+/// Raw `wg show <adapter> dump` output for the tunnel adapter.
+pub async fn wg_show_dump<R: Runtime>(app: &AppHandle<R>) -> Result<String, String> {
     let dump_out = app
         .shell()
         .sidecar("wg-tool")
@@ -466,7 +490,21 @@ pub async fn append_allowed_ip_and_route<R: Runtime>(
         ));
     }
 
-    let dump = String::from_utf8_lossy(&dump_out.stdout);
+    Ok(String::from_utf8_lossy(&dump_out.stdout).into_owned())
+}
+
+/// Ensures WireGuard will encapsulate traffic to `ip`, then adds a Windows /32 route via the tunnel.
+pub async fn append_allowed_ip_and_route<R: Runtime>(
+    app: &AppHandle<R>,
+    ip: &str,
+) -> Result<String, String> {
+    if is_full_tunnel_active() {
+        tracing::debug!(target = %ip, "full tunnel active; skipping extra route injection");
+        return Ok("Full tunnel active; no extra route needed.".to_string());
+    }
+
+    let _update = ALLOWED_IPS_UPDATE.lock().await;
+    let dump = wg_show_dump(app).await?;
     let (pubkey, allowed) = wg_dump_first_peer(&dump).ok_or_else(|| {
         "WireGuard dump had no peer row; is the tunnel up and configured?"
             .to_string()
@@ -511,25 +549,7 @@ pub fn add_route_to_vpn(ip: &str) -> Result<String, String> {
         return Ok("Full tunnel active; no extra route needed.".to_string());
     }
 
-    let if_index_output = Command::new("netsh")
-        .args(["interface", "ipv4", "show", "interfaces"])
-        .output()
-        .map_err(|e| format!("failed to list interfaces: {}", e))?;
-
-    let if_output_text = String::from_utf8_lossy(&if_index_output.stdout);
-    let mut interface_idx = None;
-
-    for line in if_output_text.lines() {
-        if line.contains(INTERFACE_NAME) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if !parts.is_empty() {
-                if let Ok(idx) = parts[0].parse::<u32>() {
-                    interface_idx = Some(idx);
-                    break;
-                }
-            }
-        }
-    }
+    let interface_idx = tunnel_interface_index()?;
 
     let if_idx = interface_idx
         .ok_or("VPN interface not found; connect VPN first".to_string())?;
@@ -550,23 +570,67 @@ pub fn add_route_to_vpn(ip: &str) -> Result<String, String> {
         .map_err(|e| format!("route add failed: {}", e))?;
 
     if !route_output.status.success() {
-        let err_msg = String::from_utf8_lossy(&route_output.stderr);
+        let err_msg = cmd_parsers::decode_console_output(&route_output.stderr);
         return Err(format!("route add failed: {}", err_msg));
     }
 
+    if let Ok(mut routes) = ACTIVE_ROUTES.lock() {
+        routes.push((ip.to_string(), "255.255.255.255".to_string()));
+    }
+    kill_switch::protect_host(ip);
     Ok(format!("Added {} to VPN routes", ip))
 }
 
-#[tracing::instrument(level = "info")]
+#[tracing::instrument(level = "info", skip(app))]
 #[tauri::command]
-pub fn disconnect_vpn() -> Result<String, String> {
+pub async fn disconnect_vpn<R: Runtime>(app: AppHandle<R>) -> Result<String, String> {
     tracing::debug!("disconnecting VPN");
-    crate::service_manager::stop_service()?;
-    clean_vpn_routes();
+    teardown_vpn(&app).await?;
     tracing::info!("VPN disconnected");
     Ok("VPN disconnected.".to_string())
 }
 
+async fn teardown_vpn<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    usage::stop_tracking(app).await;
+    tokio::task::spawn_blocking(stop_tunnel)
+        .await
+        .map_err(|e| format!("VPN teardown task failed: {}", e))?
+}
+
+/// Lifts the kill switch, stops the engine service and removes our routes. Blocking: runs
+/// netsh, sc and route.
+fn stop_tunnel() -> Result<(), String> {
+    kill_switch::disarm();
+    crate::service_manager::stop_service()?;
+    clean_vpn_routes();
+    if let Ok(mut active) = ACTIVE_CONFIG.lock() {
+        *active = None;
+    }
+    Ok(())
+}
+
+/// Tears the tunnel down on app exit, if anything of it is still around.
+pub async fn shutdown<R: Runtime>(app: &AppHandle<R>) {
+    let _ = std::fs::remove_file(std::env::temp_dir().join(TEMP_CONFIG_NAME));
+
+    let has_routes = ACTIVE_ROUTES.lock().map(|r| !r.is_empty()).unwrap_or(false);
+    // Off the runtime, like the rest of the teardown, so the shutdown timeout can fire.
+    let engine_running = tokio::task::spawn_blocking(crate::service_manager::is_service_running)
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+        .unwrap_or(false);
+    if active_config().is_none() && !has_routes && !engine_running {
+        return;
+    }
+
+    tracing::info!("disconnecting VPN for shutdown");
+    if let Err(e) = teardown_vpn(app).await {
+        tracing::warn!(error = %e, "VPN teardown during shutdown failed");
+        // Still drop our routes so traffic doesn't point at a dead adapter.
+        let _ = tokio::task::spawn_blocking(clean_vpn_routes).await;
+    }
+}
 
 fn clean_vpn_routes() {
     let routes = match ACTIVE_ROUTES.lock() {
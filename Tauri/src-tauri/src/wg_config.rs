@@ -0,0 +1,576 @@
+use crate::netaddr::Cidr;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, ToSocketAddrs};
+
+const SHARE_LINK_SCHEME: &str = "wg://";
+const WG_KEY_LEN: usize = 32;
+const MIN_MTU: u16 = 576;
+
+// wg-quick only keys: accepted on import, never exported and never passed to `wg setconf`.
+const IGNORED_INTERFACE_KEYS: &[&str] = &[
+    "preup",
+    "postup",
+    "predown",
+    "postdown",
+    "table",
+    "saveconfig",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WgInterface {
+    pub private_key: String,
+    pub addresses: Vec<String>,
+    pub dns: Vec<String>,
+    pub mtu: Option<u16>,
+    pub listen_port: Option<u16>,
+    /// Firewall mark for the tunnel's own packets; `None` when off.
+    #[serde(default)]
+    pub fw_mark: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WgPeer {
+    pub public_key: String,
+    pub preshared_key: Option<String>,
+    pub allowed_ips: Vec<String>,
+    pub endpoint: Option<String>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WgConfig {
+    pub interface: WgInterface,
+    pub peers: Vec<WgPeer>,
+}
+
+impl WgPeer {
+    /// Addresses the endpoint resolves to; empty without one or when it does not resolve.
+    pub fn endpoint_addrs(&self) -> Vec<IpAddr> {
+        self.endpoint
+            .as_deref()
+            .and_then(|endpoint| endpoint.to_socket_addrs().ok())
+            .map(|addrs| addrs.map(|addr| addr.ip().to_canonical()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Typed config plus its normalized wg-quick text, so the UI can store it and pass it to `connect_vpn`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WgConfigImport {
+    pub config: WgConfig,
+    pub config_content: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    Interface,
+    Peer,
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn validate_key(field: &str, key: &str) -> Result<(), String> {
+    match STANDARD.decode(key) {
+        Ok(bytes) if bytes.len() == WG_KEY_LEN => Ok(()),
+        _ => Err(format!("{} is not a valid WireGuard key", field)),
+    }
+}
+
+fn validate_cidr(field: &str, value: &str, require_prefix: bool) -> Result<(), String> {
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None if require_prefix => {
+            return Err(format!(
+                "{} entry '{}' is missing a prefix length",
+                field, value
+            ))
+        }
+        None => (value, None),
+    };
+
+    let ip: IpAddr = addr
+        .parse()
+        .map_err(|_| format!("{} entry '{}' is not an IP address", field, value))?;
+
+    if let Some(prefix) = prefix {
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u8>() {
+            Ok(p) if p <= max => {}
+            _ => {
+                return Err(format!(
+                    "{} entry '{}' has an invalid prefix length",
+                    field, value
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_endpoint(endpoint: &str) -> Result<(), String> {
+    let (host, port) = endpoint
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Endpoint '{}' is missing a port", endpoint))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("Endpoint '{}' is missing a host", endpoint));
+    }
+    match port.parse::<u16>() {
+        Ok(p) if p != 0 => Ok(()),
+        _ => Err(format!("Endpoint '{}' has an invalid port", endpoint)),
+    }
+}
+
+fn parse_u16(field: &str, value: &str) -> Result<u16, String> {
+    value
+        .parse::<u16>()
+        .map_err(|_| format!("{} '{}' is not a valid number", field, value))
+}
+
+/// `off`, decimal or `0x` hex, as `wg` accepts it; a mark of 0 is off too.
+fn parse_fw_mark(value: &str) -> Result<Option<u32>, String> {
+    let mark = match value.to_ascii_lowercase().as_str() {
+        "off" => return Ok(None),
+        v => match v.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => v.parse::<u32>(),
+        },
+    };
+    mark.map(|m| (m != 0).then_some(m))
+        .map_err(|_| format!("FwMark '{}' is not a valid number", value))
+}
+
+impl WgConfig {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut section = Section::None;
+        let mut interface: Option<WgInterface> = None;
+        let mut peers: Vec<WgPeer> = Vec::new();
+
+        for (idx, raw) in text.lines().enumerate() {
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = match line[1..line.len() - 1].trim().to_ascii_lowercase().as_str() {
+                    "interface" => {
+                        if interface.is_some() {
+                            return Err("config has more than one [Interface] section".to_string());
+                        }
+                        interface = Some(WgInterface {
+                            private_key: String::new(),
+                            addresses: Vec::new(),
+                            dns: Vec::new(),
+                            mtu: None,
+                            listen_port: None,
+                            fw_mark: None,
+                        });
+                        Section::Interface
+                    }
+                    "peer" => {
+                        peers.push(WgPeer {
+                            public_key: String::new(),
+                            preshared_key: None,
+                            allowed_ips: Vec::new(),
+                            endpoint: None,
+                            persistent_keepalive: None,
+                        });
+                        Section::Peer
+                    }
+                    other => return Err(format!("line {}: unknown section [{}]", idx + 1, other)),
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim()))
+                .ok_or_else(|| format!("line {}: expected 'Key = Value'", idx + 1))?;
+
+            match section {
+                Section::None => {
+                    return Err(format!(
+                        "line {}: '{}' is outside of a section",
+                        idx + 1,
+                        key
+                    ))
+                }
+                Section::Interface => {
+                    let iface = interface.as_mut().expect("interface section is open");
+                    match key.as_str() {
+                        "privatekey" => iface.private_key = value.to_string(),
+                        "address" => iface.addresses.extend(split_list(value)),
+                        "dns" => iface.dns.extend(split_list(value)),
+                        "mtu" => iface.mtu = Some(parse_u16("MTU", value)?),
+                        "listenport" => iface.listen_port = Some(parse_u16("ListenPort", value)?),
+                        "fwmark" => iface.fw_mark = parse_fw_mark(value)?,
+                        k if IGNORED_INTERFACE_KEYS.contains(&k) => {
+                            tracing::debug!(key = %k, "ignoring wg-quick only key");
+                        }
+                        // Newer wg-quick and third-party keys; dropping one beats refusing the file.
+                        other => {
+                            tracing::warn!(line = idx + 1, key = %other, "ignoring unknown [Interface] key")
+                        }
+                    }
+                }
+                Section::Peer => {
+                    let peer = peers.last_mut().expect("peer section is open");
+                    match key.as_str() {
+                        "publickey" => peer.public_key = value.to_string(),
+                        "presharedkey" => peer.preshared_key = Some(value.to_string()),
+                        "allowedips" => peer.allowed_ips.extend(split_list(value)),
+                        "endpoint" => peer.endpoint = Some(value.to_string()),
+                        "persistentkeepalive" => {
+                            peer.persistent_keepalive = match value.to_ascii_lowercase().as_str() {
+                                "off" => None,
+                                v => Some(parse_u16("PersistentKeepalive", v)?),
+                            }
+                        }
+                        other => {
+                            tracing::warn!(line = idx + 1, key = %other, "ignoring unknown [Peer] key")
+                        }
+                    }
+                }
+            }
+        }
+
+        let config = WgConfig {
+            interface: interface.ok_or("config has no [Interface] section")?,
+            peers,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let iface = &self.interface;
+        validate_key("PrivateKey", &iface.private_key)?;
+        for addr in &iface.addresses {
+            validate_cidr("Address", addr, false)?;
+        }
+        for dns in &iface.dns {
+            // wg-quick also accepts search domains here; only reject obviously broken entries.
+            if dns.contains(char::is_whitespace) {
+                return Err(format!("DNS entry '{}' is invalid", dns));
+            }
+        }
+        if let Some(mtu) = iface.mtu {
+            if mtu < MIN_MTU {
+                return Err(format!("MTU {} is below the minimum of {}", mtu, MIN_MTU));
+            }
+        }
+
+        if self.peers.is_empty() {
+            return Err("config has no [Peer] section".to_string());
+        }
+        for peer in &self.peers {
+            validate_key("PublicKey", &peer.public_key)?;
+            if let Some(psk) = &peer.preshared_key {
+                validate_key("PresharedKey", psk)?;
+            }
+            if peer.allowed_ips.is_empty() {
+                return Err("peer has no AllowedIPs".to_string());
+            }
+            for cidr in &peer.allowed_ips {
+                validate_cidr("AllowedIPs", cidr, true)?;
+            }
+            if let Some(endpoint) = &peer.endpoint {
+                validate_endpoint(endpoint)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every peer's AllowedIPs, without repeats.
+    pub fn allowed_networks(&self) -> Vec<Cidr> {
+        let mut networks = Vec::new();
+        for cidr in self.peers.iter().flat_map(|p| &p.allowed_ips) {
+            match cidr.parse::<Cidr>() {
+                Ok(network) if !networks.contains(&network) => networks.push(network),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(%cidr, error = %e, "skipping AllowedIPs entry"),
+            }
+        }
+        networks
+    }
+
+    /// Full wg-quick text, including Address/DNS/MTU.
+    pub fn to_wg_quick(&self) -> String {
+        self.render(true)
+    }
+
+    /// Text accepted by `wg setconf`, which rejects the wg-quick only fields.
+    pub fn to_setconf(&self) -> String {
+        self.render(false)
+    }
+
+    fn render(&self, wg_quick: bool) -> String {
+        let iface = &self.interface;
+        let mut out = String::from("[Interface]\n");
+        out.push_str(&format!("PrivateKey = {}\n", iface.private_key));
+        if let Some(port) = iface.listen_port {
+            out.push_str(&format!("ListenPort = {}\n", port));
+        }
+        if let Some(mark) = iface.fw_mark {
+            out.push_str(&format!("FwMark = 0x{:x}\n", mark));
+        }
+        if wg_quick {
+            if !iface.addresses.is_empty() {
+                out.push_str(&format!("Address = {}\n", iface.addresses.join(", ")));
+            }
+            if !iface.dns.is_empty() {
+                out.push_str(&format!("DNS = {}\n", iface.dns.join(", ")));
+            }
+            if let Some(mtu) = iface.mtu {
+                out.push_str(&format!("MTU = {}\n", mtu));
+            }
+        }
+
+        for peer in &self.peers {
+            out.push_str("\n[Peer]\n");
+            out.push_str(&format!("PublicKey = {}\n", peer.public_key));
+            if let Some(psk) = &peer.preshared_key {
+                out.push_str(&format!("PresharedKey = {}\n", psk));
+            }
+            out.push_str(&format!("AllowedIPs = {}\n", peer.allowed_ips.join(", ")));
+            if let Some(endpoint) = &peer.endpoint {
+                out.push_str(&format!("Endpoint = {}\n", endpoint));
+            }
+            if let Some(keepalive) = peer.persistent_keepalive {
+                out.push_str(&format!("PersistentKeepalive = {}\n", keepalive));
+            }
+        }
+        out
+    }
+
+    pub fn to_share_link(&self) -> String {
+        format!(
+            "{}{}",
+            SHARE_LINK_SCHEME,
+            URL_SAFE_NO_PAD.encode(self.to_wg_quick().as_bytes())
+        )
+    }
+
+    pub fn from_share_link(link: &str) -> Result<Self, String> {
+        let payload = link
+            .trim()
+            .strip_prefix(SHARE_LINK_SCHEME)
+            .ok_or("share link must start with wg://")?;
+        // Tolerate padded payloads produced by other encoders.
+        let bytes = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|_| "share link payload is not valid base64".to_string())?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| "share link payload is not valid UTF-8".to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn into_import(self) -> WgConfigImport {
+        WgConfigImport {
+            config_content: self.to_wg_quick(),
+            config: self,
+        }
+    }
+}
+
+fn resolve_config_source(config_content: Option<String>) -> Result<WgConfig, String> {
+    let text = match config_content {
+        Some(text) => text,
+        None => crate::vpn::active_config().ok_or("no active VPN config to export")?,
+    };
+    WgConfig::parse(&text)
+}
+
+#[tracing::instrument(level = "info", fields(path = %path))]
+#[tauri::command]
+pub fn import_wg_config(path: String) -> Result<WgConfigImport, String> {
+    let text =
+        std::fs::read_to_string(&path).map_err(|e| format!("failed to read config file: {}", e))?;
+    let config = WgConfig::parse(&text)?;
+    tracing::info!(peers = config.peers.len(), "imported WireGuard config");
+    Ok(config.into_import())
+}
+
+/// Writes `config_content`, or the config of the active tunnel when omitted, as a wg-quick `.conf`.
+#[tracing::instrument(level = "info", skip(config_content), fields(path = %path))]
+#[tauri::command]
+pub fn export_wg_config(path: String, config_content: Option<String>) -> Result<String, String> {
+    let config = resolve_config_source(config_content)?;
+    std::fs::write(&path, config.to_wg_quick())
+        .map_err(|e| format!("failed to write config file: {}", e))?;
+    tracing::info!("exported WireGuard config");
+    Ok(format!("Config exported to {}", path))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+#[tauri::command]
+pub fn create_wg_share_link(config_content: Option<String>) -> Result<String, String> {
+    Ok(resolve_config_source(config_content)?.to_share_link())
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+#[tauri::command]
+pub fn parse_wg_share_link(link: String) -> Result<WgConfigImport, String> {
+    Ok(WgConfig::from_share_link(&link)?.into_import())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "BwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSY=";
+    const PUBLIC_KEY: &str = "Dg8QERITFBUWFxgZGhscHR4fICEiIyQlJicoKSorLC0=";
+
+    fn sample() -> String {
+        format!(
+            "
+[Interface]
+PrivateKey = {}
+Address = 10.0.0.5/32
+DNS = 1.1.1.1
+MTU = 1280
+PostUp = echo hi
+
+[Peer]
+PublicKey = {}
+AllowedIPs = 13.0.0.0/8, 52.0.0.0/8 # game ranges
+Endpoint = 203.0.113.10:51820
+PersistentKeepalive = 25
+",
+            PRIVATE_KEY, PUBLIC_KEY
+        )
+    }
+
+    #[test]
+    fn allowed_networks_span_every_peer() {
+        let text = format!(
+            "[Interface]\nPrivateKey = {key}\n\
+             [Peer]\nPublicKey = {key}\nallowedips = 0.0.0.0/0, ::/0\nAllowedIPs = 13.0.0.0/8\n\
+             Endpoint = [2001:db8::1]:51820\n\
+             [Peer]\nPublicKey = {key}\nAllowedIPs = 13.0.0.0/8, 52.0.0.0/8\n",
+            key = PUBLIC_KEY
+        );
+        let config = WgConfig::parse(&text).unwrap();
+        let networks: Vec<String> = config
+            .allowed_networks()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(networks, ["0.0.0.0/0", "::/0", "13.0.0.0/8", "52.0.0.0/8"]);
+        assert_eq!(
+            config.peers[0].endpoint_addrs(),
+            ["2001:db8::1".parse::<IpAddr>().unwrap()]
+        );
+        assert!(config.peers[1].endpoint_addrs().is_empty());
+    }
+
+    #[test]
+    fn parse_keeps_wg_quick_fields() {
+        let config = WgConfig::parse(&sample()).unwrap();
+        assert_eq!(config.interface.addresses, vec!["10.0.0.5/32"]);
+        assert_eq!(config.interface.dns, vec!["1.1.1.1"]);
+        assert_eq!(config.interface.mtu, Some(1280));
+        assert_eq!(config.peers.len(), 1);
+        assert_eq!(
+            config.peers[0].allowed_ips,
+            vec!["13.0.0.0/8", "52.0.0.0/8"]
+        );
+        assert_eq!(config.peers[0].persistent_keepalive, Some(25));
+    }
+
+    #[test]
+    fn wg_quick_round_trips() {
+        let config = WgConfig::parse(&sample()).unwrap();
+        let reparsed = WgConfig::parse(&config.to_wg_quick()).unwrap();
+        assert_eq!(config, reparsed);
+        assert!(!config.to_wg_quick().contains("PostUp"));
+    }
+
+    #[test]
+    fn setconf_strips_wg_quick_fields() {
+        let text = WgConfig::parse(&sample()).unwrap().to_setconf();
+        assert!(!text.contains("Address"));
+        assert!(!text.contains("DNS"));
+        assert!(!text.contains("MTU"));
+        assert!(text.contains("AllowedIPs = 13.0.0.0/8, 52.0.0.0/8"));
+    }
+
+    #[test]
+    fn share_link_round_trips() {
+        let config = WgConfig::parse(&sample()).unwrap();
+        let link = config.to_share_link();
+        let payload = link.strip_prefix("wg://").unwrap();
+        assert!(!payload.contains(['+', '/', '=']));
+        assert_eq!(WgConfig::from_share_link(&link).unwrap(), config);
+    }
+
+    #[test]
+    fn share_link_rejects_bad_input() {
+        assert!(WgConfig::from_share_link("https://example.com").is_err());
+        assert!(WgConfig::from_share_link("wg://!!!").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_configs() {
+        let bad_key = sample().replace(PUBLIC_KEY, "not-a-key");
+        assert!(WgConfig::parse(&bad_key).is_err());
+
+        let bad_cidr = sample().replace("13.0.0.0/8", "13.0.0.0/40");
+        assert!(WgConfig::parse(&bad_cidr).is_err());
+
+        let bad_endpoint = sample().replace("203.0.113.10:51820", "203.0.113.10");
+        assert!(WgConfig::parse(&bad_endpoint).is_err());
+
+        let bad_mtu = sample().replace("MTU = 1280", "MTU = 100");
+        assert!(WgConfig::parse(&bad_mtu).is_err());
+
+        let bad_fw_mark = sample().replace("MTU = 1280", "FwMark = 0xzz");
+        assert!(WgConfig::parse(&bad_fw_mark).is_err());
+
+        let no_peer = format!("[Interface]\nPrivateKey = {}\n", PRIVATE_KEY);
+        assert!(WgConfig::parse(&no_peer).is_err());
+    }
+
+    #[test]
+    fn fw_mark_reaches_setconf_and_unknown_keys_are_skipped() {
+        let text = sample()
+            .replace("MTU = 1280", "MTU = 1280\nFwMark = 51820\nFoo = bar")
+            .replace(
+                "PersistentKeepalive = 25",
+                "PersistentKeepalive = 25\nBar = baz",
+            );
+        let config = WgConfig::parse(&text).unwrap();
+        assert_eq!(config.interface.fw_mark, Some(51820));
+        assert!(config.to_setconf().contains("FwMark = 0xca6c\n"));
+        assert!(!config.to_wg_quick().contains("Foo"));
+        assert_eq!(WgConfig::parse(&config.to_wg_quick()).unwrap(), config);
+
+        for (value, mark) in [("0xCA6C", Some(51820)), ("off", None), ("0", None)] {
+            let text = sample().replace("MTU = 1280", &format!("FwMark = {}", value));
+            assert_eq!(WgConfig::parse(&text).unwrap().interface.fw_mark, mark);
+        }
+    }
+
+    #[test]
+    fn parse_accepts_bare_address_and_ipv6_endpoint() {
+        let text = sample()
+            .replace("10.0.0.5/32", "10.0.0.5")
+            .replace("203.0.113.10:51820", "[2001:db8::1]:51820");
+        let config = WgConfig::parse(&text).unwrap();
+        assert_eq!(config.interface.addresses, vec!["10.0.0.5"]);
+        assert_eq!(
+            config.peers[0].endpoint.as_deref(),
+            Some("[2001:db8::1]:51820")
+        );
+    }
+}
@@ -5,9 +5,14 @@ import {
   addDetectedIpToRoutes,
   getAllSessionIps,
   getDetectedServers,
+  listGameProfiles,
   startMonitoring as tauriStartMonitoring,
   stopMonitoring as tauriStopMonitoring,
+  subscribeMonitorUpdates,
+  unsubscribeMonitorUpdates,
   type DetectedServerPayload,
+  type MonitorUpdatePayload,
+  type ServerKeyPayload,
 } from "@/lib/tauriCommands";
 
 type MonitorStatusType = "info" | "success" | "error";
@@ -74,6 +79,8 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
 
   const intervalMs = options.pollIntervalMs ?? 2000;
   let pollInterval: number | null = null;
+  let subscriptionId: number | null = null;
+  const serversByKey = new Map<string, DetectedServerPayload>();
 
   function parseErrorMessage(error: unknown): string {
     if (typeof error === "string") return error;
@@ -96,6 +103,11 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
       clearInterval(pollInterval);
       pollInterval = null;
     }
+    if (subscriptionId !== null) {
+      void unsubscribeMonitorUpdates(subscriptionId).catch(() => {});
+      subscriptionId = null;
+    }
+    serversByKey.clear();
   }
 
   function setActivity(message: string, type: MonitorActivityType = "info") {
@@ -117,8 +129,13 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
     statusType.value = "info";
     setActivity("Monitoring active", "info");
     clearPollingTimer();
-    pollInterval = window.setInterval(fetchServers, intervalMs);
-    await fetchServers();
+    try {
+      subscriptionId = await subscribeMonitorUpdates(applyUpdate, intervalMs);
+    } catch (error) {
+      console.warn("Live monitor updates unavailable, polling instead:", error);
+      pollInterval = window.setInterval(fetchServers, intervalMs);
+      await fetchServers();
+    }
   }
 
   function initializeAutoCaches() {
@@ -190,25 +207,44 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
     }
   }
 
-  async function fetchServers() {
-    try {
-      const servers = await getDetectedServers();
-      detectedServers.value = servers;
-
-      if (servers.length > 0 && isMonitoring.value) {
-        const gameServers = servers.filter((s) => s.is_game_server);
-        if (gameServers.length > 0) {
-          gameServers.forEach((server) => queueAutoRoute(server.ip));
-          statusMessage.value = "Connected";
-          statusType.value = "success";
-        } else {
-          statusMessage.value = "Scanning...";
-          statusType.value = "info";
-        }
-      } else if (isMonitoring.value) {
+  function showServers(servers: DetectedServerPayload[]) {
+    detectedServers.value = servers;
+
+    if (servers.length > 0 && isMonitoring.value) {
+      const gameServers = servers.filter((s) => s.is_game_server);
+      if (gameServers.length > 0) {
+        gameServers.forEach((server) => queueAutoRoute(server.ip));
+        statusMessage.value = "Connected";
+        statusType.value = "success";
+      } else {
         statusMessage.value = "Scanning...";
         statusType.value = "info";
       }
+    } else if (isMonitoring.value) {
+      statusMessage.value = "Scanning...";
+      statusType.value = "info";
+    }
+  }
+
+  function serverKey(server: ServerKeyPayload): string {
+    return `${server.session_id}/${server.protocol}:${server.ip}:${server.port}`;
+  }
+
+  function applyUpdate(update: MonitorUpdatePayload) {
+    update.removed.forEach((key) => serversByKey.delete(serverKey(key)));
+    [...update.added, ...update.updated].forEach((server) =>
+      serversByKey.set(serverKey(server), server),
+    );
+    showServers([...serversByKey.values()]);
+
+    if (isMonitoring.value && options.getGameId?.()) {
+      update.session_ips?.forEach((ip) => queueAutoPostRange(ip));
+    }
+  }
+
+  async function fetchServers() {
+    try {
+      showServers(await getDetectedServers());
     } catch (error) {
       console.error("Failed to fetch servers:", error);
     }
@@ -220,6 +256,18 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
     }
   }
 
+  // Uses the game's detection profile when it covers the chosen executable.
+  async function monitoringTarget(processName: string) {
+    const gameId = options.getGameId?.();
+    if (!gameId) return { processName };
+    const profiles = await listGameProfiles().catch(() => []);
+    const profile = profiles.find((p) => p.id === gameId);
+    const covered = profile?.executables.some(
+      (exe) => exe.toLowerCase() === processName.toLowerCase(),
+    );
+    return covered ? { profileId: gameId } : { processName };
+  }
+
   async function startMonitoring() {
     if (isMonitoring.value) return;
 
@@ -248,8 +296,9 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
     }, startMonitorTimeoutMs);
 
     try {
+      const target = await monitoringTarget(processName);
       try {
-        await tauriStartMonitoring({ processName });
+        await tauriStartMonitoring(target);
       } catch (error) {
         if (!isAlreadyMonitoringError(error)) {
           throw error;
@@ -261,7 +310,7 @@ export function useGameTrafficMonitor(options: UseGameTrafficMonitorOptions) {
           }
         });
 
-        await tauriStartMonitoring({ processName });
+        await tauriStartMonitoring(target);
       }
 
       trafficMonitorStore.claimOwner(localOwnerId);
@@ -94,6 +94,8 @@ PersistentKeepalive = 25
       await connectVpn({
         configContent,
         ipv4Address: ipv4,
+        profileId: cfg.profileId,
+        gameId: currentGameId.value ?? undefined,
       });
 
       if (cfg.profileId && cfg.privateKey) {
@@ -1,29 +1,109 @@
-import { invoke } from "@tauri-apps/api/core";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export const TAURI_CMD = {
   getDeviceName: "get_device_name",
   connectVpn: "connect_vpn",
   disconnectVpn: "disconnect_vpn",
+  importWgConfig: "import_wg_config",
+  exportWgConfig: "export_wg_config",
+  createWgShareLink: "create_wg_share_link",
+  parseWgShareLink: "parse_wg_share_link",
+  setKillSwitchEnabled: "set_kill_switch_enabled",
+  getKillSwitchStatus: "get_kill_switch_status",
+  getVpnUsage: "get_vpn_usage",
+  listGameProfiles: "list_game_profiles",
   startMonitoring: "start_monitoring",
+  startMonitoringFromCapture: "start_monitoring_from_capture",
   stopMonitoring: "stop_monitoring",
+  startCaptureRecording: "start_capture_recording",
+  stopCaptureRecording: "stop_capture_recording",
+  getCaptureRecordingStatus: "get_capture_recording_status",
+  listMonitorSessions: "list_monitor_sessions",
+  getCaptureInterfaces: "get_capture_interfaces",
   getDetectedServers: "get_detected_servers",
+  getFlowHistory: "get_flow_history",
   getAllSessionIps: "get_all_session_ips",
+  subscribeMonitorUpdates: "subscribe_monitor_updates",
+  unsubscribeMonitorUpdates: "unsubscribe_monitor_updates",
   addDetectedIpToRoutes: "add_detected_ip_to_routes",
+  getRouteAuditLog: "get_route_audit_log",
+  listSessions: "list_sessions",
+  getSessionServers: "get_session_servers",
+  getServerHistoryStats: "get_server_history_stats",
+  getGeoStatus: "get_geo_status",
+  reloadGeoDatabases: "reload_geo_databases",
+  setGeoOnlineFallback: "set_geo_online_fallback",
+  getCloudRangesStatus: "get_cloud_ranges_status",
+  reloadCloudRanges: "reload_cloud_ranges",
+  getGameWatcherStatus: "get_game_watcher_status",
+  setAutoMonitor: "set_auto_monitor",
   startHopProbe: "start_hop_probe",
   stopHopProbe: "stop_hop_probe",
   getHopStats: "get_hop_stats",
 } as const;
 
+export type FlowCategory = "game" | "voice" | "matchmaking" | "telemetry" | "other";
+
+export type GeoInfoPayload = {
+  country_code: string | null;
+  country: string | null;
+  city: string | null;
+  latitude: number | null;
+  longitude: number | null;
+  asn: number | null;
+  as_org: string | null;
+};
+
+export type CloudLabelPayload = {
+  provider: string | null;
+  /** The provider's own region name, e.g. `ap-northeast-1`. */
+  region: string | null;
+  service: string | null;
+  asn: number | null;
+  as_org: string | null;
+};
+
 export type DetectedServerPayload = {
+  /** Monitoring session that saw the server. */
+  session_id: string;
   ip: string;
   port: number;
   protocol: string;
   send_rate: number;
   recv_rate: number;
+  /** "City, Country" from `geo`. */
   country: string | null;
+  geo: GeoInfoPayload | null;
+  cloud: CloudLabelPayload | null;
   ping_ms: number | null;
   detected_at: string;
+  category: FlowCategory;
+  confidence: number;
   is_game_server: boolean;
+  /** Passive estimates from server packet timing; null until enough packets arrived. */
+  jitter_ms: number | null;
+  tick_rate_hz: number | null;
+  loss_pct: number | null;
+  /** Capture interfaces the flow was seen on, busiest first. */
+  interfaces: string[];
+  /** Whether its packets went through the tunnel; null until seen on a tagged interface. */
+  tunnel: TunnelStatus | null;
+};
+
+/** `leaking`: should go through the tunnel, but was seen on another interface. */
+export type TunnelStatus = "vpn" | "direct" | "leaking";
+
+/** A server that should go through the tunnel was seen going direct. */
+export function onServerLeaking(
+  handler: (server: DetectedServerPayload) => void,
+): Promise<UnlistenFn> {
+  return listen<DetectedServerPayload>("server-leaking", (event) => handler(event.payload));
+}
+
+export type FlowFilter = {
+  categories?: FlowCategory[];
+  minConfidence?: number;
 };
 
 export function getDeviceName(): Promise<string> {
@@ -33,6 +113,8 @@ export function getDeviceName(): Promise<string> {
 export function connectVpn(args: {
   configContent: string;
   ipv4Address: string;
+  profileId?: string;
+  gameId?: string;
 }): Promise<string> {
   return invoke<string>(TAURI_CMD.connectVpn, args);
 }
@@ -41,32 +123,421 @@ export function disconnectVpn(): Promise<string> {
   return invoke<string>(TAURI_CMD.disconnectVpn);
 }
 
+export type WgConfigPayload = {
+  interface: {
+    private_key: string;
+    addresses: string[];
+    dns: string[];
+    mtu: number | null;
+    listen_port: number | null;
+    fw_mark: number | null;
+  };
+  peers: {
+    public_key: string;
+    preshared_key: string | null;
+    allowed_ips: string[];
+    endpoint: string | null;
+    persistent_keepalive: number | null;
+  }[];
+};
+
+export type WgConfigImportPayload = {
+  config: WgConfigPayload;
+  config_content: string;
+};
+
+export function importWgConfig(path: string): Promise<WgConfigImportPayload> {
+  return invoke<WgConfigImportPayload>(TAURI_CMD.importWgConfig, { path });
+}
+
+/** Omit `configContent` to export the config of the active tunnel. */
+export function exportWgConfig(args: {
+  path: string;
+  configContent?: string;
+}): Promise<string> {
+  return invoke<string>(TAURI_CMD.exportWgConfig, args);
+}
+
+export function createWgShareLink(configContent?: string): Promise<string> {
+  return invoke<string>(TAURI_CMD.createWgShareLink, { configContent });
+}
+
+export function parseWgShareLink(link: string): Promise<WgConfigImportPayload> {
+  return invoke<WgConfigImportPayload>(TAURI_CMD.parseWgShareLink, { link });
+}
+
+export type KillSwitchStatusPayload = {
+  enabled: boolean;
+  armed: boolean;
+  blocking: boolean;
+  full_tunnel: boolean;
+  protected: string[];
+};
+
+export function setKillSwitchEnabled(enabled: boolean): Promise<string> {
+  return invoke<string>(TAURI_CMD.setKillSwitchEnabled, { enabled });
+}
+
+export function getKillSwitchStatus(): Promise<KillSwitchStatusPayload> {
+  return invoke<KillSwitchStatusPayload>(TAURI_CMD.getKillSwitchStatus);
+}
+
+export type ByteCountPayload = {
+  rx_bytes: number;
+  tx_bytes: number;
+};
+
+export type VpnUsagePayload = {
+  total: ByteCountPayload;
+  by_profile: Record<string, ByteCountPayload>;
+  by_game: Record<string, ByteCountPayload>;
+  days: {
+    date: string;
+    total: ByteCountPayload;
+    by_profile: Record<string, ByteCountPayload>;
+    by_game: Record<string, ByteCountPayload>;
+  }[];
+};
+
+/** `days` limits the report to the most recent N days with traffic. */
+export function getVpnUsage(days?: number): Promise<VpnUsagePayload> {
+  return invoke<VpnUsagePayload>(TAURI_CMD.getVpnUsage, { days });
+}
+
+export type GameProfilePayload = {
+  id: string;
+  name: string;
+  executables: string[];
+  server_ports: string[];
+  server_cidrs: string[];
+  hostname_patterns: string[];
+  protocols: ("udp" | "tcp")[];
+  thresholds: Record<string, number>;
+  route_rules: RouteRulePayload[];
+};
+
+/** Every listed condition must hold; omitted ones match anything. */
+export type RouteRulePayload = {
+  name: string | null;
+  min_confidence: number | null;
+  countries: string[];
+  cidrs: string[];
+  ports: string[];
+};
+
+export function listGameProfiles(): Promise<GameProfilePayload[]> {
+  return invoke<GameProfilePayload[]>(TAURI_CMD.listGameProfiles);
+}
+
 export function startMonitoring(args: {
-  processName: string;
+  profileId?: string;
+  processName?: string;
+  /** Sessions with different ids run side by side; defaults to "default". */
+  sessionId?: string;
 }): Promise<string> {
   return invoke<string>(TAURI_CMD.startMonitoring, args);
 }
 
-export function stopMonitoring(): Promise<string> {
-  return invoke<string>(TAURI_CMD.stopMonitoring);
+export function startMonitoringFromCapture(args: {
+  path: string;
+  profileId?: string;
+  speed?: number;
+  localPorts?: number[];
+  sessionId?: string;
+}): Promise<string> {
+  return invoke<string>(TAURI_CMD.startMonitoringFromCapture, args);
+}
+
+/** Stops `sessionId`, or every running session. */
+export function stopMonitoring(sessionId?: string): Promise<string> {
+  return invoke<string>(TAURI_CMD.stopMonitoring, { sessionId });
+}
+
+export type MonitorSessionPayload = {
+  id: string;
+  label: string;
+  is_monitoring: boolean;
+  replay: boolean;
+  server_count: number;
+};
+
+export function listMonitorSessions(): Promise<MonitorSessionPayload[]> {
+  return invoke<MonitorSessionPayload[]>(TAURI_CMD.listMonitorSessions);
+}
+
+export type CaptureInterfacePayload = {
+  index: number;
+  name: string;
+  addrs: string[];
+  tunnel: boolean;
+  capturing: boolean;
+};
+
+export function getCaptureInterfaces(): Promise<CaptureInterfacePayload[]> {
+  return invoke<CaptureInterfacePayload[]>(TAURI_CMD.getCaptureInterfaces);
+}
+
+export type CaptureRecordingStatusPayload = {
+  path: string;
+  active: boolean;
+  packets: number;
+  bytes: number;
+  elapsed_secs: number;
+  stop_reason: "stopped" | "size_limit" | "duration_limit" | "write_error" | null;
+};
+
+export function startCaptureRecording(args?: {
+  path?: string;
+  maxBytes?: number;
+  maxDurationSecs?: number;
+  sessionId?: string;
+}): Promise<CaptureRecordingStatusPayload> {
+  return invoke<CaptureRecordingStatusPayload>(
+    TAURI_CMD.startCaptureRecording,
+    args ?? {},
+  );
+}
+
+export function stopCaptureRecording(
+  sessionId?: string,
+): Promise<CaptureRecordingStatusPayload | null> {
+  return invoke<CaptureRecordingStatusPayload | null>(TAURI_CMD.stopCaptureRecording, {
+    sessionId,
+  });
+}
+
+export function getCaptureRecordingStatus(
+  sessionId?: string,
+): Promise<CaptureRecordingStatusPayload | null> {
+  return invoke<CaptureRecordingStatusPayload | null>(
+    TAURI_CMD.getCaptureRecordingStatus,
+    { sessionId },
+  );
+}
+
+/** Servers of `sessionId`, or of every session. */
+export function getDetectedServers(sessionId?: string): Promise<DetectedServerPayload[]> {
+  return invoke<DetectedServerPayload[]>(TAURI_CMD.getDetectedServers, { sessionId });
+}
+
+export type FlowSamplePayload = {
+  /** Unix time in milliseconds. */
+  at_ms: number;
+  send_rate: number;
+  recv_rate: number;
+  send_pps: number;
+  recv_pps: number;
+  ping_ms: number | null;
+  jitter_ms: number | null;
+};
+
+/** Samples of the flow to `ip`:`port`, averaged down to at most `maxPoints`. */
+export function getFlowHistory(args: {
+  ip: string;
+  port: number;
+  windowSecs?: number;
+  protocol?: "udp" | "tcp";
+  maxPoints?: number;
+  sessionId?: string;
+}): Promise<FlowSamplePayload[]> {
+  return invoke<FlowSamplePayload[]>(TAURI_CMD.getFlowHistory, args);
+}
+
+export function getAllSessionIps(filter?: FlowFilter, sessionId?: string): Promise<string[]> {
+  return invoke<string[]>(TAURI_CMD.getAllSessionIps, { ...filter, sessionId });
+}
+
+export type ServerKeyPayload = {
+  session_id: string;
+  ip: string;
+  port: number;
+  protocol: string;
+};
+
+export type MonitorUpdatePayload = {
+  seq: number;
+  added: DetectedServerPayload[];
+  updated: DetectedServerPayload[];
+  removed: ServerKeyPayload[];
+  pings: { ip: string; ping_ms: number | null }[];
+  session_ips: string[] | null;
+};
+
+/** Resolves to the subscription id; updates arrive at most every `intervalMs`. */
+export function subscribeMonitorUpdates(
+  onUpdate: (update: MonitorUpdatePayload) => void,
+  intervalMs?: number,
+): Promise<number> {
+  const channel = new Channel<MonitorUpdatePayload>();
+  channel.onmessage = onUpdate;
+  return invoke<number>(TAURI_CMD.subscribeMonitorUpdates, { onUpdate: channel, intervalMs });
+}
+
+export function unsubscribeMonitorUpdates(id: number): Promise<boolean> {
+  return invoke<boolean>(TAURI_CMD.unsubscribeMonitorUpdates, { id });
+}
+
+export function addDetectedIpToRoutes(ip: string, filter?: FlowFilter): Promise<string> {
+  return invoke<string>(TAURI_CMD.addDetectedIpToRoutes, { ip, ...filter });
+}
+
+export type RouteDecisionPayload = {
+  at: string;
+  session_id: string;
+  ip: string;
+  port: number;
+  rule: string;
+  confidence: number;
+  country_code: string | null;
+  outcome: "routed" | "failed";
+  detail: string;
+};
+
+/** Automatic routing decisions, newest first. */
+export function getRouteAuditLog(limit?: number): Promise<RouteDecisionPayload[]> {
+  return invoke<RouteDecisionPayload[]>(TAURI_CMD.getRouteAuditLog, { limit });
+}
+
+export type SessionFilter = {
+  game?: string;
+  /** RFC 3339 time or local `YYYY-MM-DD` date. */
+  from?: string;
+  /** RFC 3339 time or local `YYYY-MM-DD` date, inclusive. */
+  to?: string;
+  limit?: number;
+};
+
+export type SessionSummaryPayload = {
+  id: number;
+  game: string;
+  vpn_profile: string | null;
+  started_at: string;
+  /** For a session still being recorded, when its totals were last saved. */
+  ended_at: string;
+  in_progress: boolean;
+  server_count: number;
+  bytes_sent: number;
+  bytes_recv: number;
+};
+
+export type SessionServerPayload = {
+  ip: string;
+  port: number;
+  protocol: string;
+  country: string | null;
+  category: FlowCategory;
+  ping_min_ms: number | null;
+  ping_avg_ms: number | null;
+  ping_max_ms: number | null;
+  bytes_sent: number;
+  bytes_recv: number;
+  first_seen: string;
+  last_seen: string;
+};
+
+export type ServerHistoryStatsPayload = {
+  ip: string;
+  port: number;
+  protocol: string;
+  country: string | null;
+  sessions: number;
+  ping_min_ms: number | null;
+  ping_avg_ms: number | null;
+  ping_max_ms: number | null;
+  bytes_sent: number;
+  bytes_recv: number;
+  last_seen: string;
+  vpn_profiles: string[];
+};
+
+export function listSessions(filter?: SessionFilter): Promise<SessionSummaryPayload[]> {
+  return invoke<SessionSummaryPayload[]>(TAURI_CMD.listSessions, { filter });
+}
+
+export function getSessionServers(sessionId: number): Promise<SessionServerPayload[]> {
+  return invoke<SessionServerPayload[]>(TAURI_CMD.getSessionServers, { sessionId });
+}
+
+export function getServerHistoryStats(
+  filter?: SessionFilter,
+): Promise<ServerHistoryStatsPayload[]> {
+  return invoke<ServerHistoryStatsPayload[]>(TAURI_CMD.getServerHistoryStats, { filter });
+}
+
+export type GeoStatusPayload = {
+  database_dir: string | null;
+  location_db: string | null;
+  asn_db: string | null;
+  online_fallback: boolean;
+  cached_entries: number;
+};
+
+export function getGeoStatus(): Promise<GeoStatusPayload> {
+  return invoke<GeoStatusPayload>(TAURI_CMD.getGeoStatus);
+}
+
+/** Re-reads `.mmdb` files dropped into the geoip folder and clears the lookup cache. */
+export function reloadGeoDatabases(): Promise<GeoStatusPayload> {
+  return invoke<GeoStatusPayload>(TAURI_CMD.reloadGeoDatabases);
+}
+
+/** Allows asking ip-api.com about addresses the local databases miss; kept across restarts. */
+export function setGeoOnlineFallback(enabled: boolean): Promise<GeoStatusPayload> {
+  return invoke<GeoStatusPayload>(TAURI_CMD.setGeoOnlineFallback, { enabled });
+}
+
+export type CloudRangesStatusPayload = {
+  directory: string | null;
+  sources: { file: string; provider: string; entries: number }[];
+  prefixes: number;
+  asn_ranges: number;
+};
+
+export function getCloudRangesStatus(): Promise<CloudRangesStatusPayload> {
+  return invoke<CloudRangesStatusPayload>(TAURI_CMD.getCloudRangesStatus);
+}
+
+/** Re-reads the cloud range and ASN files in the cloud folder. */
+export function reloadCloudRanges(): Promise<CloudRangesStatusPayload> {
+  return invoke<CloudRangesStatusPayload>(TAURI_CMD.reloadCloudRanges);
+}
+
+export type RunningGamePayload = {
+  profile_id: string;
+  /** Display name from the profile. */
+  name: string;
+  /** Executable name of the matched process. */
+  process: string;
+  pids: number[];
+};
+
+export type GameWatcherStatusPayload = {
+  auto_monitor: boolean;
+  running: RunningGamePayload[];
+};
+
+export function getGameWatcherStatus(): Promise<GameWatcherStatusPayload> {
+  return invoke<GameWatcherStatusPayload>(TAURI_CMD.getGameWatcherStatus);
 }
 
-export function getDetectedServers(): Promise<DetectedServerPayload[]> {
-  return invoke<DetectedServerPayload[]>(TAURI_CMD.getDetectedServers);
+/** Auto-started sessions use the profile id as their session id. */
+export function setAutoMonitor(enabled: boolean): Promise<GameWatcherStatusPayload> {
+  return invoke<GameWatcherStatusPayload>(TAURI_CMD.setAutoMonitor, { enabled });
 }
 
-export function getAllSessionIps(): Promise<string[]> {
-  return invoke<string[]>(TAURI_CMD.getAllSessionIps);
+export function onGameLaunched(handler: (game: RunningGamePayload) => void): Promise<UnlistenFn> {
+  return listen<RunningGamePayload>("game-launched", (event) => handler(event.payload));
 }
 
-export function addDetectedIpToRoutes(ip: string): Promise<string> {
-  return invoke<string>(TAURI_CMD.addDetectedIpToRoutes, { ip });
+export function onGameExited(handler: (game: RunningGamePayload) => void): Promise<UnlistenFn> {
+  return listen<RunningGamePayload>("game-exited", (event) => handler(event.payload));
 }
 
 export type HopStatPayload = {
   hop: number;
   ip: string;
   hostname: string | null;
+  cloud: CloudLabelPayload | null;
   loss_pct: number;
   sent: number;
   recv: number;